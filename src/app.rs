@@ -1,9 +1,19 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
 use regex::Regex;
 
+use crate::checks::CheckDef;
+use crate::fuzzy::fuzzy_match;
+use crate::health::HealthUpdate;
 use crate::history::History;
-use crate::server::{Server, ServerGroup};
-use crate::ssh::is_mosh_installed;
+use crate::query::Query;
+use crate::server::{group_path, Server, ServerGroup, ServerId, SourceLocation};
+use crate::ssh::{build_groups_by, is_mosh_installed, read_host_entry, EffectiveConfig, HostEdit, HostEntry};
+use crate::tags::{parse_tags, TagFile};
 use crate::tunnel::TunnelManager;
+use crate::views::{Column, View, ViewFile};
 
 /// View mode for the TUI
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -57,23 +67,178 @@ impl SortOrder {
     }
 }
 
+/// What the server list and group view are grouped by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GroupBy {
+    #[default]
+    Name,
+    Bastion,
+    Tag,
+}
+
+impl GroupBy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GroupBy::Name => "name",
+            GroupBy::Bastion => "bastion",
+            GroupBy::Tag => "tag",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Self {
+        match s {
+            "bastion" => GroupBy::Bastion,
+            "tag" => GroupBy::Tag,
+            _ => GroupBy::Name,
+        }
+    }
+}
+
+/// Group name for servers reached without a bastion in bastion grouping mode
+pub const DIRECT_GROUP: &str = "direct";
+
+/// Group name for servers without tags in tag grouping mode
+pub const UNTAGGED_GROUP: &str = "untagged";
+
+/// A row in the server list or group view
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListRow {
+    Group {
+        path: String,
+        name: String,
+        depth: usize,
+        /// Servers in the group and its subgroups, for the header rollup
+        servers: Vec<usize>,
+        collapsed: bool,
+    },
+    Server {
+        index: usize,
+        depth: usize,
+    },
+}
+
+/// What submitting the host form does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostFormMode {
+    Add,
+    Edit,
+    Duplicate,
+}
+
+/// State of the host editor form
+#[derive(Debug, Clone)]
+pub struct HostForm {
+    pub mode: HostFormMode,
+    /// Host being edited or duplicated, and where its block is
+    pub source: Option<(String, SourceLocation)>,
+    /// File the edit is written to
+    pub file: PathBuf,
+    pub entry: HostEntry,
+    pub focused: usize,
+}
+
+/// Hosts affected by merging a reloaded server list
+#[derive(Debug, Default)]
+pub struct ServerListChanges {
+    pub added: Vec<ServerId>,
+    pub removed: Vec<ServerId>,
+    /// Hosts whose connection settings changed; their old health data may not apply
+    pub changed: Vec<ServerId>,
+}
+
+impl ServerListChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Prefix that switches the filter from fuzzy search to a regex
+pub const REGEX_FILTER_PREFIX: &str = "re:";
+
+/// Search ranking boosts for favorites and servers used in the last day
+/// (half as much for the last week)
+const FAVORITE_BOOST: i64 = 24;
+const RECENT_BOOST: i64 = 16;
+
+/// A server matching the filter
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterMatch {
+    pub index: usize,
+    pub score: i64,
+    /// Matched character positions in the host alias, for highlighting
+    pub host_positions: Vec<usize>,
+}
+
+/// Text the filter searches, with a weight in percent. The host comes first.
+fn search_fields(server: &Server) -> Vec<(&str, i64)> {
+    let mut fields = vec![(server.host.as_str(), 100), (server.hostname.as_str(), 70)];
+    if let Some(ref group) = server.group {
+        fields.push((group, 80));
+    }
+    fields.extend(server.tags.iter().map(|tag| (tag.as_str(), 80)));
+    if let Some(ref user) = server.user {
+        fields.push((user, 60));
+    }
+    fields.extend(server.jump_chain.iter().map(|hop| (hop.host.as_str(), 60)));
+    if let Some(ref owner) = server.owner {
+        fields.push((owner, 60));
+    }
+    if let Some(ref description) = server.description {
+        fields.push((description, 50));
+    }
+    fields
+}
+
+/// Order groups, recursively, by the best search rank among their servers
+fn sort_groups_by_rank(groups: &mut [ServerGroup], rank: &HashMap<usize, usize>) {
+    for group in groups.iter_mut() {
+        sort_groups_by_rank(&mut group.children, rank);
+    }
+    groups.sort_by_cached_key(|group| {
+        group
+            .all_servers()
+            .iter()
+            .filter_map(|idx| rank.get(idx))
+            .min()
+            .copied()
+    });
+}
+
+/// Ranked matches and list rows, reused until the filter, grouping, sort order
+/// or `revision` changes, so redraws and cursor moves don't redo the search
+#[derive(Debug)]
+struct RowCache {
+    filter_text: String,
+    group_by: GroupBy,
+    order: Vec<ServerId>,
+    revision: u64,
+    matches: Vec<FilterMatch>,
+    rows: Vec<ListRow>,
+}
+
 /// Duration before status messages auto-clear (in seconds)
 pub const STATUS_MESSAGE_TIMEOUT_SECS: u64 = 3;
 
 /// Main application state
 pub struct App {
+    /// Servers in the order they were loaded; see `order` for the sorted view
     pub servers: Vec<Server>,
+    /// Server IDs in the current sort order
+    pub order: Vec<ServerId>,
     pub groups: Vec<ServerGroup>,
     pub selected_index: usize,
     pub selected_group: usize,
     pub view_mode: ViewMode,
     pub sort_order: SortOrder,
+    pub group_by: GroupBy,
     pub filter_text: String,
     pub is_filtering: bool,
     pub should_quit: bool,
     pub status_message: Option<String>,
     pub status_message_time: Option<std::time::Instant>,
     pub is_fetching: bool,
+    /// Time between automatic health checks, if they're on
+    pub refresh_interval: Option<std::time::Duration>,
     pub history: History,
     // Command execution
     pub is_entering_command: bool,
@@ -95,23 +260,55 @@ pub struct App {
     pub use_mosh: bool,
     pub is_showing_install_menu: bool,
     pub install_menu_selection: usize,
+    // Effective config (`ssh -G`) panel in the details view
+    pub show_effective_config: bool,
+    /// Server an `ssh -G` is running for
+    pub resolving_config: Option<ServerId>,
+    /// Server the resolved config belongs to, and the result of `ssh -G`
+    pub effective_config: Option<(ServerId, Result<EffectiveConfig, String>)>,
+    pub effective_scroll: u16,
+    // Host editor
+    pub host_form: Option<HostForm>,
+    pub is_confirming_delete: bool,
+    /// Where new hosts go when the selected server can't tell
+    pub default_config_file: Option<PathBuf>,
+    // Tags set from within ggoto
+    pub tag_file: TagFile,
+    pub is_editing_tags: bool,
+    pub tag_input: String,
+    // Saved views
+    pub view_file: ViewFile,
+    /// Position of the last applied view in the view file
+    pub active_view: Option<usize>,
+    /// Columns shown in the server list
+    pub columns: Vec<Column>,
+    /// Columns shown outside a view, or by a view that doesn't pick them
+    pub default_columns: Vec<Column>,
+    pub is_naming_view: bool,
+    pub view_input: String,
+    /// Bumped whenever server data, tags, favorites or collapsed groups change
+    revision: u64,
+    row_cache: RefCell<Option<RowCache>>,
 }
 
 impl App {
     pub fn new() -> Self {
         Self {
             servers: Vec::new(),
+            order: Vec::new(),
             groups: Vec::new(),
             selected_index: 0,
             selected_group: 0,
             view_mode: ViewMode::ServerList,
             sort_order: SortOrder::Name,
+            group_by: GroupBy::Name,
             filter_text: String::new(),
             is_filtering: false,
             should_quit: false,
             status_message: None,
             status_message_time: None,
             is_fetching: false,
+            refresh_interval: None,
             history: History::default(),
             is_entering_command: false,
             command_text: String::new(),
@@ -129,6 +326,24 @@ impl App {
             use_mosh: is_mosh_installed(),
             is_showing_install_menu: false,
             install_menu_selection: 0,
+            show_effective_config: false,
+            resolving_config: None,
+            effective_config: None,
+            effective_scroll: 0,
+            host_form: None,
+            is_confirming_delete: false,
+            default_config_file: None,
+            tag_file: TagFile::default(),
+            is_editing_tags: false,
+            tag_input: String::new(),
+            view_file: ViewFile::default(),
+            active_view: None,
+            columns: Column::ALL.to_vec(),
+            default_columns: Column::ALL.to_vec(),
+            is_naming_view: false,
+            view_input: String::new(),
+            revision: 0,
+            row_cache: RefCell::new(None),
         }
     }
 
@@ -237,88 +452,708 @@ impl App {
         self.tunnel_input.pop();
     }
 
-    /// Get servers in display order (grouped by group name, then by filtered order within each group)
-    /// This matches the order shown in the UI
-    pub fn display_order_servers(&self) -> Vec<usize> {
-        use std::collections::BTreeMap;
-        let filtered = self.filtered_servers();
-        let mut grouped: BTreeMap<String, Vec<usize>> = BTreeMap::new();
-        for &idx in &filtered {
-            let group = self.servers[idx].group.clone().unwrap_or_default();
-            grouped.entry(group).or_default().push(idx);
+    /// Open the host form. Edit and duplicate start from the options the selected
+    /// host's own block sets; add starts empty, targeting the selected host's file.
+    pub fn start_host_form(&mut self, mode: HostFormMode) {
+        let selected = self
+            .selected_server()
+            .map(|s| (s.host.clone(), s.defined_at.clone()));
+
+        let form = match (mode, selected) {
+            (HostFormMode::Add, selected) => {
+                let file = selected
+                    .and_then(|(_, at)| at.map(|at| at.file))
+                    .or_else(|| self.default_config_file.clone());
+                let Some(file) = file else {
+                    self.set_status("No config file to add hosts to".to_string());
+                    return;
+                };
+                HostForm {
+                    mode,
+                    source: None,
+                    file,
+                    entry: HostEntry::default(),
+                    focused: 0,
+                }
+            }
+            (_, Some((host, Some(at)))) => {
+                let entry = std::fs::read_to_string(&at.file)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|content| read_host_entry(&content, at.line, &host));
+                let mut entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        self.set_status(format!("Cannot edit {}: {}", host, e));
+                        return;
+                    }
+                };
+                if mode == HostFormMode::Duplicate {
+                    entry.host = format!("{}-copy", host);
+                }
+                HostForm {
+                    mode,
+                    file: at.file.clone(),
+                    source: Some((host, at)),
+                    entry,
+                    focused: 0,
+                }
+            }
+            (_, Some((host, None))) => {
+                self.set_status(format!("{} doesn't come from a config file", host));
+                return;
+            }
+            (_, None) => return,
+        };
+        self.host_form = Some(form);
+    }
+
+    /// Close the host form without saving
+    pub fn stop_host_form(&mut self) {
+        self.host_form = None;
+    }
+
+    pub fn host_form_push(&mut self, c: char) {
+        if let Some(ref mut form) = self.host_form {
+            form.entry.field_mut(form.focused).push(c);
         }
-        let mut result = Vec::new();
-        for (_, server_indices) in grouped {
-            result.extend(server_indices);
+    }
+
+    pub fn host_form_pop(&mut self) {
+        if let Some(ref mut form) = self.host_form {
+            form.entry.field_mut(form.focused).pop();
         }
-        result
     }
 
-    /// Get filtered servers based on current filter text
-    /// Supports regex patterns - uses simple substring match for plain text
-    pub fn filtered_servers(&self) -> Vec<usize> {
-        if self.filter_text.is_empty() {
-            (0..self.servers.len()).collect()
-        } else {
-            let filter_lower = self.filter_text.to_lowercase();
-
-            // Check if pattern contains regex metacharacters
-            let has_regex_chars = self.filter_text.chars().any(|c| {
-                matches!(c, '.' | '*' | '+' | '?' | '^' | '$' | '[' | ']' | '(' | ')' | '{' | '}' | '|' | '\\')
+    /// Move focus to the next (or previous) form field, wrapping around
+    pub fn host_form_focus_next(&mut self, forward: bool) {
+        if let Some(ref mut form) = self.host_form {
+            let count = HostEntry::FIELD_COUNT;
+            form.focused = if forward {
+                (form.focused + 1) % count
+            } else {
+                (form.focused + count - 1) % count
+            };
+        }
+    }
+
+    /// Validate the form and turn it into an edit. The form stays open on errors.
+    pub fn submit_host_form(&mut self) -> Option<HostEdit> {
+        let form = self.host_form.as_ref()?;
+        let mut entry = form.entry.clone();
+        entry.host = entry.host.trim().to_string();
+
+        let renamed = form.source.as_ref().is_none_or(|(host, _)| *host != entry.host);
+        let result = entry.validate().and_then(|_| {
+            if renamed && self.server_index(&entry.host).is_some() {
+                anyhow::bail!("Host {} already exists", entry.host);
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            self.set_status(e.to_string());
+            return None;
+        }
+
+        let edit = match (form.mode, form.source.clone()) {
+            (HostFormMode::Edit, Some((host, at))) => HostEdit::Update { at, host, entry },
+            (HostFormMode::Duplicate, Some((host, at))) => HostEdit::Duplicate { at, host, entry },
+            _ => HostEdit::Add {
+                file: form.file.clone(),
+                entry,
+            },
+        };
+        self.host_form = None;
+        Some(edit)
+    }
+
+    /// Start editing the selected server's tags, starting from those in the tag file
+    pub fn start_tag_input(&mut self) {
+        if let Some(server) = self.selected_server() {
+            let tags = self.tag_file.get(&server.host);
+            self.tag_input = tags.into_iter().collect::<Vec<_>>().join(", ");
+            self.is_editing_tags = true;
+        }
+    }
+
+    /// Stop tag input mode
+    pub fn stop_tag_input(&mut self) {
+        self.is_editing_tags = false;
+    }
+
+    /// Add character to tag input
+    pub fn tag_input_push(&mut self, c: char) {
+        self.tag_input.push(c);
+    }
+
+    /// Remove character from tag input
+    pub fn tag_input_pop(&mut self) {
+        self.tag_input.pop();
+    }
+
+    /// Store the typed tags for the selected server. Returns whether the tag file
+    /// changed and needs saving.
+    pub fn submit_tags(&mut self) -> bool {
+        self.is_editing_tags = false;
+        let Some(idx) = self.selected_server_index() else {
+            return false;
+        };
+        let tags = parse_tags(&self.tag_input);
+        let host = self.servers[idx].host.clone();
+        if self.tag_file.get(&host) == tags {
+            return false;
+        }
+
+        self.servers[idx].set_local_tags(&tags);
+        self.tag_file.set(&host, tags);
+        // Tags show up in the tag groups, so keep the cursor on the server
+        self.rebuild_groups();
+        self.select_server(idx);
+        true
+    }
+
+    /// Show a column for each custom check, before the description
+    pub fn add_check_columns(&mut self, checks: &[CheckDef]) {
+        let at = self
+            .default_columns
+            .iter()
+            .position(|c| *c == Column::Description)
+            .unwrap_or(self.default_columns.len());
+        self.default_columns
+            .splice(at..at, checks.iter().map(|c| Column::Check(c.name.clone())));
+        self.columns = self.default_columns.clone();
+    }
+
+    /// Apply a saved view: its filter and scope, sort order, grouping and columns
+    pub fn apply_view(&mut self, index: usize) {
+        let Some(view) = self.view_file.views.get(index).cloned() else {
+            return;
+        };
+        self.filter_text = view.query();
+        if let Some(sort) = &view.sort {
+            self.sort_order = SortOrder::from_str(sort);
+            self.sort_servers();
+        }
+        self.group_by = view.group_by.as_deref().map(GroupBy::from_str).unwrap_or_default();
+        self.rebuild_groups();
+        self.columns = view.columns.unwrap_or_else(|| self.default_columns.clone());
+        self.active_view = Some(index);
+        self.selected_group = 0;
+        self.select_best_match();
+        self.set_status(format!("View: {}", view.name));
+    }
+
+    /// Apply a saved view by name. Returns false if there is no such view.
+    pub fn apply_view_named(&mut self, name: &str) -> bool {
+        match self.view_file.position(name) {
+            Some(index) => {
+                self.apply_view(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Switch to the next saved view, and back to all servers after the last one
+    pub fn cycle_view(&mut self) {
+        let count = self.view_file.views.len();
+        if count == 0 {
+            self.set_status("No saved views (W saves the current one)".to_string());
+            return;
+        }
+        match self.active_view.map(|i| i + 1) {
+            Some(next) if next >= count => self.clear_view(),
+            next => self.apply_view(next.unwrap_or(0)),
+        }
+    }
+
+    /// Leave the current view: no filter, grouping by name and every column
+    pub fn clear_view(&mut self) {
+        self.active_view = None;
+        self.filter_text.clear();
+        self.group_by = GroupBy::Name;
+        self.rebuild_groups();
+        self.columns = self.default_columns.clone();
+        self.selected_index = 0;
+        self.selected_group = 0;
+        self.set_status("View: all servers".to_string());
+    }
+
+    /// Start naming a view to save the current filter, sort, grouping and columns under
+    pub fn start_view_input(&mut self) {
+        self.view_input = self
+            .active_view
+            .and_then(|i| self.view_file.views.get(i))
+            .map(|v| v.name.clone())
+            .unwrap_or_default();
+        self.is_naming_view = true;
+    }
+
+    /// Stop view naming mode
+    pub fn stop_view_input(&mut self) {
+        self.is_naming_view = false;
+    }
+
+    /// Add character to the view name
+    pub fn view_input_push(&mut self, c: char) {
+        self.view_input.push(c);
+    }
+
+    /// Remove character from the view name
+    pub fn view_input_pop(&mut self) {
+        self.view_input.pop();
+    }
+
+    /// Save the current state as a view under the typed name, replacing a view
+    /// with the same name. Returns whether the view file changed and needs saving.
+    pub fn submit_view(&mut self) -> bool {
+        self.is_naming_view = false;
+        let name = self.view_input.trim().to_string();
+        if name.is_empty() {
+            return false;
+        }
+        let view = View {
+            name: name.clone(),
+            filter: self.filter_text.trim().to_string(),
+            sort: Some(self.sort_order.as_str().to_string()),
+            group_by: Some(self.group_by.as_str().to_string()),
+            group: None,
+            tag: None,
+            columns: (self.columns != self.default_columns).then(|| self.columns.clone()),
+        };
+        self.active_view = Some(self.view_file.upsert(view));
+        self.set_status(format!("Saved view: {}", name));
+        true
+    }
+
+    /// Ask to confirm deleting the selected host
+    pub fn start_delete_confirmation(&mut self) {
+        match self.selected_server() {
+            Some(server) if server.defined_at.is_none() => {
+                let msg = format!("{} doesn't come from a config file", server.host);
+                self.set_status(msg);
+            }
+            Some(_) => self.is_confirming_delete = true,
+            None => {}
+        }
+    }
+
+    /// The delete edit for the selected host, once confirmed
+    pub fn confirm_delete(&mut self) -> Option<HostEdit> {
+        self.is_confirming_delete = false;
+        let server = self.selected_server()?;
+        Some(HostEdit::Delete {
+            at: server.defined_at.clone()?,
+            host: server.host.clone(),
+        })
+    }
+
+    /// Tree paths of the groups a server is shown under in the current grouping mode.
+    /// Bastion and tag groups are a single level, since host names and tags contain
+    /// dashes. In tag mode a server shows up under each of its tags.
+    pub fn group_paths(&self, server: &Server) -> Vec<Vec<String>> {
+        match self.group_by {
+            GroupBy::Name => vec![group_path(server.group.as_deref().unwrap_or_default())],
+            GroupBy::Bastion => vec![vec![server.bastion().unwrap_or(DIRECT_GROUP).to_string()]],
+            GroupBy::Tag if server.tags.is_empty() => vec![vec![UNTAGGED_GROUP.to_string()]],
+            GroupBy::Tag => server.tags.iter().map(|tag| vec![tag.clone()]).collect(),
+        }
+    }
+
+    /// Switch to a grouping mode, or back to grouping by name if it's already active
+    pub fn toggle_group_by(&mut self, mode: GroupBy) {
+        self.group_by = if self.group_by == mode { GroupBy::Name } else { mode };
+        self.rebuild_groups();
+        self.selected_index = 0;
+        self.selected_group = 0;
+    }
+
+    /// Rebuild the group tree for the current grouping mode
+    pub fn rebuild_groups(&mut self) {
+        self.groups = build_groups_by(&self.servers, |server| self.group_paths(server));
+        self.invalidate_rows();
+    }
+
+    /// Key a group's collapsed state is stored under in history
+    fn collapse_key(&self, path: &str) -> String {
+        match self.group_by {
+            GroupBy::Name => path.to_string(),
+            GroupBy::Bastion => format!("bastion:{}", path),
+            GroupBy::Tag => format!("tag:{}", path),
+        }
+    }
+
+    /// Check if a group is collapsed in the current grouping mode
+    pub fn is_collapsed(&self, path: &str) -> bool {
+        self.history.is_collapsed(&self.collapse_key(path))
+    }
+
+    fn set_collapsed(&mut self, path: &str, collapsed: bool) {
+        let key = self.collapse_key(path);
+        self.history.set_collapsed(&key, collapsed);
+        self.invalidate_rows();
+    }
+
+    /// Drop the cached rows after changing something they're built from
+    pub fn invalidate_rows(&mut self) {
+        self.revision += 1;
+    }
+
+    /// Run `f` on the cached matches and rows, rebuilding them if they're stale
+    fn with_rows<T>(&self, f: impl FnOnce(&RowCache) -> T) -> T {
+        let fresh = self.row_cache.borrow().as_ref().is_some_and(|cache| {
+            cache.revision == self.revision
+                && cache.group_by == self.group_by
+                && cache.filter_text == self.filter_text
+                && cache.order == self.order
+        });
+        if !fresh {
+            let matches = self.rank_matches();
+            let rows = self.build_rows(&matches);
+            *self.row_cache.borrow_mut() = Some(RowCache {
+                filter_text: self.filter_text.clone(),
+                group_by: self.group_by,
+                order: self.order.clone(),
+                revision: self.revision,
+                matches,
+                rows,
             });
+        }
+        f(self.row_cache.borrow().as_ref().expect("row cache was just filled"))
+    }
 
-            // Only use regex if pattern contains metacharacters
-            let regex = if has_regex_chars {
-                Regex::new(&format!("(?i){}", &self.filter_text)).ok()
-            } else {
-                None
+    /// Flatten a group tree into rows, skipping the contents of collapsed groups
+    fn push_rows(&self, groups: &[ServerGroup], depth: usize, with_servers: bool, rows: &mut Vec<ListRow>) {
+        for group in groups {
+            // A filter shows every match, so groups stay open while filtering
+            let collapsed = self.filter_text.is_empty() && self.is_collapsed(&group.path);
+            rows.push(ListRow::Group {
+                path: group.path.clone(),
+                name: group.name.clone(),
+                depth,
+                servers: group.all_servers(),
+                collapsed,
+            });
+            if collapsed {
+                continue;
+            }
+            if with_servers {
+                rows.extend(group.servers.iter().map(|&index| ListRow::Server {
+                    index,
+                    depth: depth + 1,
+                }));
+            }
+            self.push_rows(&group.children, depth + 1, with_servers, rows);
+        }
+    }
+
+    /// Rows of the server list: group headers and the filtered servers under them
+    pub fn list_rows(&self) -> Vec<ListRow> {
+        self.with_rows(|cache| cache.rows.clone())
+    }
+
+    fn build_rows(&self, matches: &[FilterMatch]) -> Vec<ListRow> {
+        let filtered: Vec<usize> = matches.iter().map(|m| m.index).collect();
+        let mut groups = ServerGroup::tree(filtered.iter().flat_map(|&idx| {
+            self.group_paths(&self.servers[idx])
+                .into_iter()
+                .map(move |path| (idx, path))
+        }));
+        if !self.filter_text.is_empty() {
+            // Groups holding the best matches come first
+            let rank: HashMap<usize, usize> =
+                filtered.iter().enumerate().map(|(rank, &idx)| (idx, rank)).collect();
+            sort_groups_by_rank(&mut groups, &rank);
+        }
+        let mut rows = Vec::new();
+        self.push_rows(&groups, 0, true, &mut rows);
+        rows
+    }
+
+    /// Rows of the group view: the group tree without servers
+    pub fn group_rows(&self) -> Vec<ListRow> {
+        let mut rows = Vec::new();
+        self.push_rows(&self.groups, 0, false, &mut rows);
+        rows
+    }
+
+    /// Get visible servers in display order (grouped, then by filtered order within each group)
+    /// This matches the order shown in the UI, without servers in collapsed groups
+    pub fn display_order_servers(&self) -> Vec<usize> {
+        self.list_rows()
+            .into_iter()
+            .filter_map(|row| match row {
+                ListRow::Server { index, .. } => Some(index),
+                ListRow::Group { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Get filtered servers based on current filter text, best matches first
+    pub fn filtered_servers(&self) -> Vec<usize> {
+        self.with_rows(|cache| cache.matches.iter().map(|m| m.index).collect())
+    }
+
+    /// Servers matching the filter, ranked. Predicates such as `cpu<20` or `tag:prod`
+    /// narrow the list first. The remaining text is a fuzzy search over host,
+    /// hostname, user, group, tags and other metadata, with each space-separated
+    /// term required to match; `re:` switches to a case-insensitive regex.
+    pub fn filter_matches(&self) -> Vec<FilterMatch> {
+        self.with_rows(|cache| cache.matches.clone())
+    }
+
+    fn rank_matches(&self) -> Vec<FilterMatch> {
+        let query = Query::parse(&self.filter_text);
+        let candidates = self
+            .sorted_indices()
+            .into_iter()
+            .map(|index| (index, &self.servers[index]))
+            .filter(|(_, s)| query.matches(s, &self.history));
+
+        if query.text.is_empty() {
+            return candidates
+                .map(|(index, _)| FilterMatch {
+                    index,
+                    score: 0,
+                    host_positions: Vec::new(),
+                })
+                .collect();
+        }
+
+        if let Some(pattern) = query.text.strip_prefix(REGEX_FILTER_PREFIX) {
+            // An incomplete pattern matches nothing until it parses
+            let Ok(re) = Regex::new(&format!("(?i){}", pattern)) else {
+                return Vec::new();
             };
+            return candidates
+                .filter(|(_, s)| search_fields(s).iter().any(|(text, _)| re.is_match(text)))
+                .map(|(index, s)| FilterMatch {
+                    index,
+                    score: 0,
+                    host_positions: re
+                        .find(&s.host)
+                        .map(|m| {
+                            let start = s.host[..m.start()].chars().count();
+                            (start..start + m.as_str().chars().count()).collect()
+                        })
+                        .unwrap_or_default(),
+                })
+                .collect();
+        }
 
-            self.servers
-                .iter()
-                .enumerate()
-                .filter(|(_, s)| {
-                    if let Some(ref re) = regex {
-                        // Use regex matching
-                        re.is_match(&s.host)
-                            || re.is_match(&s.hostname)
-                            || s.group.as_ref().is_some_and(|g| re.is_match(g))
-                    } else {
-                        // Use simple substring matching (case-insensitive)
-                        s.host.to_lowercase().contains(&filter_lower)
-                            || s.hostname.to_lowercase().contains(&filter_lower)
-                            || s.group
-                                .as_ref()
-                                .is_some_and(|g| g.to_lowercase().contains(&filter_lower))
+        let terms: Vec<&str> = query.text.split_whitespace().collect();
+        let mut matches: Vec<FilterMatch> = candidates
+            .filter_map(|(index, server)| {
+                let fields = search_fields(server);
+                let mut score = 0;
+                let mut host_positions = Vec::new();
+                for term in &terms {
+                    // Best weighted match of this term over all fields
+                    let (field, m, weighted) = fields
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(i, (text, weight))| {
+                            let m = fuzzy_match(term, text)?;
+                            let weighted = m.score * weight / 100;
+                            Some((i, m, weighted))
+                        })
+                        .max_by_key(|(i, _, weighted)| (*weighted, std::cmp::Reverse(*i)))?;
+                    score += weighted;
+                    if field == 0 {
+                        host_positions.extend(m.positions);
                     }
+                }
+                score += self.search_boost(&server.host);
+                host_positions.sort_unstable();
+                host_positions.dedup();
+                Some(FilterMatch {
+                    index,
+                    score,
+                    host_positions,
                 })
-                .map(|(i, _)| i)
-                .collect()
+            })
+            .collect();
+        // Stable, so equal scores keep the current sort order
+        matches.sort_by_key(|m| std::cmp::Reverse(m.score));
+        matches
+    }
+
+    /// Ranking boost for favorites and recently used servers
+    fn search_boost(&self, host: &str) -> i64 {
+        let mut boost = 0;
+        if self.history.is_favorite(host) {
+            boost += FAVORITE_BOOST;
+        }
+        if let Some(last) = self.history.last_connected(host) {
+            let age = chrono::Utc::now().signed_duration_since(last);
+            if age.num_days() < 1 {
+                boost += RECENT_BOOST;
+            } else if age.num_days() < 7 {
+                boost += RECENT_BOOST / 2;
+            }
+        }
+        boost
+    }
+
+    /// Index of the currently selected server, if a server row is selected
+    pub fn selected_server_index(&self) -> Option<usize> {
+        match self.list_rows().get(self.selected_index) {
+            Some(ListRow::Server { index, .. }) => Some(*index),
+            _ => None,
         }
     }
 
     /// Get the currently selected server (based on display order)
     pub fn selected_server(&self) -> Option<&Server> {
-        let display_order = self.display_order_servers();
-        display_order.get(self.selected_index).map(|&i| &self.servers[i])
+        self.selected_server_index().map(|i| &self.servers[i])
+    }
+
+    /// ID of the currently selected server
+    pub fn selected_server_id(&self) -> Option<ServerId> {
+        self.selected_server().map(|s| s.id)
+    }
+
+    /// Servers the free GPU finder looks at: the selected group's, or all the
+    /// servers the filter shows
+    pub fn free_gpu_scope(&self) -> Vec<usize> {
+        match self.list_rows().get(self.selected_index) {
+            Some(ListRow::Group { servers, .. }) => servers.clone(),
+            _ => self.filtered_servers(),
+        }
     }
 
     /// Get mutable reference to selected server (based on display order)
     #[allow(dead_code)]
     pub fn selected_server_mut(&mut self) -> Option<&mut Server> {
-        let display_order = self.display_order_servers();
-        if let Some(&idx) = display_order.get(self.selected_index) {
-            Some(&mut self.servers[idx])
-        } else {
-            None
+        self.selected_server_index().map(|i| &mut self.servers[i])
+    }
+
+    /// Move the cursor to a server. If it's hidden in a collapsed group, the
+    /// innermost visible header containing it is selected instead.
+    pub fn select_server(&mut self, idx: usize) {
+        let rows = self.list_rows();
+        let pos = rows
+            .iter()
+            .position(|row| matches!(row, ListRow::Server { index, .. } if *index == idx))
+            .or_else(|| {
+                rows.iter().rposition(
+                    |row| matches!(row, ListRow::Group { servers, .. } if servers.contains(&idx)),
+                )
+            });
+        self.selected_index = match pos {
+            Some(pos) => pos,
+            None => self.selected_index.min(rows.len().saturating_sub(1)),
+        };
+    }
+
+    /// Move the cursor to a server by host alias
+    #[allow(dead_code)]
+    pub fn select_host(&mut self, host: &str) {
+        if let Some(idx) = self.server_index(host) {
+            self.select_server(idx);
+        }
+    }
+
+    /// The group row under the cursor in the current view
+    fn selected_group_row(&self) -> Option<ListRow> {
+        let row = match self.view_mode {
+            ViewMode::GroupList => self.group_rows().get(self.selected_group).cloned(),
+            _ => self.list_rows().get(self.selected_index).cloned(),
+        };
+        row.filter(|row| matches!(row, ListRow::Group { .. }))
+    }
+
+    /// Collapse or expand the selected group. Returns whether anything changed.
+    pub fn toggle_selected_group(&mut self) -> bool {
+        match self.selected_group_row() {
+            Some(ListRow::Group { collapsed, .. }) if collapsed => self.expand_selected_group(),
+            Some(ListRow::Group { .. }) => self.collapse_selected_group(),
+            _ => false,
+        }
+    }
+
+    /// Collapse the group under the cursor. On a server or an already collapsed
+    /// group, move to the parent group's header instead.
+    pub fn collapse_selected_group(&mut self) -> bool {
+        if !self.filter_text.is_empty() {
+            self.set_status("Clear the filter to collapse groups".to_string());
+            return false;
+        }
+        let (rows, cursor) = match self.view_mode {
+            ViewMode::GroupList => (self.group_rows(), self.selected_group),
+            _ => (self.list_rows(), self.selected_index),
+        };
+        let (depth, expanded_group) = match rows.get(cursor) {
+            Some(ListRow::Group { path, depth, collapsed, .. }) => {
+                (*depth, (!collapsed).then(|| path.clone()))
+            }
+            Some(ListRow::Server { depth, .. }) => (*depth, None),
+            None => return false,
+        };
+
+        if let Some(path) = expanded_group {
+            self.set_collapsed(&path, true);
+            return true;
+        }
+        // Jump to the enclosing header
+        let parent = rows[..cursor]
+            .iter()
+            .rposition(|row| matches!(row, ListRow::Group { depth: d, .. } if *d < depth));
+        if let Some(pos) = parent {
+            match self.view_mode {
+                ViewMode::GroupList => self.selected_group = pos,
+                _ => self.selected_index = pos,
+            }
+        }
+        false
+    }
+
+    /// Expand the group under the cursor
+    pub fn expand_selected_group(&mut self) -> bool {
+        match self.selected_group_row() {
+            Some(ListRow::Group { path, collapsed, .. }) if collapsed => {
+                self.set_collapsed(&path, false);
+                true
+            }
+            _ => false,
         }
     }
 
+    /// Collapse every group, or expand them all if they're all collapsed already
+    pub fn toggle_all_groups(&mut self) -> bool {
+        if !self.filter_text.is_empty() {
+            self.set_status("Clear the filter to collapse groups".to_string());
+            return false;
+        }
+        fn paths(groups: &[ServerGroup], out: &mut Vec<String>) {
+            for group in groups {
+                out.push(group.path.clone());
+                paths(&group.children, out);
+            }
+        }
+        let mut all = Vec::new();
+        paths(&self.groups, &mut all);
+
+        let selected = self.selected_server_index();
+        let collapse = all.iter().any(|path| !self.is_collapsed(path));
+        for path in &all {
+            self.set_collapsed(path, collapse);
+        }
+        match selected {
+            Some(idx) => self.select_server(idx),
+            None => {
+                let count = self.list_rows().len();
+                self.selected_index = self.selected_index.min(count.saturating_sub(1));
+            }
+        }
+        self.selected_group = self.selected_group.min(self.group_rows().len().saturating_sub(1));
+        !all.is_empty()
+    }
+
     /// Move selection up
     pub fn select_previous(&mut self) {
         let count = match self.view_mode {
-            ViewMode::ServerList => self.display_order_servers().len(),
-            ViewMode::GroupList => self.groups.len(),
+            ViewMode::ServerList => self.list_rows().len(),
+            ViewMode::GroupList => self.group_rows().len(),
             _ => 0,
         };
         if count > 0 {
@@ -337,21 +1172,17 @@ impl App {
     /// Move selection down
     pub fn select_next(&mut self) {
         let count = match self.view_mode {
-            ViewMode::ServerList => self.display_order_servers().len(),
-            ViewMode::GroupList => self.groups.len(),
+            ViewMode::ServerList => self.list_rows().len(),
+            ViewMode::GroupList => self.group_rows().len(),
             _ => 0,
         };
         if count > 0 {
             match self.view_mode {
-                ViewMode::ServerList => {
-                    if self.selected_index < count - 1 {
-                        self.selected_index += 1;
-                    }
+                ViewMode::ServerList if self.selected_index < count - 1 => {
+                    self.selected_index += 1;
                 }
-                ViewMode::GroupList => {
-                    if self.selected_group < count - 1 {
-                        self.selected_group += 1;
-                    }
+                ViewMode::GroupList if self.selected_group < count - 1 => {
+                    self.selected_group += 1;
                 }
                 _ => {}
             }
@@ -384,81 +1215,89 @@ impl App {
         self.sort_servers();
     }
 
-    /// Sort servers based on current sort order
+    /// Sort servers based on current sort order. Only `order` changes, so server
+    /// indices stay valid, and the cursor stays on the selected server.
     pub fn sort_servers(&mut self) {
+        let selected = self.selected_server().map(|s| s.id);
+        let mut indices = self.sorted_indices();
+        indices.sort_by(|&a, &b| self.compare_servers(&self.servers[a], &self.servers[b]));
+        self.order = indices.iter().map(|&i| self.servers[i].id).collect();
+        self.invalidate_rows();
+        if let Some(idx) = selected.and_then(|id| self.index_of(id)) {
+            self.select_server(idx);
+        }
+    }
+
+    /// Compare two servers by the current sort order
+    fn compare_servers(&self, a: &Server, b: &Server) -> std::cmp::Ordering {
         match self.sort_order {
-            SortOrder::Name => {
-                self.servers.sort_by(|a, b| a.host.cmp(&b.host));
-            }
+            SortOrder::Name => a.host.cmp(&b.host),
             SortOrder::Favorites => {
                 // Sort favorites first, then by name
-                self.servers.sort_by(|a, b| {
-                    let a_fav = self.history.is_favorite(&a.host);
-                    let b_fav = self.history.is_favorite(&b.host);
-                    match (b_fav, a_fav) {
-                        (true, false) => std::cmp::Ordering::Greater,
-                        (false, true) => std::cmp::Ordering::Less,
-                        _ => a.host.cmp(&b.host),
-                    }
-                });
+                let a_fav = self.history.is_favorite(&a.host);
+                let b_fav = self.history.is_favorite(&b.host);
+                match (b_fav, a_fav) {
+                    (true, false) => std::cmp::Ordering::Greater,
+                    (false, true) => std::cmp::Ordering::Less,
+                    _ => a.host.cmp(&b.host),
+                }
             }
             SortOrder::RecentlyUsed => {
                 // Sort by last connection time (most recent first)
-                self.servers.sort_by(|a, b| {
-                    let a_time = self.history.last_connected(&a.host);
-                    let b_time = self.history.last_connected(&b.host);
-                    // Reverse order: most recent first
-                    match (b_time, a_time) {
-                        (Some(b_t), Some(a_t)) => b_t.cmp(&a_t),
-                        (Some(_), None) => std::cmp::Ordering::Less,
-                        (None, Some(_)) => std::cmp::Ordering::Greater,
-                        (None, None) => a.host.cmp(&b.host), // Fall back to name
-                    }
-                });
-            }
-            SortOrder::Latency => {
-                self.servers.sort_by(|a, b| {
-                    a.latency_ms()
-                        .unwrap_or(u64::MAX)
-                        .cmp(&b.latency_ms().unwrap_or(u64::MAX))
-                });
+                let a_time = self.history.last_connected(&a.host);
+                let b_time = self.history.last_connected(&b.host);
+                // Reverse order: most recent first
+                match (b_time, a_time) {
+                    (Some(b_t), Some(a_t)) => b_t.cmp(&a_t),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => a.host.cmp(&b.host), // Fall back to name
+                }
             }
+            SortOrder::Latency => a
+                .latency_ms()
+                .unwrap_or(u64::MAX)
+                .cmp(&b.latency_ms().unwrap_or(u64::MAX)),
             SortOrder::CpuUsage => {
-                self.servers.sort_by(|a, b| {
-                    let a_cpu = a.metrics.as_ref().map(|m| m.cpu_usage).unwrap_or(f32::MAX);
-                    let b_cpu = b.metrics.as_ref().map(|m| m.cpu_usage).unwrap_or(f32::MAX);
-                    a_cpu
-                        .partial_cmp(&b_cpu)
-                        .unwrap_or(std::cmp::Ordering::Equal)
-                });
+                let a_cpu = a.metrics.as_ref().map(|m| m.cpu_usage).unwrap_or(f32::MAX);
+                let b_cpu = b.metrics.as_ref().map(|m| m.cpu_usage).unwrap_or(f32::MAX);
+                a_cpu
+                    .partial_cmp(&b_cpu)
+                    .unwrap_or(std::cmp::Ordering::Equal)
             }
             SortOrder::RamUsage => {
-                self.servers.sort_by(|a, b| {
-                    let a_ram = a
-                        .metrics
-                        .as_ref()
-                        .map(|m| m.ram_usage_percent())
-                        .unwrap_or(f32::MAX);
-                    let b_ram = b
-                        .metrics
-                        .as_ref()
-                        .map(|m| m.ram_usage_percent())
-                        .unwrap_or(f32::MAX);
-                    a_ram
-                        .partial_cmp(&b_ram)
-                        .unwrap_or(std::cmp::Ordering::Equal)
-                });
+                let a_ram = a
+                    .metrics
+                    .as_ref()
+                    .map(|m| m.ram_usage_percent())
+                    .unwrap_or(f32::MAX);
+                let b_ram = b
+                    .metrics
+                    .as_ref()
+                    .map(|m| m.ram_usage_percent())
+                    .unwrap_or(f32::MAX);
+                a_ram
+                    .partial_cmp(&b_ram)
+                    .unwrap_or(std::cmp::Ordering::Equal)
             }
             SortOrder::Group => {
-                self.servers.sort_by(|a, b| {
-                    let a_group = a.group.as_deref().unwrap_or("");
-                    let b_group = b.group.as_deref().unwrap_or("");
-                    a_group.cmp(b_group).then_with(|| a.host.cmp(&b.host))
-                });
+                let a_group = a.group.as_deref().unwrap_or("");
+                let b_group = b.group.as_deref().unwrap_or("");
+                a_group.cmp(b_group).then_with(|| a.host.cmp(&b.host))
             }
         }
     }
 
+    /// Server indices in the current sort order. Servers the last sort didn't
+    /// see come last, in list order.
+    pub fn sorted_indices(&self) -> Vec<usize> {
+        let position: HashMap<ServerId, usize> =
+            self.order.iter().enumerate().map(|(pos, &id)| (id, pos)).collect();
+        let mut indices: Vec<usize> = (0..self.servers.len()).collect();
+        indices.sort_by_key(|&i| position.get(&self.servers[i].id).copied().unwrap_or(usize::MAX));
+        indices
+    }
+
     /// Enter filter mode
     pub fn start_filtering(&mut self) {
         self.is_filtering = true;
@@ -473,13 +1312,21 @@ impl App {
     /// Add character to filter
     pub fn filter_push(&mut self, c: char) {
         self.filter_text.push(c);
-        self.selected_index = 0;
+        self.select_best_match();
     }
 
     /// Remove character from filter
     pub fn filter_pop(&mut self) {
         self.filter_text.pop();
-        self.selected_index = 0;
+        self.select_best_match();
+    }
+
+    /// Put the cursor on the top-ranked match, so Enter connects to it
+    fn select_best_match(&mut self) {
+        match self.filtered_servers().first() {
+            Some(&idx) => self.select_server(idx),
+            None => self.selected_index = 0,
+        }
     }
 
     /// Clear filter
@@ -488,12 +1335,83 @@ impl App {
         self.selected_index = 0;
     }
 
+    /// Index of a server by host alias
+    pub fn server_index(&self, host: &str) -> Option<usize> {
+        self.servers.iter().position(|s| s.host == host)
+    }
+
+    /// Index of a server by ID
+    pub fn index_of(&self, id: ServerId) -> Option<usize> {
+        self.servers.iter().position(|s| s.id == id)
+    }
+
+    /// Store a health check's results on its server. The host may have been
+    /// removed by a config reload since the check started.
+    pub fn apply_health_update(&mut self, update: HealthUpdate) {
+        if let Some(idx) = self.index_of(update.server_id) {
+            let server = &mut self.servers[idx];
+            server.latency = update.latency;
+            server.status = update.status;
+            server.metrics = update.metrics;
+            server.check_results = update.checks;
+            server.last_check = Some(std::time::Instant::now());
+            self.invalidate_rows();
+        }
+    }
+
+    /// Replace the server list with a freshly parsed one. IDs, health data and
+    /// the selection carry over for hosts that still exist.
+    pub fn merge_servers(&mut self, mut servers: Vec<Server>) -> ServerListChanges {
+        let selected = self.selected_server().map(|s| s.id);
+        let mut previous: HashMap<String, Server> = self
+            .servers
+            .drain(..)
+            .map(|s| (s.host.clone(), s))
+            .collect();
+
+        let mut changes = ServerListChanges::default();
+        for server in &mut servers {
+            match previous.remove(&server.host) {
+                Some(old) => {
+                    server.id = old.id;
+                    if !server.same_endpoint(&old) {
+                        changes.changed.push(server.id);
+                    }
+                    server.latency = old.latency;
+                    server.status = old.status;
+                    server.metrics = old.metrics;
+                    server.check_results = old.check_results;
+                    server.last_check = old.last_check;
+                }
+                None => changes.added.push(server.id),
+            }
+        }
+        changes.removed = previous.into_values().map(|s| s.id).collect();
+        changes.removed.sort();
+
+        self.servers = servers;
+        self.rebuild_groups();
+        self.sort_servers();
+
+        // Keep the cursor on the same host, or clamp it if the host is gone
+        match selected.and_then(|id| self.index_of(id)) {
+            Some(idx) => self.select_server(idx),
+            None => {
+                let count = self.list_rows().len();
+                self.selected_index = self.selected_index.min(count.saturating_sub(1));
+            }
+        }
+        self.selected_group = self.selected_group.min(self.group_rows().len().saturating_sub(1));
+
+        changes
+    }
+
     /// Toggle favorite for the currently selected server
     pub fn toggle_selected_favorite(&mut self) {
-        let display_order = self.display_order_servers();
-        if let Some(&idx) = display_order.get(self.selected_index) {
+        if let Some(idx) = self.selected_server_index() {
             let host = self.servers[idx].host.clone();
             self.history.toggle_favorite(&host);
+            self.invalidate_rows();
         }
     }
 }
@@ -507,6 +1425,7 @@ impl Default for App {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::server::{GpuInfo, SystemMetrics};
 
     #[test]
     fn test_filter_contains_substring() {
@@ -550,13 +1469,291 @@ mod tests {
         ];
 
         // Regex: ysdc. should match both ysdc servers
-        app.filter_text = "ysdc.".to_string();
+        app.filter_text = "re:ysdc.".to_string();
         let filtered = app.filtered_servers();
         assert_eq!(filtered.len(), 2);
 
         // Regex: ^prod should match prod-web
-        app.filter_text = "^prod".to_string();
+        app.filter_text = "re:^prod".to_string();
         let filtered = app.filtered_servers();
         assert_eq!(filtered.len(), 1);
+
+        // Without the prefix, metacharacters are searched for literally
+        app.filter_text = "^prod".to_string();
+        assert!(app.filtered_servers().is_empty());
+
+        // Incomplete patterns match nothing
+        app.filter_text = "re:(".to_string();
+        assert!(app.filtered_servers().is_empty());
+    }
+
+    #[test]
+    fn test_filter_annotations() {
+        let mut app = App::new();
+        app.servers = vec![
+            Server::new("box1".to_string(), "10.0.0.1".to_string()),
+            Server::new("box2".to_string(), "10.0.0.2".to_string()),
+        ];
+        app.servers[0].tags.insert("a100".to_string());
+        app.servers[1].description = Some("Build machine".to_string());
+        app.servers[1].owner = Some("ml-team".to_string());
+
+        app.filter_text = "a100".to_string();
+        assert_eq!(app.filtered_servers(), vec![0]);
+
+        app.filter_text = "build".to_string();
+        assert_eq!(app.filtered_servers(), vec![1]);
+
+        app.filter_text = "re:^ml-".to_string();
+        assert_eq!(app.filtered_servers(), vec![1]);
+    }
+
+    #[test]
+    fn test_merge_servers_keeps_state() {
+        use crate::server::HealthStatus;
+
+        let mut app = App::new();
+        app.servers = vec![
+            Server::new("alpha".to_string(), "10.0.0.1".to_string()),
+            Server::new("beta".to_string(), "10.0.0.2".to_string()),
+            Server::new("gamma".to_string(), "10.0.0.3".to_string()),
+        ];
+        app.servers[1].status = HealthStatus::Healthy;
+        app.servers[2].status = HealthStatus::Healthy;
+        app.select_host("beta");
+        let ids: Vec<ServerId> = app.servers.iter().map(|s| s.id).collect();
+
+        let reloaded = vec![
+            Server::new("beta".to_string(), "10.0.0.2".to_string()),
+            Server::new("gamma".to_string(), "10.9.9.9".to_string()),
+            Server::new("delta".to_string(), "10.0.0.4".to_string()),
+        ];
+        let changes = app.merge_servers(reloaded);
+
+        // Hosts that are still there keep their IDs
+        assert_eq!(app.servers[0].id, ids[1]);
+        assert_eq!(app.servers[1].id, ids[2]);
+        assert_eq!(changes.added, vec![app.servers[2].id]);
+        assert_eq!(changes.removed, vec![ids[0]]);
+        assert_eq!(changes.changed, vec![ids[2]]);
+
+        let beta = &app.servers[app.server_index("beta").unwrap()];
+        assert_eq!(beta.status, HealthStatus::Healthy);
+        assert_eq!(app.selected_server().unwrap().host, "beta");
+    }
+
+    #[test]
+    fn test_collapsed_groups() {
+        let mut app = App::new();
+        app.servers = ["prod-web-01", "prod-web-02", "prod-db-01", "dev"]
+            .iter()
+            .map(|h| Server::new(h.to_string(), "10.0.0.1".to_string()))
+            .collect();
+        crate::ssh::group_servers(&mut app.servers, &[]);
+        app.sort_servers();
+        app.rebuild_groups();
+
+        let headers = |app: &App| -> Vec<String> {
+            app.list_rows()
+                .into_iter()
+                .filter_map(|row| match row {
+                    ListRow::Group { path, .. } => Some(path),
+                    ListRow::Server { .. } => None,
+                })
+                .collect()
+        };
+        assert_eq!(headers(&app), vec!["dev", "prod", "prod-db", "prod-web"]);
+        assert_eq!(app.display_order_servers().len(), 4);
+
+        // Collapse `prod` from one of its servers: the first Left jumps to the
+        // `prod-db` header, the next collapses it, then up to `prod`
+        app.select_host("prod-db-01");
+        assert!(!app.collapse_selected_group());
+        assert!(app.collapse_selected_group());
+        assert!(!app.collapse_selected_group());
+        assert!(app.collapse_selected_group());
+        assert_eq!(headers(&app), vec!["dev", "prod"]);
+        assert_eq!(app.display_order_servers().len(), 1);
+        assert!(app.history.is_collapsed("prod"));
+
+        // Hidden servers select their collapsed header
+        app.select_host("prod-web-02");
+        assert!(app.selected_server().is_none());
+        assert!(matches!(
+            app.list_rows().get(app.selected_index),
+            Some(ListRow::Group { path, collapsed: true, .. }) if path == "prod"
+        ));
+
+        // A filter shows matches inside collapsed groups
+        app.filter_text = "web-02".to_string();
+        assert_eq!(app.display_order_servers().len(), 1);
+        app.filter_text.clear();
+
+        assert!(app.toggle_selected_group());
+        assert_eq!(app.display_order_servers().len(), 3);
+    }
+
+    #[test]
+    fn test_tag_groups() {
+        let mut app = App::new();
+        app.servers = vec![
+            Server::new("train1".to_string(), "10.0.0.1".to_string()),
+            Server::new("train2".to_string(), "10.0.0.2".to_string()),
+            Server::new("web".to_string(), "10.0.0.3".to_string()),
+        ];
+        app.servers[0].tags = parse_tags("gpu a100");
+        app.servers[1].tags = parse_tags("gpu");
+        app.toggle_group_by(GroupBy::Tag);
+
+        let names: Vec<&str> = app.groups.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, vec!["a100", "gpu", UNTAGGED_GROUP]);
+        assert_eq!(app.groups[1].servers, vec![0, 1]);
+
+        // Tagging from the TUI regroups and keeps the cursor on the server
+        app.select_host("web");
+        app.start_tag_input();
+        app.tag_input = "gpu, team-vision".to_string();
+        assert!(app.submit_tags());
+        assert_eq!(app.selected_server().unwrap().host, "web");
+        assert_eq!(app.tag_file.get("web"), parse_tags("gpu team-vision"));
+        let names: Vec<&str> = app.groups.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, vec!["a100", "gpu", "team-vision"]);
+
+        app.toggle_group_by(GroupBy::Tag);
+        assert_eq!(app.group_by, GroupBy::Name);
+    }
+
+    #[test]
+    fn test_fuzzy_filter_ranking() {
+        let mut app = App::new();
+        app.servers = ["prod-web-01", "prod-web-02", "pw1-backup", "staging-web-1"]
+            .iter()
+            .map(|h| Server::new(h.to_string(), format!("{}.example.com", h)))
+            .collect();
+
+        app.filter_text = "pw1".to_string();
+        let hosts: Vec<&str> = app
+            .filtered_servers()
+            .iter()
+            .map(|&i| app.servers[i].host.as_str())
+            .collect();
+        // staging-web-1 has no `p`
+        assert_eq!(hosts, vec!["pw1-backup", "prod-web-01"]);
+
+        let matches = app.filter_matches();
+        assert_eq!(matches[1].host_positions, vec![0, 5, 10]);
+
+        // Favorites are boosted above an equally good match
+        app.filter_text = "prod web".to_string();
+        assert_eq!(app.filtered_servers()[0], 0);
+        app.history.toggle_favorite("prod-web-02");
+        // Rows are cached until something they depend on is known to change
+        assert_eq!(app.filtered_servers()[0], 0);
+        app.invalidate_rows();
+        assert_eq!(app.filtered_servers()[0], 1);
+    }
+
+    #[test]
+    fn test_filter_predicates() {
+        let mut app = App::new();
+        app.servers = ["gpu-01", "gpu-02", "web-01"]
+            .iter()
+            .map(|h| Server::new(h.to_string(), format!("{}.example.com", h)))
+            .collect();
+        for (server, util) in app.servers.iter_mut().zip([0.0, 100.0]) {
+            server.metrics = Some(SystemMetrics {
+                gpus: vec![GpuInfo {
+                    name: "A100".to_string(),
+                    utilization: util,
+                    memory_used: 0,
+                    memory_total: 80_000_000_000,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            });
+        }
+
+        app.filter_text = "gpu.free>=1".to_string();
+        assert_eq!(app.filtered_servers(), vec![0]);
+
+        // Predicates combine with the free-text search
+        app.filter_text = "gpus>0 02".to_string();
+        assert_eq!(app.filtered_servers(), vec![1]);
+        app.filter_text = "gpus>0 re:web".to_string();
+        assert!(app.filtered_servers().is_empty());
+    }
+
+    #[test]
+    fn test_saved_views() {
+        let mut app = App::new();
+        app.servers = ["prod-web-01", "prod-web-02", "dev-box"]
+            .iter()
+            .map(|h| Server::new(h.to_string(), format!("{}.example.com", h)))
+            .collect();
+        app.servers[2].tags.insert("mine".to_string());
+        app.view_file = serde_json::from_str(
+            r#"[
+                { "name": "prod web", "group": "prod", "sort": "name" },
+                { "name": "my boxes", "tag": "mine", "group_by": "tag", "columns": ["ping", "cpu"] }
+            ]"#,
+        )
+        .unwrap();
+
+        app.cycle_view();
+        assert_eq!(app.active_view, Some(0));
+        assert_eq!(app.filter_text, "group:prod");
+        assert_eq!(app.display_order_servers().len(), 0);
+        app.servers.iter_mut().for_each(|s| {
+            s.group = s.host.rsplit_once('-').map(|(g, _)| g.to_string());
+        });
+        app.invalidate_rows();
+        assert_eq!(app.display_order_servers().len(), 2);
+
+        app.cycle_view();
+        assert_eq!(app.group_by, GroupBy::Tag);
+        assert_eq!(app.columns, vec![Column::Ping, Column::Cpu]);
+        assert_eq!(app.selected_server().unwrap().host, "dev-box");
+
+        // Past the last view, back to everything
+        app.cycle_view();
+        assert_eq!(app.active_view, None);
+        assert!(app.filter_text.is_empty());
+        assert_eq!(app.columns, Column::ALL.to_vec());
+
+        // Saving under an existing name replaces that view
+        app.filter_text = "cpu<20".to_string();
+        app.view_input = "prod web".to_string();
+        assert!(app.submit_view());
+        assert_eq!(app.view_file.views.len(), 2);
+        assert_eq!(app.view_file.views[0].filter, "cpu<20");
+        assert_eq!(app.view_file.views[0].columns, None);
+        assert!(app.apply_view_named("my boxes"));
+        assert!(!app.apply_view_named("nope"));
+    }
+
+    #[test]
+    fn test_sort_keeps_indices() {
+        let mut app = App::new();
+        app.servers = ["charlie", "alpha", "bravo"]
+            .iter()
+            .map(|h| Server::new(h.to_string(), format!("{}.example.com", h)))
+            .collect();
+        let ids: Vec<ServerId> = app.servers.iter().map(|s| s.id).collect();
+        app.select_host("bravo");
+
+        app.sort_servers();
+        // The list keeps its order, so indices held by in-flight work stay valid
+        let hosts: Vec<&str> = app.servers.iter().map(|s| s.host.as_str()).collect();
+        assert_eq!(hosts, vec!["charlie", "alpha", "bravo"]);
+        assert_eq!(app.sorted_indices(), vec![1, 2, 0]);
+        assert_eq!(app.display_order_servers(), vec![1, 2, 0]);
+        assert_eq!(app.selected_server().unwrap().host, "bravo");
+
+        app.sort_order = SortOrder::Favorites;
+        app.history.toggle_favorite("charlie");
+        app.sort_servers();
+        assert_eq!(app.sorted_indices(), vec![0, 1, 2]);
+        assert_eq!(app.selected_server_id(), Some(ids[2]));
+        assert_eq!(app.index_of(ids[2]), Some(2));
     }
 }
@@ -0,0 +1,396 @@
+use std::collections::HashSet;
+use std::future::{poll_fn, Future};
+use std::task::Poll;
+
+use anyhow::Result;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use tokio::sync::Semaphore;
+
+use crate::server::Server;
+use crate::ssh::config::wildcard_match;
+use crate::ssh::Backend;
+
+/// Most checks run at once on one server. Each is a session on the shared
+/// connection, where sshd allows 10 (MaxSessions), and terminals and tunnels
+/// need some too.
+const MAX_CHECKS_PER_SERVER: usize = 4;
+
+/// A custom health check from the settings, e.g. `nginx: systemctl is-active nginx`
+#[derive(Debug, Clone, Deserialize)]
+pub struct CheckDef {
+    /// Shown in the details view and as the column header (`check:<name>` in views)
+    pub name: String,
+    /// Shell command run on the server
+    pub command: String,
+    /// How the output becomes a result
+    #[serde(default)]
+    pub parse: CheckParser,
+    /// Values at or past this are a warning
+    pub warn: Option<f64>,
+    /// Values at or past this are critical. Below `warn`, lower values are worse.
+    pub critical: Option<f64>,
+    /// Host aliases to run on, wildcards allowed
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// Groups to run on, including their subgroups
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+/// How a check's output is read
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckParser {
+    /// Passes when the command exits with 0
+    #[default]
+    Exit,
+    /// The first word of the output is a number held against the thresholds
+    Number,
+    /// The output must match; the first capture group, if any, is the value
+    #[serde(deserialize_with = "deserialize_regex")]
+    Regex(Regex),
+}
+
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+/// How bad a check result is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CheckLevel {
+    Ok,
+    Warn,
+    Critical,
+}
+
+impl CheckLevel {
+    pub fn label(&self) -> &'static str {
+        match self {
+            CheckLevel::Ok => "ok",
+            CheckLevel::Warn => "warn",
+            CheckLevel::Critical => "critical",
+        }
+    }
+}
+
+/// The outcome of one check on one server
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckResult {
+    pub name: String,
+    pub level: CheckLevel,
+    /// What the list shows: the number, the captured text, or the first line
+    pub value: String,
+    /// The command's output, or why there is none
+    pub output: String,
+}
+
+impl CheckDef {
+    /// Whether the check runs on the server; checks without hosts or groups run everywhere
+    pub fn applies_to(&self, server: &Server) -> bool {
+        if self.hosts.is_empty() && self.groups.is_empty() {
+            return true;
+        }
+        self.hosts
+            .iter()
+            .any(|pattern| wildcard_match(pattern, &server.host))
+            || self.groups.iter().any(|group| server.in_group(group))
+    }
+
+    /// Judge the command's exit code and output
+    pub fn evaluate(&self, code: i32, output: &str) -> CheckResult {
+        let output = output.trim();
+        let first_line = output.lines().next().unwrap_or_default().trim();
+        let result = |level, value: &str| CheckResult {
+            name: self.name.clone(),
+            level,
+            value: value.to_string(),
+            output: output.to_string(),
+        };
+
+        let value = match self.parse {
+            CheckParser::Exit => {
+                let level = if code == 0 {
+                    CheckLevel::Ok
+                } else {
+                    CheckLevel::Critical
+                };
+                let fallback = if code == 0 {
+                    "ok".to_string()
+                } else {
+                    format!("exit {}", code)
+                };
+                return result(
+                    level,
+                    if first_line.is_empty() {
+                        &fallback
+                    } else {
+                        first_line
+                    },
+                );
+            }
+            _ if code != 0 => return result(CheckLevel::Critical, &format!("exit {}", code)),
+            CheckParser::Number => output.split_whitespace().next().unwrap_or_default(),
+            CheckParser::Regex(ref re) => match re.captures(output) {
+                Some(caps) => caps
+                    .get(1)
+                    .or_else(|| caps.get(0))
+                    .map_or("", |m| m.as_str()),
+                None => return result(CheckLevel::Critical, "no match"),
+            },
+        };
+
+        let needs_number = matches!(self.parse, CheckParser::Number) || self.has_thresholds();
+        match value.trim_end_matches('%').parse::<f64>() {
+            Ok(number) => result(self.threshold_level(number), value),
+            Err(_) if needs_number => result(CheckLevel::Critical, "?"),
+            Err(_) => result(CheckLevel::Ok, value),
+        }
+    }
+
+    fn has_thresholds(&self) -> bool {
+        self.warn.is_some() || self.critical.is_some()
+    }
+
+    fn threshold_level(&self, value: f64) -> CheckLevel {
+        // A critical threshold below the warning one means lower is worse, like free space
+        let lower_is_worse = matches!((self.warn, self.critical), (Some(w), Some(c)) if c < w);
+        let past = |limit: f64| {
+            if lower_is_worse {
+                value <= limit
+            } else {
+                value >= limit
+            }
+        };
+        if self.critical.is_some_and(past) {
+            CheckLevel::Critical
+        } else if self.warn.is_some_and(past) {
+            CheckLevel::Warn
+        } else {
+            CheckLevel::Ok
+        }
+    }
+
+    /// Names must be unique and usable as a column name
+    pub fn validate(checks: &[CheckDef]) -> Result<()> {
+        let mut names = HashSet::new();
+        for check in checks {
+            if check.name.trim().is_empty() || check.name.contains(char::is_whitespace) {
+                anyhow::bail!("Check names must be single words, got {:?}", check.name);
+            }
+            if !names.insert(check.name.as_str()) {
+                anyhow::bail!("Check {:?} is defined twice", check.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Give each server the checks that apply to it
+pub fn assign_checks(servers: &mut [Server], checks: &[CheckDef]) {
+    for server in servers.iter_mut() {
+        server.checks = checks
+            .iter()
+            .filter(|c| c.applies_to(server))
+            .cloned()
+            .collect();
+    }
+}
+
+/// Wrap a check's command so its exit code follows its output
+fn check_script(check: &CheckDef) -> String {
+    format!("( {}\n) 2>&1\necho \"===EXIT $?===\"\n", check.command)
+}
+
+/// Read a check's result back from the wrapper's output
+fn parse_check_output(check: &CheckDef, output: &str) -> CheckResult {
+    let exit = output.rsplit_once("===EXIT ").and_then(|(body, rest)| {
+        let code = rest.trim_end().strip_suffix("===")?.parse::<i32>().ok()?;
+        Some((body, code))
+    });
+    match exit {
+        Some((body, code)) => check.evaluate(code, body),
+        // The wrapper never finished, e.g. because the command was cut short
+        None => failed(check, "No result"),
+    }
+}
+
+fn failed(check: &CheckDef, error: &str) -> CheckResult {
+    CheckResult {
+        name: check.name.clone(),
+        level: CheckLevel::Critical,
+        value: "failed".to_string(),
+        output: error.to_string(),
+    }
+}
+
+/// Run the server's checks side by side, each as its own command, so one that
+/// hangs past the command timeout doesn't take the others' results with it.
+/// At most [`MAX_CHECKS_PER_SERVER`] run at once.
+pub async fn run_checks(backend: &dyn Backend, server: &Server) -> Vec<CheckResult> {
+    let permits = Semaphore::new(MAX_CHECKS_PER_SERVER);
+    let runs = server.checks.iter().map(|check| {
+        let permits = &permits;
+        async move {
+            let _permit = permits.acquire().await;
+            match backend.run_command(server, &check_script(check)).await {
+                Ok(output) => parse_check_output(check, &output),
+                Err(e) => failed(check, &format!("{:#}", e)),
+            }
+        }
+    });
+    join_all(runs.collect()).await
+}
+
+/// Wait for all the futures, polling them together on the current task
+async fn join_all<F: Future>(futures: Vec<F>) -> Vec<F::Output> {
+    let mut futures: Vec<_> = futures.into_iter().map(Box::pin).collect();
+    let mut outputs: Vec<Option<F::Output>> = futures.iter().map(|_| None).collect();
+    poll_fn(|cx| {
+        for (future, output) in futures.iter_mut().zip(outputs.iter_mut()) {
+            if output.is_none() {
+                if let Poll::Ready(result) = future.as_mut().poll(cx) {
+                    *output = Some(result);
+                }
+            }
+        }
+        if outputs.iter().all(Option::is_some) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await;
+    outputs.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checks(json: &str) -> Vec<CheckDef> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_evaluate_checks() {
+        let defs = checks(
+            r#"[
+                { "name": "nginx", "command": "systemctl is-active nginx" },
+                { "name": "queue", "command": "redis-cli llen jobs", "parse": "number",
+                  "warn": 100, "critical": 1000 },
+                { "name": "free_gb", "command": "df -BG --output=avail / | tail -1",
+                  "parse": { "regex": "(\\d+)G" }, "warn": 20, "critical": 5 },
+                { "name": "version", "command": "cat /etc/debian_version",
+                  "parse": { "regex": "^\\d+" } }
+            ]"#,
+        );
+        let level = |i: usize, code: i32, output: &str| defs[i].evaluate(code, output).level;
+
+        assert_eq!(level(0, 0, "active\n"), CheckLevel::Ok);
+        let down = defs[0].evaluate(3, "inactive\n");
+        assert_eq!(
+            (down.level, down.value.as_str()),
+            (CheckLevel::Critical, "inactive")
+        );
+
+        assert_eq!(level(1, 0, "42\n"), CheckLevel::Ok);
+        assert_eq!(level(1, 0, "100"), CheckLevel::Warn);
+        assert_eq!(level(1, 0, "5000"), CheckLevel::Critical);
+        assert_eq!(
+            level(1, 0, "Could not connect to Redis"),
+            CheckLevel::Critical
+        );
+        assert_eq!(level(1, 1, "42"), CheckLevel::Critical);
+
+        // Thresholds the other way round: less free space is worse
+        assert_eq!(defs[2].evaluate(0, " 120G\n").value, "120");
+        assert_eq!(level(2, 0, " 120G"), CheckLevel::Ok);
+        assert_eq!(level(2, 0, " 12G"), CheckLevel::Warn);
+        assert_eq!(level(2, 0, " 3G"), CheckLevel::Critical);
+
+        assert_eq!(defs[3].evaluate(0, "12.5\n").value, "12");
+        assert_eq!(level(3, 0, "bookworm/sid"), CheckLevel::Critical);
+    }
+
+    #[test]
+    fn test_check_script_output() {
+        let defs = checks(
+            r#"[ { "name": "load", "command": "cut -d' ' -f1 /proc/loadavg", "parse": "number", "warn": 4 } ]"#,
+        );
+        let load = &defs[0];
+        assert_eq!(
+            check_script(load),
+            "( cut -d' ' -f1 /proc/loadavg\n) 2>&1\necho \"===EXIT $?===\"\n"
+        );
+
+        let result = parse_check_output(load, "6.10\n===EXIT 0===\n");
+        assert_eq!((result.level, result.value.as_str()), (CheckLevel::Warn, "6.10"));
+        // Output without a trailing newline runs into the marker
+        let result = parse_check_output(load, "1.5===EXIT 0===\n");
+        assert_eq!((result.level, result.value.as_str()), (CheckLevel::Ok, "1.5"));
+        let result = parse_check_output(load, "cut: /proc/loadavg: No such file\n===EXIT 1===\n");
+        assert_eq!((result.level, result.value.as_str()), (CheckLevel::Critical, "exit 1"));
+        let result = parse_check_output(load, "6.10\n");
+        assert_eq!((result.level, result.output.as_str()), (CheckLevel::Critical, "No result"));
+    }
+
+    #[tokio::test]
+    async fn test_slow_check_keeps_other_results() {
+        use crate::ssh::ScriptedBackend;
+
+        let mut server = Server::new("web-01".to_string(), "10.0.0.1".to_string());
+        server.checks = checks(
+            r#"[
+                { "name": "nginx", "command": "systemctl is-active nginx" },
+                { "name": "slow", "command": "sleep 60" }
+            ]"#,
+        );
+        let backend = ScriptedBackend::new()
+            .reply("*", "systemctl", "active\n===EXIT 0===\n")
+            .fail("*", "sleep 60", "No answer within 10s");
+
+        let results = run_checks(&backend, &server).await;
+        assert_eq!(
+            (results[0].level, results[0].value.as_str()),
+            (CheckLevel::Ok, "active")
+        );
+        assert_eq!(
+            (results[1].level, results[1].output.as_str()),
+            (CheckLevel::Critical, "No answer within 10s")
+        );
+        assert_eq!(backend.calls().len(), 2);
+    }
+
+    #[test]
+    fn test_check_targets() {
+        let defs = checks(
+            r#"[
+                { "name": "everywhere", "command": "true" },
+                { "name": "web", "command": "true", "hosts": ["web-*"], "groups": ["db"] }
+            ]"#,
+        );
+        let mut servers = vec![
+            Server::new("web-01".to_string(), "10.0.0.1".to_string()),
+            Server::new("db-main".to_string(), "10.0.0.2".to_string()),
+            Server::new("cache".to_string(), "10.0.0.3".to_string()),
+        ];
+        servers[1].group = Some("db-main".to_string());
+        assign_checks(&mut servers, &defs);
+
+        let counts: Vec<usize> = servers.iter().map(|s| s.checks.len()).collect();
+        assert_eq!(counts, vec![2, 2, 1]);
+
+        assert!(CheckDef::validate(&defs).is_ok());
+        let twice =
+            checks(r#"[ { "name": "a", "command": "x" }, { "name": "a", "command": "y" } ]"#);
+        assert!(CheckDef::validate(&twice).is_err());
+        assert!(
+            CheckDef::validate(&checks(r#"[ { "name": "two words", "command": "x" } ]"#)).is_err()
+        );
+    }
+}
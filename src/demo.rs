@@ -0,0 +1,175 @@
+use std::time::Duration;
+
+use crate::server::{JumpHost, Server};
+use crate::ssh::ScriptedBackend;
+
+/// A demo server: host, hostname, user, latency in ms, CPU %, RAM used and total
+type DemoHost = (&'static str, &'static str, &'static str, u64, f32, u64, u64);
+
+#[rustfmt::skip]
+const DEMO_HOSTS: [DemoHost; 12] = [
+    ("prod-web-01", "10.0.1.1", "deploy", 12, 23.0, 4_200_000_000, 8_000_000_000),
+    ("prod-web-02", "10.0.1.2", "deploy", 15, 45.0, 3_800_000_000, 8_000_000_000),
+    ("prod-web-03", "10.0.1.3", "deploy", 18, 67.0, 5_100_000_000, 8_000_000_000),
+    ("prod-db-01", "10.0.2.1", "admin", 8, 12.0, 8_100_000_000, 16_000_000_000),
+    ("prod-db-02", "10.0.2.2", "admin", 9, 15.0, 7_800_000_000, 16_000_000_000),
+    ("staging-api", "staging.example.com", "developer", 45, 5.0, 2_100_000_000, 4_000_000_000),
+    ("staging-web", "staging-web.example.com", "developer", 48, 8.0, 1_800_000_000, 4_000_000_000),
+    ("dev-server", "dev.example.com", "dev", 120, 67.0, 1_200_000_000, 2_000_000_000),
+    ("ci-runner-01", "ci-01.internal", "ci", 25, 89.0, 3_500_000_000, 4_000_000_000),
+    ("ci-runner-02", "ci-02.internal", "ci", 28, 45.0, 2_800_000_000, 4_000_000_000),
+    ("monitoring", "monitor.example.com", "ops", 35, 15.0, 1_500_000_000, 2_000_000_000),
+    ("bastion", "bastion.example.com", "admin", 5, 2.0, 500_000_000, 1_000_000_000),
+];
+
+/// What nvidia-smi reports on the demo servers that have GPUs: host, GPUs
+/// and their compute processes
+const DEMO_GPUS: [(&str, &str, &str); 2] = [
+    (
+        "ci-runner-01",
+        "0, GPU-0c1d2e3f-0001, NVIDIA A100-SXM4-80GB, 98, 71234, 81920, 74, 377.10, 400.00, 0\n\
+         1, GPU-0c1d2e3f-0002, NVIDIA A100-SXM4-80GB, 91, 65002, 81920, 71, 352.44, 400.00, 0\n",
+        "GPU-0c1d2e3f-0001, 31337, 71200, ci, python train.py\n\
+         GPU-0c1d2e3f-0002, 31338, 64990, ci, python train.py\n",
+    ),
+    (
+        "ci-runner-02",
+        "0, GPU-5a6b7c8d-0001, NVIDIA A100-SXM4-80GB, 67, 40960, 81920, 63, 250.02, 400.00, 0\n\
+         1, GPU-5a6b7c8d-0002, NVIDIA A100-SXM4-80GB, 0, 4, 81920, 34, 61.30, 400.00, 0\n\
+         2, GPU-5a6b7c8d-0003, NVIDIA A100-SXM4-80GB, 3, 2048, 81920, 38, 72.85, 400.00, 0\n",
+        "GPU-5a6b7c8d-0001, 4242, 40900, alice, python finetune.py\n\
+         GPU-5a6b7c8d-0003, 5151, 2040, bob, jupyter-lab\n",
+    ),
+];
+
+/// Generate demo servers for screenshots/demos. Their health comes from
+/// [`demo_backend`] through the usual checks.
+pub fn generate_demo_servers() -> Vec<Server> {
+    DEMO_HOSTS
+        .iter()
+        .map(|(host, hostname, user, ..)| {
+            let mut server = Server::new(host.to_string(), hostname.to_string());
+            server.user = Some(user.to_string());
+            // The databases are only reachable through the bastion
+            if host.starts_with("prod-db") {
+                server.jump_chain = JumpHost::parse("bastion").into_iter().collect();
+                server.tags = ["prod", "db"].iter().map(|t| t.to_string()).collect();
+                server.owner = Some("dba-team".to_string());
+            } else if host.starts_with("prod-") {
+                server.tags.insert("prod".to_string());
+            }
+            server
+        })
+        .collect()
+}
+
+/// A scripted backend that answers health checks for the demo servers with
+/// made-up numbers. Other commands, and sessions, are refused; the refusal
+/// shows up as a status in the TUI.
+pub fn demo_backend() -> ScriptedBackend {
+    let mut backend = ScriptedBackend::new();
+    for (host, _, _, latency_ms, cpu, ram_used, ram_total) in DEMO_HOSTS {
+        let (gpus, apps) = DEMO_GPUS
+            .iter()
+            .find(|(h, ..)| *h == host)
+            .map(|(_, gpus, apps)| (*gpus, *apps))
+            .unwrap_or_default();
+        backend = backend
+            .reply(host, "echo ok", "ok\n")
+            .after(Duration::from_millis(latency_ms))
+            .reply(
+                host,
+                "===OS===",
+                &metrics_output(cpu, ram_used, ram_total, gpus, apps),
+            );
+    }
+    backend
+        .reply("*", "which mosh-server", "/usr/bin/mosh-server\n")
+        .reply(
+            "*",
+            "uptime",
+            " 10:42:01 up 42 days,  3:14,  1 user,  load average: 0.52, 0.58, 0.59\n",
+        )
+        .fail("*", "", "Demo mode: Remote commands disabled")
+        .refuse_sessions("Demo mode: SSH connections disabled")
+}
+
+/// What the metrics probe prints on a demo server, which runs Linux. The CPU
+/// counters move by 1000 ticks between samples.
+fn metrics_output(cpu: f32, ram_used: u64, ram_total: u64, gpus: &str, apps: &str) -> String {
+    let busy = (cpu * 10.0) as u64;
+    format!(
+        "===OS===\nLinux\n===CORES===\n4\n===STAT===\ncpu  0 0 0 0 0 0 0 0\n\
+         cpu  {} 0 0 {} 0 0 0 0\n===MEMINFO===\nMemTotal: {} kB\nMemAvailable: {} kB\n\
+         ===LOADAVG===\n{:.2} {:.2} {:.2} 1/200 4242\n===USERS===\nuser\n===NVIDIA===\n{}\
+         ===NVIDIA_APPS===\n{}===MOSH===\nmosh-server\n",
+        busy,
+        1000 - busy,
+        ram_total / 1024,
+        (ram_total - ram_used) / 1024,
+        cpu / 25.0,
+        cpu / 30.0,
+        cpu / 35.0,
+        gpus,
+        apps
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::{spawn_health_checks, HealthUpdate};
+    use crate::server::{HealthStatus, Issue};
+    use crate::ssh::Backend;
+    use std::sync::Arc;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_demo_runs_health_checks() {
+        let servers = generate_demo_servers();
+        let backend: Arc<dyn Backend> = Arc::new(demo_backend());
+        let (tx, mut rx) = mpsc::unbounded_channel::<HealthUpdate>();
+        let all: Vec<usize> = (0..servers.len()).collect();
+        spawn_health_checks(&backend, &servers, &all, tx);
+
+        let mut updates = Vec::new();
+        while updates.len() < servers.len() {
+            updates.push(rx.recv().await.unwrap());
+        }
+        let update_for = |host: &str| {
+            let id = servers.iter().find(|s| s.host == host).unwrap().id;
+            updates.iter().find(|u| u.server_id == id).unwrap()
+        };
+
+        let web = update_for("prod-web-01");
+        assert_eq!(web.status, HealthStatus::Healthy);
+        let metrics = web.metrics.as_ref().unwrap();
+        assert_eq!(metrics.cpu_usage, 23.0);
+        // /proc/meminfo counts in kB
+        assert!(metrics.ram_used.abs_diff(4_200_000_000) < 2048);
+        assert_eq!(metrics.mosh_server_path.as_deref(), Some("mosh-server"));
+        // Slow enough to count as degraded
+        assert!(matches!(
+            update_for("dev-server").status,
+            HealthStatus::Degraded(ref issues) if matches!(issues[..], [Issue::Slow(_)])
+        ));
+        // Checked after the bastion it depends on
+        assert_eq!(update_for("prod-db-01").status, HealthStatus::Healthy);
+
+        let gpus = &update_for("ci-runner-02").metrics.as_ref().unwrap().gpus;
+        assert_eq!(gpus.len(), 3);
+        assert_eq!(gpus[0].owners(), vec![("alice", 40900 * 1024 * 1024)]);
+        assert_eq!(gpus.iter().filter(|g| g.is_free()).count(), 2);
+    }
+
+    #[test]
+    fn test_demo_refuses_sessions() {
+        let backend = demo_backend();
+        let refusal = backend.session_refusal();
+        assert_eq!(refusal.as_deref(), Some("Demo mode: SSH connections disabled"));
+
+        // The check happens up front; nothing is launched
+        assert!(backend.calls().is_empty());
+        assert!(crate::ssh::ScriptedBackend::new().session_refusal().is_none());
+    }
+}
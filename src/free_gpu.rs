@@ -0,0 +1,162 @@
+use crate::server::{GpuInfo, GpuVendor, Server};
+
+/// Free GPUs on one server to start a job on
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpuPick {
+    /// Index into the server list
+    pub server: usize,
+    pub vendor: GpuVendor,
+    pub indices: Vec<u32>,
+}
+
+impl GpuPick {
+    /// The variable and value that limit a session to the picked GPUs, like
+    /// `CUDA_VISIBLE_DEVICES=1,3`
+    pub fn env(&self) -> (String, String) {
+        let var = self.vendor.visible_devices_var().unwrap_or_default();
+        let indices: Vec<String> = self.indices.iter().map(|i| i.to_string()).collect();
+        (var.to_string(), indices.join(","))
+    }
+}
+
+/// Free memory first, then the least busy
+fn rank(gpu: &GpuInfo) -> (u64, f32) {
+    (
+        gpu.memory_total.saturating_sub(gpu.memory_used),
+        gpu.utilization,
+    )
+}
+
+/// Pick `count` free GPUs (see [`GpuInfo::is_free`]) from one of the given
+/// servers. The server whose picked GPUs have the most free memory between
+/// them wins, then the one where they're least busy. Servers that aren't up
+/// and GPUs whose jobs can't be pointed at them are left out.
+pub fn pick_free_gpus(servers: &[Server], candidates: &[usize], count: usize) -> Option<GpuPick> {
+    let count = count.max(1);
+    let mut best: Option<(GpuPick, (u64, f32))> = None;
+    for &index in candidates {
+        let server = &servers[index];
+        let Some(ref metrics) = server.metrics else {
+            continue;
+        };
+        if !server.status.is_up() || metrics.free_gpus() < count {
+            continue;
+        }
+        // A job runs on one vendor's GPUs
+        for vendor in [GpuVendor::Nvidia, GpuVendor::Amd, GpuVendor::Intel] {
+            let mut free: Vec<&GpuInfo> = metrics
+                .gpus
+                .iter()
+                .filter(|gpu| gpu.vendor == vendor && gpu.is_free())
+                .collect();
+            if free.len() < count {
+                continue;
+            }
+            free.sort_by(|a, b| {
+                let (a, b) = (rank(a), rank(b));
+                b.0.cmp(&a.0).then(a.1.total_cmp(&b.1))
+            });
+            free.truncate(count);
+
+            let score = free.iter().fold((0, 0.0), |(memory, util), gpu| {
+                let (free_memory, utilization) = rank(gpu);
+                (memory + free_memory, util + utilization)
+            });
+            let better = match best {
+                Some((_, (memory, util))) => {
+                    score.0 > memory || (score.0 == memory && score.1 < util)
+                }
+                None => true,
+            };
+            if better {
+                let mut indices: Vec<u32> = free.iter().map(|gpu| gpu.index).collect();
+                indices.sort_unstable();
+                best = Some((
+                    GpuPick {
+                        server: index,
+                        vendor,
+                        indices,
+                    },
+                    score,
+                ));
+            }
+        }
+    }
+    best.map(|(pick, _)| pick)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{HealthStatus, SystemMetrics};
+
+    const GB: u64 = 1 << 30;
+
+    fn gpu_server(host: &str, gpus: &[(GpuVendor, f32, u64)]) -> Server {
+        let mut server = Server::new(host.to_string(), host.to_string());
+        server.status = HealthStatus::Healthy;
+        server.metrics = Some(SystemMetrics {
+            gpus: gpus
+                .iter()
+                .enumerate()
+                .map(|(index, &(vendor, utilization, used))| GpuInfo {
+                    vendor,
+                    index: index as u32,
+                    name: "GPU".to_string(),
+                    utilization,
+                    memory_used: used * GB,
+                    memory_total: 80 * GB,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        });
+        server
+    }
+
+    #[test]
+    fn test_pick_free_gpus() {
+        use GpuVendor::*;
+        let servers = vec![
+            // Busy except one
+            gpu_server(
+                "a",
+                &[(Nvidia, 95.0, 70), (Nvidia, 0.0, 1), (Nvidia, 90.0, 60)],
+            ),
+            // Two free, one emptier than the other
+            gpu_server(
+                "b",
+                &[(Nvidia, 2.0, 4), (Nvidia, 80.0, 40), (Nvidia, 0.0, 0)],
+            ),
+            // Mixed vendors can't be combined
+            gpu_server("c", &[(Nvidia, 0.0, 0), (Amd, 0.0, 0)]),
+            gpu_server("mac", &[(Apple, 0.0, 0)]),
+        ];
+        let all: Vec<usize> = (0..servers.len()).collect();
+
+        let pick = pick_free_gpus(&servers, &all, 1).unwrap();
+        // The first completely empty GPU wins
+        assert_eq!((pick.server, pick.indices.clone()), (1, vec![2]));
+        assert_eq!(
+            pick.env(),
+            ("CUDA_VISIBLE_DEVICES".to_string(), "2".to_string())
+        );
+
+        let pick = pick_free_gpus(&servers, &all, 2).unwrap();
+        assert_eq!((pick.server, pick.indices), (1, vec![0, 2]));
+        assert_eq!(pick_free_gpus(&servers, &all, 3), None);
+
+        // Only within the candidates
+        let pick = pick_free_gpus(&servers, &[0, 3], 1).unwrap();
+        assert_eq!((pick.server, pick.indices), (0, vec![1]));
+        assert_eq!(pick_free_gpus(&servers, &[3], 1), None);
+
+        let pick = pick_free_gpus(&servers, &[2], 1).unwrap();
+        assert_eq!(pick.vendor, Nvidia);
+
+        // Servers that are down don't count, whatever their last metrics said
+        let mut down = servers.clone();
+        down[1].status = HealthStatus::Unknown;
+        assert_eq!(pick_free_gpus(&down, &[0, 1], 1).unwrap().server, 0);
+    }
+}
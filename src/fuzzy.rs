@@ -0,0 +1,126 @@
+/// Points for each matched character
+const SCORE_MATCH: i64 = 16;
+/// Extra points when a match directly follows the previous one
+const BONUS_CONSECUTIVE: i64 = 8;
+/// Extra points for matching the first character of the text
+const BONUS_FIRST_CHAR: i64 = 10;
+/// Extra points for matching at the start of a word (after `-`, `.`, `_`, ...)
+const BONUS_BOUNDARY: i64 = 8;
+/// Penalty for starting a gap between two matches, and for each further skipped character
+const PENALTY_GAP_START: i64 = 3;
+const PENALTY_GAP_EXTENSION: i64 = 1;
+
+/// A successful fuzzy match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    /// Character positions in the text that matched the pattern
+    pub positions: Vec<usize>,
+}
+
+fn is_boundary(prev: char) -> bool {
+    matches!(prev, '-' | '_' | '.' | '/' | ' ' | '@' | ':')
+}
+
+/// Match `pattern` against `text` as a case-insensitive subsequence, picking the
+/// alignment with the best score: word starts and runs of consecutive characters
+/// score higher, gaps cost points. `pw1` matches `prod-web-01`.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<FuzzyMatch> {
+    let pattern: Vec<char> = pattern.chars().flat_map(|c| c.to_lowercase()).collect();
+    let text: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = text
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    let (m, n) = (pattern.len(), text.len());
+    if m == 0 || m > n {
+        return None;
+    }
+
+    let bonus: Vec<i64> = (0..n)
+        .map(|j| match j {
+            0 => BONUS_FIRST_CHAR,
+            _ if is_boundary(text[j - 1]) => BONUS_BOUNDARY,
+            _ => 0,
+        })
+        .collect();
+
+    // score[i][j]: best score with pattern[i] matched at text[j];
+    // from[i][j]: where pattern[i - 1] was matched on that best path
+    let mut score = vec![vec![None::<i64>; n]; m];
+    let mut from = vec![vec![0usize; n]; m];
+
+    for i in 0..m {
+        // Best match of pattern[i - 1] at least one character before j - 1,
+        // less the penalty for the gap up to j
+        let mut best_gap: Option<(i64, usize)> = None;
+        for j in 0..n {
+            if i > 0 && j > 1 {
+                let extended = best_gap.map(|(s, k)| (s - PENALTY_GAP_EXTENSION, k));
+                let started = score[i - 1][j - 2].map(|s| (s - PENALTY_GAP_START, j - 2));
+                best_gap = match (extended, started) {
+                    (Some(a), Some(b)) => Some(if b.0 >= a.0 { b } else { a }),
+                    (a, b) => a.or(b),
+                };
+            }
+            if lower[j] != pattern[i] {
+                continue;
+            }
+            let base = SCORE_MATCH + bonus[j];
+            if i == 0 {
+                score[i][j] = Some(base);
+                continue;
+            }
+            let consecutive = (j > 0)
+                .then(|| score[i - 1][j - 1])
+                .flatten()
+                .map(|s| (s + BONUS_CONSECUTIVE, j - 1));
+            let best = match (consecutive, best_gap) {
+                (Some(a), Some(b)) => Some(if a.0 >= b.0 { a } else { b }),
+                (a, b) => a.or(b),
+            };
+            if let Some((prev, k)) = best {
+                score[i][j] = Some(prev + base);
+                from[i][j] = k;
+            }
+        }
+    }
+
+    let (end, best) = (0..n)
+        .filter_map(|j| score[m - 1][j].map(|s| (j, s)))
+        .max_by_key(|&(j, s)| (s, std::cmp::Reverse(j)))?;
+
+    let mut positions = vec![end; m];
+    for i in (1..m).rev() {
+        positions[i - 1] = from[i][positions[i]];
+    }
+    Some(FuzzyMatch {
+        score: best,
+        positions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_match() {
+        let m = fuzzy_match("pw1", "prod-web-01").unwrap();
+        assert_eq!(m.positions, vec![0, 5, 10]);
+
+        assert!(fuzzy_match("PROD", "prod-web-01").is_some());
+        assert!(fuzzy_match("pwx", "prod-web-01").is_none());
+        assert!(fuzzy_match("", "prod").is_none());
+
+        // Word starts and runs beat scattered matches
+        let web = fuzzy_match("web", "prod-web-01").unwrap();
+        assert_eq!(web.positions, vec![5, 6, 7]);
+        let scattered = fuzzy_match("web", "wide-enabled-box").unwrap();
+        assert!(web.score > scattered.score);
+
+        // The best alignment wins over the first one found
+        let m = fuzzy_match("db", "dev-db").unwrap();
+        assert_eq!(m.positions, vec![4, 5]);
+    }
+}
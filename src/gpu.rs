@@ -0,0 +1,504 @@
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use crate::server::{GpuInfo, GpuProcess, GpuVendor};
+
+/// Each vendor's tool, in its own section. The AMD and Intel tools print JSON.
+/// NVIDIA's compute processes get their owner from /proc, or `ps` elsewhere.
+pub const GPU_PROBE: &str = r#"
+echo "===NVIDIA==="
+nvidia-smi --query-gpu=index,uuid,name,utilization.gpu,memory.used,memory.total,temperature.gpu,power.draw,power.limit,ecc.errors.uncorrected.volatile.total --format=csv,noheader,nounits 2>/dev/null
+echo "===NVIDIA_APPS==="
+nvidia-smi --query-compute-apps=gpu_uuid,pid,used_memory,process_name --format=csv,noheader,nounits 2>/dev/null |
+while IFS=, read -r uuid pid memory name; do
+    echo "$uuid,$pid,$memory,$(stat -c %U /proc/$pid 2>/dev/null || ps -o user= -p $pid 2>/dev/null),$name"
+done
+echo "===ROCM==="
+if command -v rocm-smi >/dev/null 2>&1; then
+    rocm-smi --showproductname --showuse --showmeminfo vram --showtemp --showpower --showmaxpower --json 2>/dev/null
+elif command -v amd-smi >/dev/null 2>&1; then
+    amd-smi static --asic --limit --json 2>/dev/null
+    amd-smi metric --usage --mem-usage --temperature --power --ecc --json 2>/dev/null
+fi
+echo "===XPU==="
+if command -v xpu-smi >/dev/null 2>&1; then
+    for id in $(xpu-smi discovery -j 2>/dev/null | grep -o '"device_id": *[0-9]*' | grep -o '[0-9]*$'); do
+        xpu-smi discovery -d "$id" -j 2>/dev/null
+        xpu-smi stats -d "$id" -j 2>/dev/null
+    done
+fi
+"#;
+
+/// Apple Silicon GPUs report their load to the IOAccelerator registry entry,
+/// which unlike `powermetrics` doesn't need root
+pub const APPLE_GPU_PROBE: &str = r#"
+echo "===IOREG==="
+ioreg -r -d 1 -w 0 -c IOAccelerator
+"#;
+
+const MIB: u64 = 1024 * 1024;
+
+/// GPUs from nvidia-smi's CSV: index, uuid, name, util%, mem_used, mem_total
+/// (MiB), temperature, power draw and limit, ECC errors. Readings a card
+/// doesn't support come out as `[N/A]`. The compute apps, `uuid, pid,
+/// mem_used, user, name`, are matched to their GPU by UUID.
+pub fn parse_nvidia(lines: &[&str], apps: &[&str]) -> Vec<GpuInfo> {
+    let mut uuids = Vec::new();
+    let mut gpus: Vec<GpuInfo> = lines
+        .iter()
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split(',').map(|s| s.trim()).collect();
+            if parts.len() < 10 {
+                return None;
+            }
+            uuids.push(parts[1]);
+            Some(GpuInfo {
+                vendor: GpuVendor::Nvidia,
+                index: parts[0].parse().unwrap_or(0),
+                name: parts[2].to_string(),
+                utilization: parts[3].parse().unwrap_or(0.0),
+                memory_used: parts[4].parse::<u64>().unwrap_or(0) * MIB,
+                memory_total: parts[5].parse::<u64>().unwrap_or(0) * MIB,
+                temperature: parts[6].parse().ok(),
+                power_draw: parts[7].parse().ok(),
+                power_limit: parts[8].parse().ok(),
+                ecc_errors: parts[9].parse().ok(),
+                processes: Vec::new(),
+            })
+        })
+        .collect();
+
+    for line in apps {
+        let parts: Vec<&str> = line.splitn(5, ',').map(|s| s.trim()).collect();
+        let [uuid, pid, memory, user, name] = parts[..] else {
+            continue;
+        };
+        let (Some(gpu), Ok(pid)) = (uuids.iter().position(|u| *u == uuid), pid.parse()) else {
+            continue;
+        };
+        gpus[gpu].processes.push(GpuProcess {
+            pid,
+            user: (!user.is_empty()).then(|| user.to_string()),
+            name: name.to_string(),
+            // Not reported under MIG or on Windows guests
+            memory_used: memory.parse::<u64>().unwrap_or(0) * MIB,
+        });
+    }
+    gpus
+}
+
+/// GPUs from `rocm-smi --json`, or from `amd-smi static` and `amd-smi metric`
+pub fn parse_rocm(text: &str) -> Vec<GpuInfo> {
+    let mut gpus = Vec::new();
+    let mut amd_smi: BTreeMap<u64, GpuInfo> = BTreeMap::new();
+    for value in json_values(text) {
+        match value {
+            Value::Object(_) => gpus.extend(rocm_smi_cards(&value)),
+            Value::Array(entries) => {
+                for entry in &entries {
+                    let Some(index) = entry.get("gpu").and_then(Value::as_u64) else {
+                        continue;
+                    };
+                    let gpu = amd_smi.entry(index).or_insert_with(|| GpuInfo {
+                        vendor: GpuVendor::Amd,
+                        index: index as u32,
+                        name: "AMD GPU".to_string(),
+                        ..Default::default()
+                    });
+                    merge_amd_smi(gpu, entry);
+                }
+            }
+            _ => {}
+        }
+    }
+    gpus.extend(amd_smi.into_values());
+    gpus
+}
+
+/// rocm-smi's `{"card0": {"Card Series": ..., "GPU use (%)": "12", ...}}`. Key
+/// names vary between ROCm releases, so they're matched loosely.
+fn rocm_smi_cards(value: &Value) -> Vec<GpuInfo> {
+    let Some(object) = value.as_object() else {
+        return Vec::new();
+    };
+    let mut cards: Vec<(u32, &Value)> = object
+        .iter()
+        .filter_map(|(key, card)| Some((key.strip_prefix("card")?.parse().ok()?, card)))
+        .collect();
+    cards.sort_by_key(|(index, _)| *index);
+
+    cards
+        .into_iter()
+        .enumerate()
+        .map(|(index, (_, card))| {
+            let field = |name: &str| {
+                card.as_object()?
+                    .iter()
+                    .find_map(|(key, value)| key.eq_ignore_ascii_case(name).then_some(value))
+            };
+            // The first of these keys with a number; sensors a card lacks say `N/A`
+            let reading = |names: &[&str]| names.iter().find_map(|name| number(field(name)?));
+            // The model is often just a PCI ID like `0x740f`
+            let name = ["Card Series", "Device Name", "Card Model"]
+                .iter()
+                .filter_map(|key| field(key)?.as_str())
+                .find(|name| !name.is_empty() && !name.starts_with("0x"))
+                .unwrap_or("AMD GPU");
+            GpuInfo {
+                vendor: GpuVendor::Amd,
+                index: index as u32,
+                name: name.to_string(),
+                utilization: field("GPU use (%)").and_then(number).unwrap_or(0.0) as f32,
+                memory_used: field("VRAM Total Used Memory (B)")
+                    .and_then(number)
+                    .unwrap_or(0.0) as u64,
+                memory_total: field("VRAM Total Memory (B)")
+                    .and_then(number)
+                    .unwrap_or(0.0) as u64,
+                temperature: reading(&[
+                    "Temperature (Sensor edge) (C)",
+                    "Temperature (Sensor junction) (C)",
+                ])
+                .map(|t| t as f32),
+                power_draw: reading(&[
+                    "Average Graphics Package Power (W)",
+                    "Current Socket Graphics Package Power (W)",
+                ])
+                .map(|p| p as f32),
+                power_limit: reading(&["Max Graphics Package Power (W)"]).map(|p| p as f32),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// Fill in a GPU from one amd-smi entry; `static` has the name and power
+/// limit, `metric` the readings
+fn merge_amd_smi(gpu: &mut GpuInfo, entry: &Value) {
+    let reading = |pointers: &[&str]| {
+        pointers
+            .iter()
+            .find_map(|pointer| number(entry.pointer(pointer)?))
+    };
+    if let Some(name) = entry.pointer("/asic/market_name").and_then(Value::as_str) {
+        gpu.name = name.to_string();
+    }
+    if let Some(util) = entry.pointer("/usage/gfx_activity").and_then(number) {
+        gpu.utilization = util as f32;
+    }
+    if let Some(total) = entry.pointer("/mem_usage/total_vram").and_then(bytes) {
+        gpu.memory_total = total;
+    }
+    if let Some(used) = entry.pointer("/mem_usage/used_vram").and_then(bytes) {
+        gpu.memory_used = used;
+    }
+    // MI300s have no edge sensor
+    if let Some(temperature) = reading(&["/temperature/edge", "/temperature/hotspot"]) {
+        gpu.temperature = Some(temperature as f32);
+    }
+    if let Some(power) = reading(&["/power/socket_power", "/power/average_socket_power"]) {
+        gpu.power_draw = Some(power as f32);
+    }
+    if let Some(limit) = reading(&["/limit/max_power"]) {
+        gpu.power_limit = Some(limit as f32);
+    }
+    if let Some(errors) = reading(&["/ecc/total_uncorrectable_count"]) {
+        gpu.ecc_errors = Some(errors as u64);
+    }
+}
+
+/// GPUs from `xpu-smi discovery -d N -j` and `xpu-smi stats -d N -j`, matched
+/// up by device ID
+pub fn parse_xpu(text: &str) -> Vec<GpuInfo> {
+    let mut gpus: BTreeMap<u64, GpuInfo> = BTreeMap::new();
+    for value in json_values(text) {
+        let Some(id) = value.get("device_id").and_then(Value::as_u64) else {
+            continue;
+        };
+        let gpu = gpus.entry(id).or_insert_with(|| GpuInfo {
+            vendor: GpuVendor::Intel,
+            index: id as u32,
+            name: "Intel GPU".to_string(),
+            ..Default::default()
+        });
+        if let Some(name) = value.get("device_name").and_then(Value::as_str) {
+            gpu.name = name.to_string();
+        }
+        if let Some(size) = value.get("memory_physical_size_byte").and_then(number) {
+            gpu.memory_total = size as u64;
+        }
+        for metric in value
+            .get("device_level")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let reading = metric
+                .get("value")
+                .or_else(|| metric.get("avg"))
+                .and_then(number);
+            match (metric.get("metrics_type").and_then(Value::as_str), reading) {
+                (Some("XPUM_STATS_GPU_UTILIZATION"), Some(util)) => gpu.utilization = util as f32,
+                (Some("XPUM_STATS_MEMORY_USED"), Some(mib)) => {
+                    gpu.memory_used = (mib * MIB as f64) as u64
+                }
+                (Some("XPUM_STATS_GPU_CORE_TEMPERATURE"), Some(celsius)) => {
+                    gpu.temperature = Some(celsius as f32)
+                }
+                (Some("XPUM_STATS_POWER"), Some(watts)) => gpu.power_draw = Some(watts as f32),
+                _ => {}
+            }
+        }
+    }
+    gpus.into_values().collect()
+}
+
+/// Apple Silicon GPUs from `ioreg -c IOAccelerator`. They share the system's
+/// memory, so that is their total.
+pub fn parse_ioreg(lines: &[&str], system_memory: u64) -> Vec<GpuInfo> {
+    let mut gpus = Vec::new();
+    // Each `+-o` line starts an accelerator's properties
+    for entry in lines.split(|line| line.starts_with("+-o")) {
+        let property = |name: &str| {
+            entry.iter().find_map(|line| {
+                let (key, value) = line.split_once(" = ")?;
+                (key.trim().trim_matches('"') == name).then(|| value.trim())
+            })
+        };
+        let Some(model) = property("model").map(|m| m.trim_matches('"')) else {
+            continue;
+        };
+        if !model.starts_with("Apple") {
+            continue;
+        }
+        let statistics = property("PerformanceStatistics").unwrap_or_default();
+        gpus.push(GpuInfo {
+            vendor: GpuVendor::Apple,
+            index: gpus.len() as u32,
+            name: model.to_string(),
+            utilization: ioreg_statistic(statistics, "Device Utilization %").unwrap_or(0) as f32,
+            memory_used: ioreg_statistic(statistics, "In use system memory").unwrap_or(0),
+            memory_total: system_memory,
+            ..Default::default()
+        });
+    }
+    gpus
+}
+
+/// A number from an ioreg dictionary like `{"Device Utilization %"=3,...}`
+fn ioreg_statistic(statistics: &str, name: &str) -> Option<u64> {
+    let key = format!("\"{}\"=", name);
+    let rest = &statistics[statistics.find(&key)? + key.len()..];
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
+/// Every JSON value in the text, skipping anything the tool printed before it
+fn json_values(text: &str) -> Vec<Value> {
+    let Some(start) = text.find(['{', '[']) else {
+        return Vec::new();
+    };
+    serde_json::Deserializer::from_str(&text[start..])
+        .into_iter::<Value>()
+        .map_while(Result::ok)
+        .collect()
+}
+
+/// A number that may be quoted, or wrapped as `{"value": 12, "unit": "%"}`
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        Value::Object(o) => o.get("value").and_then(number),
+        _ => None,
+    }
+}
+
+/// A size in bytes from amd-smi, which gives `{"value": 65520, "unit": "MB"}`
+fn bytes(value: &Value) -> Option<u64> {
+    let amount = number(value)?;
+    let unit = value.get("unit").and_then(Value::as_str).unwrap_or("MB");
+    let scale = match unit {
+        "B" => 1,
+        "KB" => 1024,
+        "GB" => 1024 * MIB,
+        _ => MIB,
+    };
+    Some((amount * scale as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nvidia() {
+        let gpus = parse_nvidia(
+            &[
+                "0, GPU-5c9a6f4e-0d1b-2c3d-8e9f-a0b1c2d3e4f5, NVIDIA A100-SXM4-80GB, 35, 20480, 81920, 54, 212.45, 400.00, 0",
+                "1, GPU-7e1f2a3b-4c5d-6e7f-8091-a2b3c4d5e6f7, NVIDIA A100-SXM4-80GB, 0, 3, 81920, 31, 61.20, 400.00, 0",
+                "2, GPU-9a8b7c6d-5e4f-3a2b-1c0d-e9f8a7b6c5d4, NVIDIA GeForce RTX 4090, 97, 22000, 24564, 78, 431.87, 450.00, [N/A]",
+            ],
+            &[
+                "GPU-5c9a6f4e-0d1b-2c3d-8e9f-a0b1c2d3e4f5, 48213, 16384, alice, python train.py",
+                "GPU-5c9a6f4e-0d1b-2c3d-8e9f-a0b1c2d3e4f5, 48390, 4090, , /usr/bin/python3",
+                "GPU-9a8b7c6d-5e4f-3a2b-1c0d-e9f8a7b6c5d4, 1022, 21990, bob, ./render, final",
+                "GPU-00000000-0000-0000-0000-000000000000, 77, 100, carol, gone",
+            ],
+        );
+        assert_eq!(gpus.len(), 3);
+        assert_eq!(gpus[0].vendor, GpuVendor::Nvidia);
+        assert_eq!(gpus[0].utilization, 35.0);
+        assert_eq!(gpus[0].memory_used, 20480 * MIB);
+        assert_eq!(gpus[0].temperature, Some(54.0));
+        assert_eq!(gpus[0].power_draw, Some(212.45));
+        assert_eq!(gpus[0].power_limit, Some(400.0));
+        assert_eq!(gpus[0].ecc_errors, Some(0));
+        assert!(gpus[1].is_free());
+        assert!(gpus[1].processes.is_empty());
+        assert_eq!(gpus[2].index, 2);
+        // Consumer cards have no ECC
+        assert_eq!(gpus[2].ecc_errors, None);
+
+        // Processes land on their GPU, whoever owns them
+        assert_eq!(gpus[0].processes.len(), 2);
+        assert_eq!(gpus[0].processes[0].user.as_deref(), Some("alice"));
+        assert_eq!(gpus[0].processes[0].memory_used, 16384 * MIB);
+        assert_eq!(gpus[0].processes[1].user, None);
+        assert_eq!(
+            gpus[2].processes,
+            vec![GpuProcess {
+                pid: 1022,
+                user: Some("bob".to_string()),
+                name: "./render, final".to_string(),
+                memory_used: 21990 * MIB,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_rocm_smi() {
+        // rocm-smi 6.x, with its usual warning line first
+        let output = r#"WARNING: Unlocked monitor_devices lock; it should have been locked.
+{"card0": {"GPU use (%)": "87", "VRAM Total Memory (B)": "68702699520", "VRAM Total Used Memory (B)": "52613349376", "Card Series": "AMD Instinct MI210", "Card Model": "0x740f", "Temperature (Sensor edge) (C)": "61.0", "Temperature (Sensor junction) (C)": "70.0", "Average Graphics Package Power (W)": "261.0", "Max Graphics Package Power (W)": "300.0", "Card Vendor": "Advanced Micro Devices, Inc. [AMD/ATI]", "Card SKU": "D67301"}, "card10": {"GPU use (%)": "0", "VRAM Total Memory (B)": "68702699520", "VRAM Total Used Memory (B)": "11452416", "Card Series": "0x740f", "Temperature (Sensor edge) (C)": "N/A", "Temperature (Sensor junction) (C)": "38.0"}, "card2": {"GPU use (%)": "0", "VRAM Total Memory (B)": "68702699520", "VRAM Total Used Memory (B)": "11452416", "Card series": "AMD Instinct MI210"}}"#;
+        let gpus = parse_rocm(output);
+        assert_eq!(gpus.len(), 3);
+        assert!(gpus.iter().all(|g| g.vendor == GpuVendor::Amd));
+        assert_eq!(gpus[0].name, "AMD Instinct MI210");
+        assert_eq!(gpus[0].utilization, 87.0);
+        assert_eq!(gpus[0].memory_used, 52613349376);
+        assert_eq!(gpus[0].memory_total, 68702699520);
+        assert_eq!(gpus[0].temperature, Some(61.0));
+        assert_eq!(gpus[0].power_draw, Some(261.0));
+        assert_eq!(gpus[0].power_limit, Some(300.0));
+        // Cards in numeric order; a bare PCI ID is no name
+        assert_eq!(gpus[1].name, "AMD Instinct MI210");
+        assert_eq!(gpus[2].name, "AMD GPU");
+        assert_eq!(gpus[2].index, 2);
+        assert_eq!(gpus[2].temperature, Some(38.0));
+        assert_eq!(gpus[2].power_draw, None);
+        assert!(gpus[2].is_free());
+    }
+
+    #[test]
+    fn test_parse_amd_smi() {
+        let output = r#"[
+            {"gpu": 0, "asic": {"market_name": "AMD Instinct MI300X", "vendor_id": "0x1002", "device_id": "0x74a1"},
+             "limit": {"max_power": {"value": 750, "unit": "W"}, "min_power": {"value": 0, "unit": "W"}}},
+            {"gpu": 1, "asic": {"market_name": "AMD Instinct MI300X", "vendor_id": "0x1002", "device_id": "0x74a1"},
+             "limit": {"max_power": {"value": 750, "unit": "W"}, "min_power": {"value": 0, "unit": "W"}}}
+        ]
+        [
+            {"gpu": 0, "usage": {"gfx_activity": {"value": 100, "unit": "%"}, "umc_activity": {"value": 42, "unit": "%"}},
+             "mem_usage": {"total_vram": {"value": 196592, "unit": "MB"}, "used_vram": {"value": 180224, "unit": "MB"}, "free_vram": {"value": 16368, "unit": "MB"}},
+             "power": {"socket_power": {"value": 702, "unit": "W"}, "gfx_voltage": "N/A"},
+             "temperature": {"edge": "N/A", "hotspot": {"value": 81, "unit": "C"}, "mem": {"value": 60, "unit": "C"}},
+             "ecc": {"total_correctable_count": 12, "total_uncorrectable_count": 1}},
+            {"gpu": 1, "usage": {"gfx_activity": {"value": 0, "unit": "%"}, "umc_activity": {"value": 0, "unit": "%"}},
+             "mem_usage": {"total_vram": {"value": 196592, "unit": "MB"}, "used_vram": {"value": 283, "unit": "MB"}, "free_vram": {"value": 196309, "unit": "MB"}}}
+        ]"#;
+        let gpus = parse_rocm(output);
+        assert_eq!(gpus.len(), 2);
+        assert_eq!(gpus[0].name, "AMD Instinct MI300X");
+        assert_eq!(gpus[0].utilization, 100.0);
+        assert_eq!(gpus[0].memory_total, 196592 * MIB);
+        assert_eq!(gpus[0].temperature, Some(81.0));
+        assert_eq!(gpus[0].power_draw, Some(702.0));
+        assert_eq!(gpus[0].power_limit, Some(750.0));
+        assert_eq!(gpus[0].ecc_errors, Some(1));
+        assert_eq!(gpus[1].index, 1);
+        assert_eq!(gpus[1].memory_used, 283 * MIB);
+        assert_eq!(gpus[1].temperature, None);
+        assert!(gpus[1].is_free());
+    }
+
+    #[test]
+    fn test_parse_xpu() {
+        let output = r#"{
+    "device_id": 0,
+    "device_name": "Intel(R) Data Center GPU Max 1550",
+    "device_type": "GPU",
+    "memory_physical_size_byte": "137438953472",
+    "vendor_name": "Intel(R) Corporation"
+}
+{
+    "device_id": 0,
+    "device_level": [
+        { "metrics_type": "XPUM_STATS_GPU_UTILIZATION", "value": 56.5 },
+        { "metrics_type": "XPUM_STATS_POWER", "value": 312.4 },
+        { "metrics_type": "XPUM_STATS_GPU_CORE_TEMPERATURE", "value": 48 },
+        { "metrics_type": "XPUM_STATS_MEMORY_USED", "value": 20480.25 },
+        { "metrics_type": "XPUM_STATS_MEMORY_UTILIZATION", "value": 15.6 }
+    ],
+    "tile_level": []
+}
+{
+    "device_id": 1,
+    "device_name": "Intel(R) Data Center GPU Flex 170",
+    "memory_physical_size_byte": "17179869184"
+}"#;
+        let gpus = parse_xpu(output);
+        assert_eq!(gpus.len(), 2);
+        assert_eq!(gpus[0].vendor, GpuVendor::Intel);
+        assert_eq!(gpus[0].name, "Intel(R) Data Center GPU Max 1550");
+        assert_eq!(gpus[0].utilization, 56.5);
+        assert_eq!(gpus[0].memory_used, 20480 * MIB + MIB / 4);
+        assert_eq!(gpus[0].memory_total, 137438953472);
+        assert_eq!(gpus[0].temperature, Some(48.0));
+        assert_eq!(gpus[0].power_draw, Some(312.4));
+        // No stats for the second one yet
+        assert_eq!(gpus[1].utilization, 0.0);
+        assert_eq!(gpus[1].memory_total, 17179869184);
+    }
+
+    #[test]
+    fn test_parse_ioreg() {
+        let output = r#"+-o AGXAcceleratorG14X  <class AGXAcceleratorG14X, id 0x1000003f2, registered, matched, active, busy 0 (0 ms), retain 89>
+{
+"IOClass" = "AGXAcceleratorG14X"
+"gpu-core-count" = 38
+"model" = "Apple M2 Max"
+"PerformanceStatistics" = {"In use system memory (driver)"=0,"Alloc system memory"=2218835968,"Tiler Utilization %"=2,"recoveryCount"=0,"Renderer Utilization %"=2,"Device Utilization %"=23,"SplitSceneCount"=0,"In use system memory"=537755648}
+"IOMatchCategory" = "IOAccelerator"
+}"#;
+        let lines: Vec<&str> = output.lines().collect();
+        let gpus = parse_ioreg(&lines, 34359738368);
+        assert_eq!(gpus.len(), 1);
+        assert_eq!(gpus[0].vendor, GpuVendor::Apple);
+        assert_eq!(gpus[0].name, "Apple M2 Max");
+        assert_eq!(gpus[0].utilization, 23.0);
+        // Not the "(driver)" counter that comes first
+        assert_eq!(gpus[0].memory_used, 537755648);
+        assert_eq!(gpus[0].memory_total, 34359738368);
+
+        // Intel Macs' accelerators don't name a model
+        assert!(parse_ioreg(
+            &["+-o IntelAccelerator", "\"IOClass\" = \"IntelAccelerator\""],
+            0
+        )
+        .is_empty());
+    }
+}
@@ -1,242 +1,376 @@
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use tokio::sync::{mpsc, Semaphore};
+use tokio::sync::{mpsc, watch, Semaphore};
 
-use crate::server::{GpuInfo, HealthStatus, Server, SystemMetrics};
-use crate::ssh::connection::run_remote_command;
+use crate::checks::{run_checks, CheckLevel, CheckResult};
+use crate::metrics::{parse_probe_output, probe_script};
+use crate::server::{
+    ConnectError, HealthStatus, Issue, Server, ServerId, SystemMetrics,
+};
 use crate::ssh::mosh::is_mosh_installed;
+use crate::ssh::Backend;
 
 /// Maximum concurrent health check connections
 const MAX_CONCURRENT_CHECKS: usize = 5;
 
+/// Longest wait before retrying an unreachable server
+const MAX_REFRESH_BACKOFF: Duration = Duration::from_secs(15 * 60);
+
 /// Message sent from health check tasks
 #[derive(Debug)]
 pub struct HealthUpdate {
-    pub server_idx: usize,
+    pub server_id: ServerId,
     pub latency: Option<Duration>,
     pub status: HealthStatus,
     pub metrics: Option<SystemMetrics>,
+    pub checks: Vec<CheckResult>,
 }
 
-/// Check latency to a server using SSH
-pub async fn check_latency(server: &Server) -> Option<Duration> {
+/// Check latency to a server using SSH. With a kept-open connection this is the
+/// round trip over it; otherwise it includes the handshake.
+pub async fn check_latency(backend: &dyn Backend, server: &Server) -> Result<Duration, ConnectError> {
+    // Open the connection first so the timing leaves out the handshake
+    if backend.keeps_connections(server) && !backend.is_connected(server).await {
+        backend
+            .run_command(server, "true")
+            .await
+            .map_err(|e| connect_error(e, server))?;
+    }
+
     let start = Instant::now();
 
     // Try to run a simple command to measure round-trip time
-    let result = run_remote_command(server, "echo ok").await;
-
-    if result.is_ok() {
-        Some(start.elapsed())
-    } else {
-        None
-    }
+    backend
+        .run_command(server, "echo ok")
+        .await
+        .map_err(|e| connect_error(e, server))?;
+    Ok(start.elapsed())
 }
 
-/// Fetch system metrics from a server
-pub async fn fetch_metrics(server: &Server) -> Result<SystemMetrics> {
-    // Combined command to fetch all metrics at once
-    let base_script = r#"
-echo "===CORES==="
-nproc 2>/dev/null || sysctl -n hw.ncpu 2>/dev/null || echo "0"
-
-echo "===CPU==="
-top -bn1 | head -5 | grep 'Cpu' | awk '{print $2}' | tr -d '%us,' 2>/dev/null || \
-    top -l1 | grep 'CPU usage' | awk '{print $3}' | tr -d '%' 2>/dev/null || \
-    echo "0"
-
-echo "===MEM==="
-free -b 2>/dev/null | awk '/^Mem:/ {print $2, $3}' || \
-    vm_stat 2>/dev/null | awk '/Pages (free|active|inactive|wired)/ {sum += $NF} END {print sum * 4096}' || \
-    echo "0 0"
-
-echo "===LOAD==="
-uptime | awk -F'load average:' '{print $2}' | tr -d ' ' 2>/dev/null || echo "0,0,0"
-
-echo "===USERS==="
-who | awk '{print $1}' | sort -u 2>/dev/null || echo ""
-
-echo "===GPU==="
-nvidia-smi --query-gpu=name,utilization.gpu,memory.used,memory.total --format=csv,noheader,nounits 2>/dev/null || \
-    rocm-smi --showuse --showmemuse 2>/dev/null | grep -E 'GPU|Memory' || \
-    echo ""
-"#;
-
-    // Only check for mosh-server if mosh is installed locally
-    let script = if is_mosh_installed() {
-        format!(
-            r#"{}
-echo "===MOSH==="
-# Check PATH first, then common conda/brew locations
-if command -v mosh-server >/dev/null 2>&1; then
-    command -v mosh-server
-elif [ -x "$HOME/miniconda3/bin/mosh-server" ]; then
-    echo "$HOME/miniconda3/bin/mosh-server"
-elif [ -x "$HOME/anaconda3/bin/mosh-server" ]; then
-    echo "$HOME/anaconda3/bin/mosh-server"
-elif [ -x "$HOME/miniforge3/bin/mosh-server" ]; then
-    echo "$HOME/miniforge3/bin/mosh-server"
-elif [ -x "$HOME/mambaforge/bin/mosh-server" ]; then
-    echo "$HOME/mambaforge/bin/mosh-server"
-elif [ -x "$HOME/.local/bin/mosh-server" ]; then
-    echo "$HOME/.local/bin/mosh-server"
-elif [ -x "/opt/homebrew/bin/mosh-server" ]; then
-    echo "/opt/homebrew/bin/mosh-server"
-elif [ -x "/usr/local/bin/mosh-server" ]; then
-    echo "/usr/local/bin/mosh-server"
-else
-    echo ""
-fi
-"#,
-            base_script
-        )
-    } else {
-        base_script.to_string()
-    };
-
-    let output = run_remote_command(server, &script).await?;
-    parse_metrics_output(&output)
+/// Why a connection failed. Backends classify what they can; other errors are
+/// classified from their text.
+fn connect_error(error: anyhow::Error, server: &Server) -> ConnectError {
+    error
+        .downcast::<ConnectError>()
+        .unwrap_or_else(|e| ConnectError::classify(&format!("{:#}", e), server.is_proxied()))
 }
 
-/// Parse the output from our metrics script
-fn parse_metrics_output(output: &str) -> Result<SystemMetrics> {
-    let mut metrics = SystemMetrics::default();
-    let mut section = "";
+/// Fetch system metrics from a server with the probe for its OS
+pub async fn fetch_metrics(backend: &dyn Backend, server: &Server) -> Result<SystemMetrics> {
+    // Only look for mosh-server if mosh is installed locally
+    let output = backend
+        .run_command(server, &probe_script(is_mosh_installed()))
+        .await?;
+    parse_probe_output(&output)
+}
 
-    for line in output.lines() {
-        let line = line.trim();
+/// Latency threshold in milliseconds (>100ms = degraded)
+const LATENCY_GOOD_MS: u64 = 100;
 
-        if line.starts_with("===") {
-            section = line.trim_matches('=');
-            continue;
+/// Spawn a health check task.
+///
+/// The task first waits for the results of the `bastions` it depends on; if any of
+/// them is unreachable the server is reported as blocked without connecting. Its own
+/// status is published on `done` so servers behind it can do the same.
+fn spawn_health_check_task(
+    backend: Arc<dyn Backend>,
+    server: Server,
+    tx: mpsc::UnboundedSender<HealthUpdate>,
+    semaphore: Option<Arc<Semaphore>>,
+    bastions: Vec<(String, watch::Receiver<Option<HealthStatus>>)>,
+    done: Option<watch::Sender<Option<HealthStatus>>>,
+) {
+    tokio::spawn(async move {
+        // Wait for bastions before taking a permit so dependents can't starve them
+        let mut blocked_by = None;
+        for (name, mut rx) in bastions {
+            let bastion_status = rx.wait_for(|s| s.is_some()).await.ok().and_then(|s| s.clone());
+            if bastion_status.is_some_and(|s| s.is_down()) {
+                blocked_by = Some(name);
+                break;
+            }
         }
 
-        match section {
-            "CORES" => {
-                if let Ok(cores) = line.parse::<u32>() {
-                    metrics.cpu_cores = cores;
-                }
-            }
-            "CPU" => {
-                if let Ok(cpu) = line.parse::<f32>() {
-                    metrics.cpu_usage = cpu;
-                }
-            }
-            "MEM" => {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() >= 2 {
-                    metrics.ram_total = parts[0].parse().unwrap_or(0);
-                    metrics.ram_used = parts[1].parse().unwrap_or(0);
-                }
-            }
-            "LOAD" => {
-                let parts: Vec<&str> = line.split(',').collect();
-                if parts.len() >= 3 {
-                    metrics.load_average = (
-                        parts[0].trim().parse().unwrap_or(0.0),
-                        parts[1].trim().parse().unwrap_or(0.0),
-                        parts[2].trim().parse().unwrap_or(0.0),
-                    );
-                }
-            }
-            "USERS" => {
-                if !line.is_empty() {
-                    metrics.logged_in_users.push(line.to_string());
-                }
-            }
-            "GPU" => {
-                if !line.is_empty() && !line.starts_with("rocm") {
-                    // Parse NVIDIA format: name, util%, mem_used, mem_total
-                    let parts: Vec<&str> = line.split(',').map(|s| s.trim()).collect();
-                    if parts.len() >= 4 {
-                        metrics.gpus.push(GpuInfo {
-                            name: parts[0].to_string(),
-                            utilization: parts[1].parse().unwrap_or(0.0),
-                            memory_used: parts[2].parse::<u64>().unwrap_or(0) * 1024 * 1024,
-                            memory_total: parts[3].parse::<u64>().unwrap_or(0) * 1024 * 1024,
-                        });
-                    }
-                }
+        let update = match blocked_by {
+            Some(bastion) => HealthUpdate {
+                server_id: server.id,
+                latency: None,
+                status: HealthStatus::BlockedByBastion(bastion),
+                metrics: None,
+                checks: Vec::new(),
+            },
+            None => {
+                // Acquire semaphore permit if provided (limits concurrent SSH connections)
+                let _permit = if let Some(ref sem) = semaphore {
+                    Some(sem.acquire().await)
+                } else {
+                    None
+                };
+                check_server(&*backend, &server).await
+                // Permit is dropped here, allowing another task to proceed
             }
-            "MOSH" => {
-                if !line.is_empty() {
-                    metrics.mosh_server_path = Some(line.to_string());
-                }
+        };
+
+        if let Some(done) = done {
+            let _ = done.send(Some(update.status.clone()));
+        }
+        let _ = tx.send(update);
+    });
+}
+
+/// Run the latency and metrics checks for one server
+async fn check_server(backend: &dyn Backend, server: &Server) -> HealthUpdate {
+    // Check latency first
+    let latency = match check_latency(backend, server).await {
+        Ok(latency) => latency,
+        Err(error) => {
+            return HealthUpdate {
+                server_id: server.id,
+                latency: None,
+                status: HealthStatus::Unreachable(error),
+                metrics: None,
+                checks: Vec::new(),
             }
-            _ => {}
         }
+    };
+
+    // Reachable, so fetch metrics and run custom checks; anything amiss makes it degraded
+    let mut issues = Vec::new();
+    if latency > Duration::from_millis(LATENCY_GOOD_MS) {
+        issues.push(Issue::Slow(latency));
     }
+    let metrics = match fetch_metrics(backend, server).await {
+        Ok(metrics) => Some(metrics),
+        Err(e) => {
+            issues.push(Issue::NoMetrics(format!("{:#}", e)));
+            None
+        }
+    };
+    let checks = run_checks(backend, server).await;
+    issues.extend(
+        checks
+            .iter()
+            .filter(|c| c.level != CheckLevel::Ok)
+            .map(|c| Issue::Check {
+                name: c.name.clone(),
+                level: c.level,
+            }),
+    );
 
-    Ok(metrics)
+    HealthUpdate {
+        server_id: server.id,
+        latency: Some(latency),
+        status: if issues.is_empty() {
+            HealthStatus::Healthy
+        } else {
+            HealthStatus::Degraded(issues)
+        },
+        metrics,
+        checks,
+    }
 }
 
-/// Latency threshold in milliseconds (>100ms = degraded)
-const LATENCY_GOOD_MS: u64 = 100;
+/// For each server, the indices of the servers in its jump chain that are also
+/// in the list. Dependencies that would form a cycle are dropped.
+fn bastion_dependencies(servers: &[Server]) -> Vec<Vec<usize>> {
+    let index: HashMap<&str, usize> = servers
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s.host.as_str(), i))
+        .collect();
 
-/// Spawn a health check task for a single server (no concurrency limit)
-pub fn spawn_health_check(
-    server_idx: usize,
-    server: Server,
-    tx: mpsc::UnboundedSender<HealthUpdate>,
-) {
-    spawn_health_check_with_semaphore(server_idx, server, tx, None);
+    let direct: Vec<Vec<usize>> = servers
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let mut deps: Vec<usize> = s
+                .jump_chain
+                .iter()
+                .filter_map(|hop| index.get(hop.host.as_str()).copied())
+                .filter(|&j| j != i)
+                .collect();
+            deps.dedup();
+            deps
+        })
+        .collect();
+
+    // Returns true if `target` is reachable from `from` through dependency edges
+    let reaches = |from: usize, target: usize| {
+        let mut stack = vec![from];
+        let mut seen = HashSet::new();
+        while let Some(n) = stack.pop() {
+            if n == target {
+                return true;
+            }
+            if seen.insert(n) {
+                stack.extend(&direct[n]);
+            }
+        }
+        false
+    };
+
+    direct
+        .iter()
+        .enumerate()
+        .map(|(i, deps)| deps.iter().copied().filter(|&j| !reaches(j, i)).collect())
+        .collect()
 }
 
-/// Spawn a health check task with optional semaphore for concurrency limiting
-fn spawn_health_check_with_semaphore(
-    server_idx: usize,
-    server: Server,
+/// Spawn health checks for some of the servers with concurrency limiting.
+/// Servers behind a bastion wait for the bastion's result and are marked
+/// blocked instead of timing out one by one when it is unreachable. A bastion
+/// that isn't checked in this batch is judged by its last known status.
+pub fn spawn_health_checks(
+    backend: &Arc<dyn Backend>,
+    servers: &[Server],
+    indices: &[usize],
     tx: mpsc::UnboundedSender<HealthUpdate>,
-    semaphore: Option<Arc<Semaphore>>,
 ) {
-    tokio::spawn(async move {
-        // Acquire semaphore permit if provided (limits concurrent SSH connections)
-        let _permit = if let Some(ref sem) = semaphore {
-            Some(sem.acquire().await)
-        } else {
-            None
-        };
+    // Use a semaphore to limit concurrent SSH connections
+    let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_CHECKS));
 
-        // Check latency first
-        let latency = check_latency(&server).await;
-        let status = match latency {
-            Some(d) => {
-                let ms = d.as_millis() as u64;
-                if ms <= LATENCY_GOOD_MS {
-                    HealthStatus::Healthy
-                } else {
-                    HealthStatus::Degraded // Reachable but slow
-                }
+    let dependencies = bastion_dependencies(servers);
+    let mut senders = HashMap::new();
+    let mut receivers = HashMap::new();
+    for &idx in indices {
+        let (done, rx) = watch::channel(None);
+        senders.insert(idx, done);
+        receivers.insert(idx, rx);
+    }
+
+    for &idx in indices {
+        let server = &servers[idx];
+        let known_down = dependencies[idx].iter().find(|&&j| {
+            !receivers.contains_key(&j) && servers[j].status.is_down()
+        });
+        if let Some(&j) = known_down {
+            let status = HealthStatus::BlockedByBastion(servers[j].host.clone());
+            if let Some(done) = senders.remove(&idx) {
+                let _ = done.send(Some(status.clone()));
             }
-            None => HealthStatus::Unreachable,
-        };
+            let _ = tx.send(HealthUpdate {
+                server_id: server.id,
+                latency: None,
+                status,
+                metrics: None,
+                checks: Vec::new(),
+            });
+            continue;
+        }
 
-        // If reachable, fetch metrics
-        let metrics = if status != HealthStatus::Unreachable {
-            fetch_metrics(&server).await.ok()
-        } else {
-            None
-        };
+        let bastions = dependencies[idx]
+            .iter()
+            .filter_map(|&j| Some((servers[j].host.clone(), receivers.get(&j)?.clone())))
+            .collect();
+        spawn_health_check_task(
+            backend.clone(),
+            server.clone(),
+            tx.clone(),
+            Some(semaphore.clone()),
+            bastions,
+            senders.remove(&idx),
+        );
+    }
+}
 
-        let _ = tx.send(HealthUpdate {
-            server_idx,
-            latency,
-            status,
-            metrics,
-        });
+/// When a server was last checked and when it's due again
+#[derive(Debug, Clone)]
+struct RefreshState {
+    in_flight: bool,
+    /// Unreachable results in a row
+    failures: u32,
+    next_due: Instant,
+}
 
-        // Permit is dropped here, allowing another task to proceed
-    });
+/// Decides which servers to recheck. Each server is checked again `interval`
+/// after its last result; unreachable servers back off exponentially, and a
+/// server with a check in flight is never checked twice.
+#[derive(Debug, Clone)]
+pub struct RefreshScheduler {
+    /// Time between checks; `None` disables periodic checks
+    interval: Option<Duration>,
+    servers: HashMap<ServerId, RefreshState>,
 }
 
-/// Spawn health checks for all servers with concurrency limiting
-pub fn spawn_all_health_checks(servers: &[Server], tx: mpsc::UnboundedSender<HealthUpdate>) {
-    // Use a semaphore to limit concurrent SSH connections
-    let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_CHECKS));
+impl RefreshScheduler {
+    pub fn new(interval: Option<Duration>) -> Self {
+        Self {
+            interval,
+            servers: HashMap::new(),
+        }
+    }
 
-    for (idx, server) in servers.iter().enumerate() {
-        spawn_health_check_with_semaphore(idx, server.clone(), tx.clone(), Some(semaphore.clone()));
+    /// Mark servers as being checked. Returns the ones that weren't already in flight.
+    pub fn claim(&mut self, servers: &[Server], indices: &[usize], now: Instant) -> Vec<usize> {
+        indices
+            .iter()
+            .copied()
+            .filter(|&idx| {
+                let state = self
+                    .servers
+                    .entry(servers[idx].id)
+                    .or_insert(RefreshState {
+                        in_flight: false,
+                        failures: 0,
+                        next_due: now,
+                    });
+                !std::mem::replace(&mut state.in_flight, true)
+            })
+            .collect()
+    }
+
+    /// Claim every server whose next periodic check is due
+    pub fn claim_due(&mut self, servers: &[Server], now: Instant) -> Vec<usize> {
+        if self.interval.is_none() {
+            return Vec::new();
+        }
+        let due: Vec<usize> = servers
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| self.servers.get(&s.id).is_none_or(|state| state.next_due <= now))
+            .map(|(idx, _)| idx)
+            .collect();
+        self.claim(servers, &due, now)
+    }
+
+    /// Record a check result and schedule the next check
+    pub fn finish(&mut self, id: ServerId, status: &HealthStatus, now: Instant) {
+        let Some(state) = self.servers.get_mut(&id) else {
+            return;
+        };
+        state.in_flight = false;
+        if matches!(status, HealthStatus::Unreachable(_)) {
+            state.failures += 1;
+        } else {
+            state.failures = 0;
+        }
+        if let Some(interval) = self.interval {
+            let backoff = interval
+                .saturating_mul(1 << state.failures.min(16))
+                .min(MAX_REFRESH_BACKOFF.max(interval));
+            state.next_due = now + backoff;
+        }
+    }
+
+    /// Forget servers that are no longer in the list
+    pub fn retain(&mut self, servers: &[Server]) {
+        let ids: HashSet<ServerId> = servers.iter().map(|s| s.id).collect();
+        self.servers.retain(|id, _| ids.contains(id));
+    }
+}
+
+/// Format the age of a server's data, e.g. `45s`, `3m`, `2h`
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 60 * 60 {
+        format!("{}m", secs / 60)
+    } else {
+        format!("{}h", secs / (60 * 60))
     }
 }
 
@@ -259,3 +393,166 @@ pub fn format_bytes(bytes: u64) -> String {
         format!("{}B", bytes)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{FailureKind, JumpHost};
+    use crate::ssh::ScriptedBackend;
+
+    fn server_via(host: &str, chain: &[&str]) -> Server {
+        let mut server = Server::new(host.to_string(), host.to_string());
+        server.jump_chain = chain.iter().filter_map(|h| JumpHost::parse(h)).collect();
+        server
+    }
+
+    #[test]
+    fn test_bastion_dependencies() {
+        let servers = vec![
+            server_via("bastion", &[]),
+            server_via("inner", &["bastion"]),
+            server_via("deep", &["bastion", "inner"]),
+            server_via("outside", &["unknown-gw"]),
+        ];
+        let deps = bastion_dependencies(&servers);
+        assert!(deps[0].is_empty());
+        assert_eq!(deps[1], vec![0]);
+        assert_eq!(deps[2], vec![0, 1]);
+        // Bastions that aren't in the list are not waited on
+        assert!(deps[3].is_empty());
+    }
+
+    #[test]
+    fn test_refresh_scheduler() {
+        let interval = Duration::from_secs(60);
+        let mut scheduler = RefreshScheduler::new(Some(interval));
+        let servers = vec![server_via("up", &[]), server_via("down", &[])];
+        let start = Instant::now();
+
+        // Everything is due at first, and nothing is claimed twice
+        assert_eq!(scheduler.claim_due(&servers, start), vec![0, 1]);
+        assert!(scheduler.claim_due(&servers, start).is_empty());
+        assert!(scheduler.claim(&servers, &[0, 1], start).is_empty());
+
+        let (up, down) = (servers[0].id, servers[1].id);
+        let unreachable = HealthStatus::Unreachable(ConnectError::new(FailureKind::Timeout, ""));
+        scheduler.finish(up, &HealthStatus::Healthy, start);
+        scheduler.finish(down, &unreachable, start);
+        assert!(scheduler.claim_due(&servers, start + interval / 2).is_empty());
+        assert_eq!(scheduler.claim_due(&servers, start + interval), vec![0]);
+
+        // Each failure in a row doubles the wait, up to the cap
+        let mut now = start;
+        for backoff in [2, 4, 8] {
+            assert!(scheduler.claim_due(&servers, now + interval * backoff - Duration::from_secs(1)).is_empty());
+            now += interval * backoff;
+            assert_eq!(scheduler.claim_due(&servers, now), vec![1]);
+            scheduler.finish(down, &unreachable, now);
+        }
+        for _ in 0..10 {
+            now += MAX_REFRESH_BACKOFF;
+            assert_eq!(scheduler.claim_due(&servers, now), vec![1]);
+            scheduler.finish(down, &unreachable, now);
+        }
+
+        // A manual refresh can claim a server that isn't due
+        scheduler.finish(up, &HealthStatus::Healthy, now);
+        assert_eq!(scheduler.claim(&servers, &[0], now), vec![0]);
+    }
+
+    #[test]
+    fn test_bastion_dependency_cycle_is_broken() {
+        let servers = vec![server_via("a", &["b"]), server_via("b", &["a"])];
+        let deps = bastion_dependencies(&servers);
+        // Neither may wait on the other forever
+        assert!(deps[0].is_empty() || deps[1].is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_check_server_scripted() {
+        let output = "===OS===\nLinux\n===CORES===\n8\n===MEMINFO===\n\
+                      MemTotal: 16000 kB\nMemAvailable: 12000 kB\n===USERS===\nalice\nbob\n";
+        let backend = ScriptedBackend::new()
+            .reply("up", "echo ok", "ok\n")
+            .reply("up", "===OS===", output)
+            .reply("bare", "echo ok", "ok\n")
+            .fail("bare", "===OS===", "sh: 1: uname: not found")
+            .fail("*", "", "ssh: connect to host down port 22: Connection timed out");
+
+        let up = check_server(&backend, &server_via("up", &[])).await;
+        assert_eq!(up.status, HealthStatus::Healthy);
+        let metrics = up.metrics.unwrap();
+        assert_eq!(metrics.cpu_cores, 8);
+        assert_eq!(metrics.ram_used, 4000 * 1024);
+        assert_eq!(metrics.logged_in_users, vec!["alice", "bob"]);
+
+        // Reachable without metrics is degraded, with the reason kept
+        let bare = check_server(&backend, &server_via("bare", &[])).await;
+        assert_eq!(
+            bare.status,
+            HealthStatus::Degraded(vec![Issue::NoMetrics("sh: 1: uname: not found".to_string())])
+        );
+
+        let down = check_server(&backend, &server_via("down", &[])).await;
+        let HealthStatus::Unreachable(ref error) = down.status else {
+            panic!("expected unreachable, got {:?}", down.status);
+        };
+        assert_eq!(error.kind, FailureKind::Timeout);
+        assert!(error.message.contains("port 22"));
+        assert!(down.metrics.is_none());
+        // No metrics are fetched from an unreachable server
+        assert_eq!(backend.calls().iter().filter(|c| c.starts_with("run down")).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_custom_checks_feed_status() {
+        let mut server = server_via("web", &[]);
+        server.checks = serde_json::from_str(
+            r#"[
+                { "name": "nginx", "command": "systemctl is-active nginx" },
+                { "name": "queue", "command": "redis-cli llen jobs", "parse": "number", "warn": 100 }
+            ]"#,
+        )
+        .unwrap();
+        let backend = ScriptedBackend::new()
+            .reply("web", "echo ok", "ok\n")
+            .reply("web", "===OS===", "===OS===\nLinux\n")
+            .reply("web", "systemctl", "active\n===EXIT 0===\n")
+            .reply("web", "redis-cli", "250\n===EXIT 0===\n");
+
+        let update = check_server(&backend, &server).await;
+        assert_eq!(update.checks.len(), 2);
+        assert_eq!(update.checks[0].value, "active");
+        assert_eq!(
+            update.status,
+            HealthStatus::Degraded(vec![Issue::Check {
+                name: "queue".to_string(),
+                level: CheckLevel::Warn,
+            }])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blocked_servers_are_not_contacted() {
+        let scripted = Arc::new(ScriptedBackend::new().fail("*", "", "Connection refused"));
+        let backend: Arc<dyn Backend> = scripted.clone();
+        let servers = vec![server_via("bastion", &[]), server_via("inner", &["bastion"])];
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        spawn_health_checks(&backend, &servers, &[0, 1], tx);
+
+        let mut statuses = HashMap::new();
+        for _ in 0..2 {
+            let update = rx.recv().await.unwrap();
+            statuses.insert(update.server_id, update.status);
+        }
+        assert!(matches!(
+            statuses[&servers[0].id],
+            HealthStatus::Unreachable(ConnectError { kind: FailureKind::Refused, .. })
+        ));
+        assert_eq!(
+            statuses[&servers[1].id],
+            HealthStatus::BlockedByBastion("bastion".to_string())
+        );
+        assert!(scripted.calls().iter().all(|c| !c.starts_with("run inner")));
+    }
+}
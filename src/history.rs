@@ -24,6 +24,9 @@ pub struct History {
     /// Last used sort order
     #[serde(default)]
     pub sort_order: String,
+    /// Group paths collapsed in the server list and group view
+    #[serde(default)]
+    pub collapsed_groups: HashSet<String>,
 }
 
 impl History {
@@ -72,7 +75,6 @@ impl History {
     }
 
     /// Get connection count for a server
-    #[allow(dead_code)]
     pub fn connect_count(&self, host: &str) -> u32 {
         self.entries.get(host).map(|e| e.connect_count).unwrap_or(0)
     }
@@ -91,6 +93,20 @@ impl History {
         }
     }
 
+    /// Check if a group is collapsed
+    pub fn is_collapsed(&self, path: &str) -> bool {
+        self.collapsed_groups.contains(path)
+    }
+
+    /// Collapse or expand a group
+    pub fn set_collapsed(&mut self, path: &str, collapsed: bool) {
+        if collapsed {
+            self.collapsed_groups.insert(path.to_string());
+        } else {
+            self.collapsed_groups.remove(path);
+        }
+    }
+
     /// Set sort order
     pub fn set_sort_order(&mut self, order: &str) {
         self.sort_order = order.to_string();
@@ -1,15 +1,27 @@
 mod app;
+mod checks;
+mod demo;
+mod free_gpu;
+mod fuzzy;
+mod gpu;
 mod health;
 mod history;
+mod metrics;
+mod query;
 mod server;
+mod settings;
 mod ssh;
+mod tags;
 mod tunnel;
 mod tui;
+mod views;
 
 use std::fs;
 use std::io::{self, Write};
+use std::path::PathBuf;
 use std::process::{Command, Stdio};
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
 use arboard::Clipboard;
@@ -22,11 +34,23 @@ use ratatui::prelude::*;
 use tokio::sync::mpsc;
 
 use app::{App, SortOrder, ViewMode};
-use health::{spawn_all_health_checks, spawn_health_check, HealthUpdate};
+use checks::assign_checks;
+use demo::{demo_backend, generate_demo_servers};
+use free_gpu::pick_free_gpus;
+use health::{spawn_health_checks, HealthUpdate, RefreshScheduler};
 use history::History;
-use server::generate_demo_servers;
-use ssh::{build_groups, group_servers, launch_mosh_session, launch_ssh_session, parse_ssh_config, run_remote_command};
+use server::{Server, ServerId};
+use settings::{apply_rule_tags, BackendKind, Settings};
+use ssh::{
+    apply_effective_configs, apply_host_edit, build_groups, config_sources, group_servers, parse_ssh_config,
+    resolve_effective_config, Backend, ConfigSnapshot, ConfigSource, EffectiveConfig, ParseOptions,
+};
+use tags::TagFile;
 use tui::{draw, handle_key_event, poll_event, HandleResult};
+use views::ViewFile;
+
+/// How often config files are checked for changes (in seconds)
+const CONFIG_POLL_INTERVAL_SECS: u64 = 2;
 
 fn print_help() {
     println!("ggoto - A blazingly fast TUI for managing SSH connections");
@@ -35,16 +59,154 @@ fn print_help() {
     println!("    ggoto [OPTIONS]");
     println!();
     println!("OPTIONS:");
-    println!("    --demo     Run with fake demo data (for screenshots/demos)");
-    println!("    --help     Print this help message");
+    println!("    --demo         Run with fake demo data (for screenshots/demos)");
+    println!("    --match-exec   Evaluate `Match exec` blocks in the SSH config (runs their commands)");
+    println!("    --resolve-with-ssh");
+    println!("                   Use `ssh -G` as the source of truth for each host's settings");
+    println!("    --config <PATH>");
+    println!("                   Also read hosts from this SSH config (repeatable)");
+    println!("    --system-config");
+    println!("                   Also apply /etc/ssh/ssh_config to hosts from the default config");
+    println!("    --view <NAME>  Start in a saved view");
+    println!("    --free-gpu [FILTER]");
+    println!("                   Check the servers matching a filter (e.g. tag:gpu) and connect to the");
+    println!("                   one with the freest GPU, limited to it with CUDA_VISIBLE_DEVICES");
+    println!("    --gpus <N>     Number of free GPUs --free-gpu looks for (default 1)");
+    println!("    --print        Print the server and GPUs --free-gpu picked instead of connecting");
+    println!("    --help         Print this help message");
+    println!();
+    println!("ENVIRONMENT:");
+    println!("    GGOTO_SSH_CONFIG");
+    println!("                   SSH config file(s) to read instead of ~/.ssh/config, separated by ':'");
     println!();
 }
 
+/// Collect the values of a repeatable `--flag <value>` / `--flag=value` option
+fn collect_option_values(args: &[String], flag: &str) -> Result<Vec<String>> {
+    let prefix = format!("{}=", flag);
+    let mut values = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == flag {
+            match iter.next() {
+                Some(value) if !value.starts_with("--") => values.push(value.clone()),
+                _ => anyhow::bail!("{} needs a value", flag),
+            }
+        } else if let Some(value) = arg.strip_prefix(&prefix) {
+            values.push(value.to_string());
+        }
+    }
+    Ok(values)
+}
+
+/// The value of a `--flag [value]` option whose value can be left out; empty
+/// when it is
+fn optional_option_value(args: &[String], flag: &str) -> Option<String> {
+    let position = args.iter().position(|a| a == flag);
+    match position {
+        Some(i) => Some(
+            args.get(i + 1)
+                .filter(|value| !value.starts_with("--"))
+                .cloned()
+                .unwrap_or_default(),
+        ),
+        None => {
+            let prefix = format!("{}=", flag);
+            args.iter()
+                .rev()
+                .find_map(|a| a.strip_prefix(&prefix))
+                .map(|value| value.to_string())
+        }
+    }
+}
+
+/// Check the servers a filter picks out and connect to the one with the freest
+/// GPUs, limited to them, or print the pick for scripts. The connection is
+/// recorded in `history` unless there is none (demo mode).
+async fn connect_to_free_gpus(
+    app: &mut App,
+    backend: &Arc<dyn Backend>,
+    history: Option<&mut History>,
+    filter: &str,
+    count: usize,
+    print_only: bool,
+) -> Result<()> {
+    // Added to a view's filter; metric predicates can only be judged after the check
+    let filter = format!("{} {}", app.filter_text, filter).trim().to_string();
+    app.filter_text = query::without_live_predicates(&filter);
+    let candidates = app.filtered_servers();
+    if candidates.is_empty() {
+        anyhow::bail!("No servers match '{}'", filter);
+    }
+
+    eprintln!("Checking {} servers for free GPUs...", candidates.len());
+    let (tx, mut rx) = mpsc::unbounded_channel::<HealthUpdate>();
+    spawn_health_checks(backend, &app.servers, &candidates, tx);
+    for _ in 0..candidates.len() {
+        match rx.recv().await {
+            Some(update) => app.apply_health_update(update),
+            None => break,
+        }
+    }
+
+    app.filter_text = filter;
+    let scope = app.filtered_servers();
+    let Some(pick) = pick_free_gpus(&app.servers, &scope, count) else {
+        match count {
+            1 => anyhow::bail!("No server has a free GPU right now"),
+            _ => anyhow::bail!("No server has {} free GPUs right now", count),
+        }
+    };
+    let server = &app.servers[pick.server];
+    let (name, value) = pick.env();
+    if print_only {
+        println!("{} {}={}", server.host, name, value);
+        return Ok(());
+    }
+
+    if let Some(history) = history {
+        history.record_connection(&server.host);
+        if let Err(e) = history.save() {
+            eprintln!("Warning: Failed to save history: {}", e);
+        }
+    }
+    println!("Using {}={}", name, value);
+    backend.launch_session(server, app.use_mosh, &[(name, value)])
+}
+
+/// Group and tag freshly parsed servers: grouping rules, then the name pattern,
+/// then tags from rules and the tag file
+fn organize_servers(servers: &mut [Server], settings: &Settings, tag_file: &TagFile) {
+    group_servers(servers, &settings.groups);
+    apply_rule_tags(servers, &settings.groups);
+    assign_checks(servers, &settings.checks);
+    tag_file.apply(servers);
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Parse command line arguments
     let args: Vec<String> = std::env::args().collect();
     let demo_mode = args.iter().any(|a| a == "--demo");
+    let parse_options = ParseOptions {
+        allow_match_exec: args.iter().any(|a| a == "--match-exec"),
+    };
+    let resolve_with_ssh = args.iter().any(|a| a == "--resolve-with-ssh");
+    let extra_configs: Vec<PathBuf> = collect_option_values(&args, "--config")?
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+    let include_system_config = args.iter().any(|a| a == "--system-config");
+    let start_view = collect_option_values(&args, "--view")?.pop();
+    let free_gpu_filter = optional_option_value(&args, "--free-gpu");
+    let gpu_count = match collect_option_values(&args, "--gpus")?.pop() {
+        Some(count) => match count.parse::<usize>() {
+            Ok(count) if count > 0 => count,
+            _ => anyhow::bail!("--gpus takes a number of GPUs, not '{}'", count),
+        },
+        None => 1,
+    };
+    let print_only = args.iter().any(|a| a == "--print");
 
     if args.iter().any(|a| a == "--help" || a == "-h") {
         print_help();
@@ -65,11 +227,48 @@ async fn main() -> Result<()> {
     // Restore sort order from history
     app.sort_order = SortOrder::from_str(history.get_sort_order());
 
+    // Load ggoto settings and tags (skip in demo mode)
+    let settings = if demo_mode {
+        Settings::default()
+    } else {
+        match Settings::load() {
+            Ok(settings) => settings,
+            Err(e) => {
+                eprintln!("Failed to load settings: {:#}", e);
+                return Ok(());
+            }
+        }
+    };
+    if !demo_mode {
+        // Don't fall back to an empty file: the next tag edit would overwrite it
+        app.tag_file = match TagFile::load() {
+            Ok(tag_file) => tag_file,
+            Err(e) => {
+                eprintln!("Failed to load tags: {:#}", e);
+                return Ok(());
+            }
+        };
+        app.refresh_interval = settings.refresh_interval();
+    }
+    app.add_check_columns(&settings.checks);
+
+    // Load saved views; they only change the display, so demo mode uses them too
+    app.view_file = match ViewFile::load() {
+        Ok(view_file) => view_file,
+        Err(e) => {
+            eprintln!("Failed to load saved views: {:#}", e);
+            return Ok(());
+        }
+    };
+
+    // Config sources and the files they were read from, watched for live reload
+    let mut config_watch: Option<(Vec<ConfigSource>, ConfigSnapshot)> = None;
+
     // Load servers
     if demo_mode {
-        // Use demo servers with fake data
+        // Use demo servers; their health comes from the demo backend
         let mut servers = generate_demo_servers();
-        group_servers(&mut servers);
+        organize_servers(&mut servers, &settings, &app.tag_file);
         let groups = build_groups(&servers);
 
         app.servers = servers;
@@ -77,27 +276,42 @@ async fn main() -> Result<()> {
         app.sort_servers();
     } else {
         // Parse SSH config
-        match parse_ssh_config() {
-            Ok(mut servers) => {
+        let sources = config_sources(&extra_configs, include_system_config)?;
+        let parsed = parse_ssh_config(&sources, &parse_options);
+        let source_list = sources
+            .iter()
+            .map(|s| s.describe())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        match parsed {
+            Ok((mut servers, snapshot)) => {
                 if servers.is_empty() {
-                    eprintln!("No SSH hosts found in ~/.ssh/config");
+                    eprintln!("No SSH hosts found in {}", source_list);
                     eprintln!("Add some hosts to your SSH config and try again.");
                     eprintln!();
                     eprintln!("Tip: Run with --demo to see a demo with fake servers.");
                     return Ok(());
                 }
 
-                // Group servers by name pattern
-                group_servers(&mut servers);
+                // Let OpenSSH resolve each host if requested
+                if resolve_with_ssh {
+                    apply_effective_configs(&mut servers).await;
+                }
+
+                // Group servers by the user's rules, then by name pattern
+                organize_servers(&mut servers, &settings, &app.tag_file);
                 let groups = build_groups(&servers);
 
                 app.servers = servers;
                 app.groups = groups;
                 app.sort_servers();
+                app.default_config_file = sources.first().and_then(|s| s.files.first().cloned());
+                config_watch = Some((sources, snapshot));
             }
             Err(e) => {
                 eprintln!("Failed to parse SSH config: {}", e);
-                eprintln!("Make sure ~/.ssh/config exists and is readable.");
+                eprintln!("Make sure {} exists and is readable.", source_list);
                 eprintln!();
                 eprintln!("Tip: Run with --demo to see a demo with fake servers.");
                 return Ok(());
@@ -105,6 +319,46 @@ async fn main() -> Result<()> {
         }
     }
 
+    // Start in the view given on the command line
+    if let Some(name) = start_view {
+        if !app.apply_view_named(&name) {
+            let names: Vec<&str> = app.view_file.views.iter().map(|v| v.name.as_str()).collect();
+            eprintln!("Unknown view: {}", name);
+            if names.is_empty() {
+                eprintln!("No views saved yet. Press W in ggoto to save one.");
+            } else {
+                eprintln!("Saved views: {}", names.join(", "));
+            }
+            return Ok(());
+        }
+    }
+
+    // Share one SSH connection per server; the guard closes them when ggoto exits
+    let _mux_guard = if demo_mode || !settings.multiplex() {
+        None
+    } else {
+        match ssh::mux::init() {
+            Ok(guard) => Some(guard),
+            Err(e) => {
+                eprintln!("Warning: Connection sharing is off: {}", e);
+                None
+            }
+        }
+    };
+
+    // Everything done on servers goes through the backend; demo mode answers from a script
+    let ssh_backend: Arc<dyn Backend> = if demo_mode {
+        Arc::new(demo_backend())
+    } else {
+        Arc::new(ssh::SshBackend::new(settings.backend == BackendKind::Native))
+    };
+
+    // Pick free GPUs without starting the TUI
+    if let Some(filter) = free_gpu_filter {
+        let history = (!demo_mode).then_some(&mut history);
+        return connect_to_free_gpus(&mut app, &ssh_backend, history, &filter, gpu_count, print_only).await;
+    }
+
     // Setup terminal
     enable_raw_mode().context("Failed to enable raw mode")?;
     let mut stdout = io::stdout();
@@ -118,16 +372,30 @@ async fn main() -> Result<()> {
     // Create channel for command output
     let (cmd_tx, mut cmd_rx) = mpsc::unbounded_channel::<Result<String>>();
 
-    // Start initial health checks (skip in demo mode - already have fake data)
-    if demo_mode {
-        app.is_fetching = false;
-    } else {
-        app.is_fetching = true;
-        spawn_all_health_checks(&app.servers, health_tx.clone());
-    }
+    // Create channel for `ssh -G` results (server, resolved config)
+    let (effective_tx, mut effective_rx) =
+        mpsc::unbounded_channel::<(ServerId, Result<EffectiveConfig>)>();
+
+    // Create channel for reloaded server lists
+    let (reload_tx, mut reload_rx) =
+        mpsc::unbounded_channel::<Result<(Vec<server::Server>, ConfigSnapshot)>>();
+    let mut is_reloading_config = false;
+
+    // Create channel for host editor results
+    let (edit_tx, mut edit_rx) = mpsc::unbounded_channel::<Result<String>>();
+    let mut last_config_poll = Instant::now();
+
+    // Schedules periodic rechecks and keeps checks of a server from overlapping
+    let mut scheduler = RefreshScheduler::new(app.refresh_interval);
+
+    // Start initial health checks
+    app.is_fetching = true;
+    let all: Vec<usize> = (0..app.servers.len()).collect();
+    let batch = scheduler.claim(&app.servers, &all, Instant::now());
+    spawn_health_checks(&ssh_backend, &app.servers, &batch, health_tx.clone());
 
     // Track if we need to launch SSH after cleanup
-    let mut ssh_target: Option<usize> = None;
+    let mut ssh_target: Option<(ServerId, Vec<(String, String)>)> = None;
 
     // Main event loop
     let result: Result<()> = loop {
@@ -139,13 +407,9 @@ async fn main() -> Result<()> {
 
         // Process any pending health updates (non-blocking)
         while let Ok(update) = health_rx.try_recv() {
-            if update.server_idx < app.servers.len() {
-                let server = &mut app.servers[update.server_idx];
-                server.latency = update.latency;
-                server.status = update.status;
-                server.metrics = update.metrics;
-                server.last_check = Some(std::time::Instant::now());
-            }
+            scheduler.finish(update.server_id, &update.status, Instant::now());
+
+            app.apply_health_update(update);
 
             // Check if all servers have been checked
             let all_checked = app.servers.iter().all(|s| s.last_check.is_some());
@@ -154,6 +418,12 @@ async fn main() -> Result<()> {
             }
         }
 
+        // Recheck servers whose data is due for a refresh
+        let due = scheduler.claim_due(&app.servers, Instant::now());
+        if !due.is_empty() {
+            spawn_health_checks(&ssh_backend, &app.servers, &due, health_tx.clone());
+        }
+
         // Process any pending command output (non-blocking)
         while let Ok(result) = cmd_rx.try_recv() {
             app.is_running_command = false;
@@ -167,6 +437,103 @@ async fn main() -> Result<()> {
             }
         }
 
+        // Re-parse the SSH config when one of its files changed
+        if let Some((ref sources, ref mut snapshot)) = config_watch {
+            if !is_reloading_config
+                && last_config_poll.elapsed() >= Duration::from_secs(CONFIG_POLL_INTERVAL_SECS)
+            {
+                last_config_poll = Instant::now();
+                if snapshot.is_stale() {
+                    // Don't retry a broken config until it changes again
+                    *snapshot = snapshot.refreshed();
+                    is_reloading_config = true;
+
+                    let sources = sources.clone();
+                    let parse_options = parse_options.clone();
+                    let tx = reload_tx.clone();
+                    tokio::spawn(async move {
+                        let parsed = tokio::task::spawn_blocking(move || {
+                            parse_ssh_config(&sources, &parse_options)
+                        })
+                        .await;
+                        let result = match parsed {
+                            Ok(Ok((mut servers, snapshot))) => {
+                                if resolve_with_ssh {
+                                    apply_effective_configs(&mut servers).await;
+                                }
+                                Ok((servers, snapshot))
+                            }
+                            Ok(Err(e)) => Err(e),
+                            Err(e) => Err(e.into()),
+                        };
+                        let _ = tx.send(result);
+                    });
+                }
+            }
+        }
+
+        // Merge reloaded server lists (non-blocking)
+        while let Ok(result) = reload_rx.try_recv() {
+            is_reloading_config = false;
+            match result {
+                Ok((mut servers, new_snapshot)) => {
+                    if let Some((_, ref mut snapshot)) = config_watch {
+                        *snapshot = new_snapshot;
+                    }
+                    organize_servers(&mut servers, &settings, &app.tag_file);
+                    let changes = app.merge_servers(servers);
+
+                    for &id in &changes.removed {
+                        if let Err(e) = app.tunnel_manager.close_server_tunnels(id) {
+                            app.set_status(format!("Failed to close tunnel: {}", e));
+                        }
+                    }
+
+                    // New hosts and hosts that now connect differently need a fresh check
+                    scheduler.retain(&app.servers);
+                    let recheck: Vec<usize> = changes
+                        .added
+                        .iter()
+                        .chain(&changes.changed)
+                        .filter_map(|&id| app.index_of(id))
+                        .collect();
+                    let batch = scheduler.claim(&app.servers, &recheck, Instant::now());
+                    if !batch.is_empty() {
+                        app.is_fetching = true;
+                        spawn_health_checks(&ssh_backend, &app.servers, &batch, health_tx.clone());
+                    }
+
+                    if !changes.is_empty() {
+                        app.set_status(format!(
+                            "Config reloaded: {} added, {} removed, {} changed",
+                            changes.added.len(),
+                            changes.removed.len(),
+                            changes.changed.len()
+                        ));
+                    }
+                }
+                Err(e) => {
+                    app.set_status(format!("Config reload failed: {}", e));
+                }
+            }
+        }
+
+        // Report host editor results (non-blocking)
+        while let Ok(result) = edit_rx.try_recv() {
+            match result {
+                Ok(summary) => app.set_status(summary),
+                Err(e) => app.set_status(format!("Edit failed: {}", e)),
+            }
+        }
+
+        // Process any resolved effective configs (non-blocking)
+        while let Ok((id, result)) = effective_rx.try_recv() {
+            if app.resolving_config == Some(id) {
+                app.resolving_config = None;
+            }
+            app.effective_config = Some((id, result.map_err(|e| e.to_string())));
+        }
+
         // Poll for events with short timeout
         if let Some(event) = poll_event(Duration::from_millis(100))? {
             match event {
@@ -174,55 +541,48 @@ async fn main() -> Result<()> {
                     let result = handle_key_event(&mut app, key);
                     match result {
                         HandleResult::Continue => {}
-                        HandleResult::LaunchSsh(idx) => {
-                            if demo_mode {
-                                app.set_status("Demo mode: SSH connections disabled".to_string());
-                            } else {
-                                ssh_target = Some(idx);
+                        // Stay in the TUI when the backend won't start sessions (demo mode)
+                        HandleResult::LaunchSsh(id) => match ssh_backend.session_refusal() {
+                            Some(reason) => app.set_status(reason),
+                            None => {
+                                ssh_target = Some((id, Vec::new()));
                                 break Ok(());
                             }
-                        }
+                        },
+                        HandleResult::LaunchSshWithEnv(id, env) => match ssh_backend.session_refusal() {
+                            Some(reason) => app.set_status(reason),
+                            None => {
+                                ssh_target = Some((id, env));
+                                break Ok(());
+                            }
+                        },
                         HandleResult::RefreshAll => {
-                            if demo_mode {
-                                app.set_status("Demo mode: Health checks disabled".to_string());
-                            } else {
-                                app.is_fetching = true;
-                                // Reset check times
-                                for server in &mut app.servers {
-                                    server.last_check = None;
-                                }
-                                spawn_all_health_checks(&app.servers, health_tx.clone());
+                            // Servers with a check in flight get its result instead
+                            let all: Vec<usize> = (0..app.servers.len()).collect();
+                            let batch = scheduler.claim(&app.servers, &all, Instant::now());
+                            app.is_fetching = true;
+                            // Reset check times
+                            for &idx in &batch {
+                                app.servers[idx].last_check = None;
                             }
+                            spawn_health_checks(&ssh_backend, &app.servers, &batch, health_tx.clone());
                         }
-                        HandleResult::RefreshServer(idx) => {
-                            if demo_mode {
-                                app.set_status("Demo mode: Health checks disabled".to_string());
-                            } else if idx < app.servers.len() {
-                                app.servers[idx].last_check = None;
-                                spawn_health_check(
-                                    idx,
-                                    app.servers[idx].clone(),
-                                    health_tx.clone(),
-                                );
+                        HandleResult::RefreshServer(id) => {
+                            if let Some(idx) = app.index_of(id) {
+                                if scheduler.claim(&app.servers, &[idx], Instant::now()).is_empty() {
+                                    app.set_status(format!("{} is already being checked", app.servers[idx].host));
+                                } else {
+                                    app.servers[idx].last_check = None;
+                                    spawn_health_checks(&ssh_backend, &app.servers, &[idx], health_tx.clone());
+                                }
                             }
                         }
                         HandleResult::ToggleFavorite => {
-                            // Remember the selected server before toggling
-                            let selected_host = app.selected_server().map(|s| s.host.clone());
-
                             app.toggle_selected_favorite();
 
-                            // Re-sort if using Favorites sort order
+                            // Re-sort if using Favorites sort order; the cursor stays on the server
                             if app.sort_order == SortOrder::Favorites {
                                 app.sort_servers();
-
-                                // Restore selection to the same server
-                                if let Some(host) = selected_host {
-                                    let display_order = app.display_order_servers();
-                                    if let Some(pos) = display_order.iter().position(|&idx| app.servers[idx].host == host) {
-                                        app.selected_index = pos;
-                                    }
-                                }
                             }
 
                             // Update history reference and save
@@ -231,6 +591,26 @@ async fn main() -> Result<()> {
                                 app.set_status(format!("Failed to save: {}", e));
                             }
                         }
+                        HandleResult::GroupsCollapsed => {
+                            history.collapsed_groups = app.history.collapsed_groups.clone();
+                            if let Err(e) = history.save() {
+                                app.set_status(format!("Failed to save: {}", e));
+                            }
+                        }
+                        HandleResult::TagsChanged => {
+                            if demo_mode {
+                                app.set_status("Demo mode: Tags are not saved".to_string());
+                            } else if let Err(e) = app.tag_file.save() {
+                                app.set_status(format!("Failed to save tags: {}", e));
+                            }
+                        }
+                        HandleResult::ViewsChanged => {
+                            if demo_mode {
+                                app.set_status("Demo mode: Views are not saved".to_string());
+                            } else if let Err(e) = app.view_file.save() {
+                                app.set_status(format!("Failed to save views: {}", e));
+                            }
+                        }
                         HandleResult::SortOrderChanged => {
                             // Save sort order to history
                             history.set_sort_order(app.sort_order.as_str());
@@ -238,18 +618,17 @@ async fn main() -> Result<()> {
                                 app.set_status(format!("Failed to save: {}", e));
                             }
                         }
-                        HandleResult::RunCommand(idx, cmd) => {
-                            if demo_mode {
-                                app.set_status("Demo mode: Remote commands disabled".to_string());
-                            } else if idx < app.servers.len() {
+                        HandleResult::RunCommand(id, cmd) => {
+                            if let Some(idx) = app.index_of(id) {
                                 let server = app.servers[idx].clone();
+                                let backend = ssh_backend.clone();
                                 let tx = cmd_tx.clone();
                                 app.is_running_command = true;
                                 app.view_mode = ViewMode::CommandOutput;
 
                                 // Spawn async task to run command
                                 tokio::spawn(async move {
-                                    let result = run_remote_command(&server, &cmd).await;
+                                    let result = backend.run_command(&server, &cmd).await;
                                     let _ = tx.send(result);
                                 });
                             }
@@ -321,10 +700,8 @@ async fn main() -> Result<()> {
                                 }
                             }
                         }
-                        HandleResult::OpenTunnel(idx, spec) => {
-                            if demo_mode {
-                                app.set_status("Demo mode: SSH tunnels disabled".to_string());
-                            } else if idx < app.servers.len() {
+                        HandleResult::OpenTunnel(id, spec) => {
+                            if let Some(idx) = app.index_of(id) {
                                 let server = &app.servers[idx];
                                 // Parse spec: "port", "port_start-port_end", "host:port", or "host:port_start-port_end"
                                 let (remote_host, port_spec) = if spec.contains(':') {
@@ -366,7 +743,7 @@ async fn main() -> Result<()> {
                                     };
 
                                     for remote_port in &ports {
-                                        match app.tunnel_manager.open_tunnel(server, &remote_host, *remote_port, None, group_id) {
+                                        match app.tunnel_manager.open_tunnel(&*ssh_backend, server, &remote_host, *remote_port, None, group_id) {
                                             Ok(_) => opened += 1,
                                             Err(e) => {
                                                 failed += 1;
@@ -435,6 +812,31 @@ async fn main() -> Result<()> {
                                 app.selected_tunnel = 0;
                             }
                         }
+                        HandleResult::EditHost(edit) => {
+                            if demo_mode {
+                                app.set_status("Demo mode: Config editing disabled".to_string());
+                            } else {
+                                // The file watcher picks up the change and reloads the list
+                                let tx = edit_tx.clone();
+                                tokio::spawn(async move {
+                                    let _ = tx.send(apply_host_edit(&edit).await);
+                                });
+                            }
+                        }
+                        HandleResult::ResolveEffectiveConfig(id) => {
+                            if demo_mode {
+                                app.set_status("Demo mode: ssh -G disabled".to_string());
+                            } else if let Some(idx) = app.index_of(id) {
+                                let server = app.servers[idx].clone();
+                                let tx = effective_tx.clone();
+                                app.resolving_config = Some(id);
+
+                                tokio::spawn(async move {
+                                    let result = resolve_effective_config(&server).await;
+                                    let _ = tx.send((id, result));
+                                });
+                            }
+                        }
                         HandleResult::InstallMoshLocally => {
                             app.set_status("Installing mosh locally...".to_string());
                             let (success, msg) = ssh::install_mosh_locally();
@@ -443,17 +845,16 @@ async fn main() -> Result<()> {
                             }
                             app.set_status(msg);
                         }
-                        HandleResult::InstallMoshOnServer(idx) => {
-                            if demo_mode {
-                                app.set_status("Demo mode: Install disabled".to_string());
-                            } else if idx < app.servers.len() {
+                        HandleResult::InstallMoshOnServer(id) => {
+                            if let Some(idx) = app.index_of(id) {
                                 let server = app.servers[idx].clone();
+                                let backend = ssh_backend.clone();
                                 let server_host = server.host.clone();
                                 let tx = cmd_tx.clone();
                                 app.set_status(format!("Installing mosh on {}...", server_host));
 
                                 tokio::spawn(async move {
-                                    let (success, msg) = ssh::install_mosh_remotely(&server).await;
+                                    let (success, msg) = ssh::install_mosh_remotely(&*backend, &server).await;
                                     let result_msg = if success {
                                         format!("✓ {}", msg)
                                     } else {
@@ -468,35 +869,32 @@ async fn main() -> Result<()> {
                             }
                         }
                         HandleResult::InstallMoshOnAllServers => {
-                            if demo_mode {
-                                app.set_status("Demo mode: Install disabled".to_string());
+                            let servers: Vec<_> = app.servers.iter()
+                                .filter(|s| s.metrics.as_ref().map(|m| m.mosh_server_path.is_none()).unwrap_or(true))
+                                .cloned()
+                                .collect();
+
+                            if servers.is_empty() {
+                                app.set_status("All servers already have mosh installed".to_string());
                             } else {
-                                let servers: Vec<_> = app.servers.iter()
-                                    .filter(|s| s.metrics.as_ref().map(|m| m.mosh_server_path.is_none()).unwrap_or(true))
-                                    .cloned()
-                                    .collect();
+                                let backend = ssh_backend.clone();
+                                let tx = cmd_tx.clone();
+                                let count = servers.len();
+                                app.set_status(format!("Installing mosh on {} servers...", count));
 
-                                if servers.is_empty() {
-                                    app.set_status("All servers already have mosh installed".to_string());
-                                } else {
-                                    let tx = cmd_tx.clone();
-                                    let count = servers.len();
-                                    app.set_status(format!("Installing mosh on {} servers...", count));
-
-                                    tokio::spawn(async move {
-                                        let mut results = Vec::new();
-                                        for server in servers {
-                                            let (success, msg) = ssh::install_mosh_remotely(&server).await;
-                                            let symbol = if success { "✓" } else { "✗" };
-                                            results.push(format!("{} {}: {}", symbol, server.host, msg));
-                                        }
-                                        let _ = tx.send(Ok(results.join("\n")));
-                                    });
+                                tokio::spawn(async move {
+                                    let mut results = Vec::new();
+                                    for server in servers {
+                                        let (success, msg) = ssh::install_mosh_remotely(&*backend, &server).await;
+                                        let symbol = if success { "✓" } else { "✗" };
+                                        results.push(format!("{} {}: {}", symbol, server.host, msg));
+                                    }
+                                    let _ = tx.send(Ok(results.join("\n")));
+                                });
 
-                                    app.command_server = Some("mosh install on all servers".to_string());
-                                    app.is_running_command = true;
-                                    app.view_mode = ViewMode::CommandOutput;
-                                }
+                                app.command_server = Some("mosh install on all servers".to_string());
+                                app.is_running_command = true;
+                                app.view_mode = ViewMode::CommandOutput;
                             }
                         }
                     }
@@ -528,28 +926,22 @@ async fn main() -> Result<()> {
     result?;
 
     // Launch SSH/Mosh if requested
-    if let Some(idx) = ssh_target {
-        if idx < app.servers.len() {
+    if let Some((id, env)) = ssh_target {
+        if let Some(idx) = app.index_of(id) {
             let server = &app.servers[idx];
 
-            // Record connection in history
-            history.record_connection(&server.host);
-            if let Err(e) = history.save() {
-                eprintln!("Warning: Failed to save history: {}", e);
+            // Record connection in history (demo history is never saved)
+            if !demo_mode {
+                history.record_connection(&server.host);
+                if let Err(e) = history.save() {
+                    eprintln!("Warning: Failed to save history: {}", e);
+                }
             }
 
-            if app.use_mosh {
-                println!("Connecting to {} via mosh...", server.host);
-                if let Err(e) = launch_mosh_session(server) {
-                    eprintln!("Mosh failed: {}", e);
-                    eprintln!("Falling back to SSH...");
-                    println!("Connecting to {}...", server.host);
-                    launch_ssh_session(server)?;
-                }
-            } else {
-                println!("Connecting to {}...", server.host);
-                launch_ssh_session(server)?;
+            for (name, value) in &env {
+                println!("Using {}={}", name, value);
             }
+            ssh_backend.launch_session(server, app.use_mosh, &env)?;
         }
     }
 
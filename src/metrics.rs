@@ -0,0 +1,541 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::gpu;
+use crate::server::SystemMetrics;
+
+/// How long CPU counters are sampled for; a single read only gives the
+/// average since boot
+const CPU_SAMPLE: &str = "0.5";
+
+/// Picks the probe for the remote OS. Everything runs in the C locale so
+/// numbers and labels don't depend on the server's language.
+const OS_SWITCH: &str = r#"
+export LC_ALL=C
+os=$(uname -s)
+echo "===OS==="
+echo "$os"
+case "$os" in
+Linux) PROBE_LINUX ;;
+Darwin) PROBE_MACOS ;;
+FreeBSD) PROBE_FREEBSD ;;
+*) PROBE_OTHER ;;
+esac
+"#;
+
+/// Raw counters from /proc, two CPU samples apart
+const PROBE_LINUX: &str = r#"
+echo "===CORES==="
+nproc 2>/dev/null || grep -c '^processor' /proc/cpuinfo
+echo "===STAT==="
+head -n1 /proc/stat
+sleep SAMPLE
+head -n1 /proc/stat
+echo "===MEMINFO==="
+cat /proc/meminfo
+echo "===LOADAVG==="
+cat /proc/loadavg
+"#;
+
+/// `top` samples twice and the second sample covers the interval; `vm_stat`
+/// counts pages, sized in its header
+const PROBE_MACOS: &str = r#"
+echo "===CORES==="
+sysctl -n hw.ncpu
+echo "===CPU==="
+top -l 2 -n 0 -s 1 | grep '^CPU usage' | tail -n1
+echo "===MEMSIZE==="
+sysctl -n hw.memsize
+echo "===VMSTAT==="
+vm_stat
+echo "===LOADAVG==="
+sysctl -n vm.loadavg
+"#;
+
+/// CPU tick counters two samples apart, and page counts by name
+const PROBE_FREEBSD: &str = r#"
+echo "===CORES==="
+sysctl -n hw.ncpu
+echo "===CPTIME==="
+sysctl -n kern.cp_time
+sleep SAMPLE
+sysctl -n kern.cp_time
+echo "===SYSCTL==="
+sysctl hw.physmem hw.pagesize vm.stats.vm.v_free_count vm.stats.vm.v_inactive_count vm.stats.vm.v_cache_count 2>/dev/null
+echo "===LOADAVG==="
+sysctl -n vm.loadavg
+"#;
+
+/// Whatever POSIX offers elsewhere: cores and load, no CPU or memory use
+const PROBE_OTHER: &str = r#"
+echo "===CORES==="
+getconf _NPROCESSORS_ONLN 2>/dev/null
+echo "===UPTIME==="
+uptime
+"#;
+
+/// Sections every OS reports the same way
+const COMMON: &str = r#"
+echo "===USERS==="
+who | awk '{print $1}' | sort -u
+"#;
+
+/// Where mosh-server is, checking PATH first, then common conda/brew locations
+const MOSH: &str = r#"
+echo "===MOSH==="
+if command -v mosh-server >/dev/null 2>&1; then
+    command -v mosh-server
+elif [ -x "$HOME/miniconda3/bin/mosh-server" ]; then
+    echo "$HOME/miniconda3/bin/mosh-server"
+elif [ -x "$HOME/anaconda3/bin/mosh-server" ]; then
+    echo "$HOME/anaconda3/bin/mosh-server"
+elif [ -x "$HOME/miniforge3/bin/mosh-server" ]; then
+    echo "$HOME/miniforge3/bin/mosh-server"
+elif [ -x "$HOME/mambaforge/bin/mosh-server" ]; then
+    echo "$HOME/mambaforge/bin/mosh-server"
+elif [ -x "$HOME/.local/bin/mosh-server" ]; then
+    echo "$HOME/.local/bin/mosh-server"
+elif [ -x "/opt/homebrew/bin/mosh-server" ]; then
+    echo "/opt/homebrew/bin/mosh-server"
+elif [ -x "/usr/local/bin/mosh-server" ]; then
+    echo "/usr/local/bin/mosh-server"
+else
+    echo ""
+fi
+"#;
+
+/// The remote OS, from `uname -s`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RemoteOs {
+    Linux,
+    MacOs,
+    FreeBsd,
+    Other,
+}
+
+impl RemoteOs {
+    fn from_uname(name: &str) -> Self {
+        match name {
+            "Linux" => RemoteOs::Linux,
+            "Darwin" => RemoteOs::MacOs,
+            "FreeBSD" => RemoteOs::FreeBsd,
+            _ => RemoteOs::Other,
+        }
+    }
+}
+
+/// The script that collects metrics in one round trip. The mosh-server
+/// lookup is only worth it when mosh is installed locally.
+pub fn probe_script(find_mosh: bool) -> String {
+    // Each probe is a block inside the `case`, so it needs braces
+    let block = |probe: &str| format!("{{ {}\n}}", probe.replace("SAMPLE", CPU_SAMPLE));
+    let mut script = OS_SWITCH
+        .replace("PROBE_LINUX", &block(PROBE_LINUX))
+        .replace("PROBE_MACOS", &block(&(PROBE_MACOS.to_string() + gpu::APPLE_GPU_PROBE)))
+        .replace("PROBE_FREEBSD", &block(PROBE_FREEBSD))
+        .replace("PROBE_OTHER", &block(PROBE_OTHER));
+    script.push_str(COMMON);
+    script.push_str(gpu::GPU_PROBE);
+    if find_mosh {
+        script.push_str(MOSH);
+    }
+    script
+}
+
+/// The probe's output split into its `===NAME===` sections
+fn sections(output: &str) -> HashMap<&str, Vec<&str>> {
+    let mut sections: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut current = "";
+    for line in output.lines() {
+        let line = line.trim();
+        if line.len() > 6 && line.starts_with("===") && line.ends_with("===") {
+            current = line.trim_matches('=');
+            sections.entry(current).or_default();
+        } else if !line.is_empty() {
+            sections.entry(current).or_default().push(line);
+        }
+    }
+    sections
+}
+
+/// Parse the output from the probe script
+pub fn parse_probe_output(output: &str) -> Result<SystemMetrics> {
+    let sections = sections(output);
+    let get = |name: &str| sections.get(name).map(|l| l.as_slice()).unwrap_or_default();
+    let Some(uname) = get("OS").first() else {
+        anyhow::bail!("Metrics probe didn't report an OS");
+    };
+
+    let mut metrics = SystemMetrics {
+        cpu_cores: get("CORES")
+            .first()
+            .and_then(|c| c.parse().ok())
+            .unwrap_or(0),
+        ..Default::default()
+    };
+    match RemoteOs::from_uname(uname) {
+        RemoteOs::Linux => {
+            // user nice system idle iowait irq softirq steal, then guest time
+            // that's already in user; iowait is idle
+            metrics.cpu_usage = cpu_from_samples(get("STAT"), &[3, 4], 8).unwrap_or(0.0);
+            (metrics.ram_total, metrics.ram_used) = linux_memory(get("MEMINFO"));
+            metrics.load_average = get("LOADAVG")
+                .first()
+                .map(|l| load_average(l))
+                .unwrap_or_default();
+        }
+        RemoteOs::MacOs => {
+            metrics.cpu_usage = get("CPU").first().and_then(|l| macos_cpu(l)).unwrap_or(0.0);
+            metrics.ram_total = get("MEMSIZE")
+                .first()
+                .and_then(|m| m.parse().ok())
+                .unwrap_or(0);
+            metrics.ram_used = macos_memory_used(get("VMSTAT")).min(metrics.ram_total);
+            metrics.load_average = get("LOADAVG")
+                .first()
+                .map(|l| load_average(l))
+                .unwrap_or_default();
+        }
+        RemoteOs::FreeBsd => {
+            // user nice sys intr idle
+            metrics.cpu_usage = cpu_from_samples(get("CPTIME"), &[4], 5).unwrap_or(0.0);
+            (metrics.ram_total, metrics.ram_used) = freebsd_memory(get("SYSCTL"));
+            metrics.load_average = get("LOADAVG")
+                .first()
+                .map(|l| load_average(l))
+                .unwrap_or_default();
+        }
+        RemoteOs::Other => {
+            metrics.load_average = get("UPTIME")
+                .first()
+                .map(|l| uptime_load(l))
+                .unwrap_or_default();
+        }
+    }
+
+    metrics.logged_in_users = get("USERS").iter().map(|u| u.to_string()).collect();
+    metrics.gpus = gpu::parse_nvidia(get("NVIDIA"), get("NVIDIA_APPS"));
+    metrics.gpus.extend(gpu::parse_rocm(&get("ROCM").join("\n")));
+    metrics.gpus.extend(gpu::parse_xpu(&get("XPU").join("\n")));
+    metrics.gpus.extend(gpu::parse_ioreg(get("IOREG"), metrics.ram_total));
+    metrics.mosh_server_path = get("MOSH").first().map(|p| p.to_string());
+    Ok(metrics)
+}
+
+/// CPU use between two counter lines, like `/proc/stat`'s `cpu  4705 356 584 3699 23 ...`
+/// or FreeBSD's `kern.cp_time`. The first `counted` counters add up to the total
+/// time and the ones at `idle` are idle time.
+fn cpu_from_samples(lines: &[&str], idle: &[usize], counted: usize) -> Option<f32> {
+    let counters = |line: &str| -> Vec<u64> {
+        line.split_whitespace()
+            .filter_map(|v| v.parse().ok())
+            .collect()
+    };
+    let (first, second) = (counters(lines.first()?), counters(lines.get(1)?));
+    if first.len() != second.len() || idle.iter().any(|&i| i >= first.len()) {
+        return None;
+    }
+    let counted = counted.min(first.len());
+    let total = |c: &[u64]| c[..counted].iter().sum::<u64>();
+    let idle = |c: &[u64]| idle.iter().map(|&i| c[i]).sum::<u64>();
+
+    let total_delta = total(&second).saturating_sub(total(&first));
+    if total_delta == 0 {
+        return None;
+    }
+    let idle_delta = idle(&second).saturating_sub(idle(&first));
+    Some(100.0 * total_delta.saturating_sub(idle_delta) as f32 / total_delta as f32)
+}
+
+/// Total and used bytes from /proc/meminfo. Used leaves out what the kernel
+/// can hand back (MemAvailable), or free, buffers and cache on old kernels.
+fn linux_memory(lines: &[&str]) -> (u64, u64) {
+    let fields: HashMap<&str, u64> = lines
+        .iter()
+        .filter_map(|line| {
+            let (key, rest) = line.split_once(':')?;
+            let kb = rest.split_whitespace().next()?.parse::<u64>().ok()?;
+            Some((key, kb * 1024))
+        })
+        .collect();
+    let total = fields.get("MemTotal").copied().unwrap_or(0);
+    let available = fields.get("MemAvailable").copied().unwrap_or_else(|| {
+        ["MemFree", "Buffers", "Cached"]
+            .iter()
+            .filter_map(|k| fields.get(k))
+            .sum()
+    });
+    (total, total.saturating_sub(available))
+}
+
+/// CPU use from `CPU usage: 5.55% user, 8.33% sys, 86.11% idle`
+fn macos_cpu(line: &str) -> Option<f32> {
+    let idle = line
+        .split(',')
+        .find(|part| part.trim_end().ends_with("idle"))?
+        .split_whitespace()
+        .next()?
+        .trim_end_matches('%')
+        .parse::<f32>()
+        .ok()?;
+    Some(100.0 - idle)
+}
+
+/// Used bytes from `vm_stat`, counted the way Activity Monitor's "Memory Used"
+/// is: active, wired and compressed pages
+fn macos_memory_used(lines: &[&str]) -> u64 {
+    let page_size = lines
+        .first()
+        .and_then(|header| header.split("page size of ").nth(1))
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|size| size.parse::<u64>().ok())
+        .unwrap_or(4096);
+    let pages = |name: &str| -> u64 {
+        lines
+            .iter()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .and_then(|value| value.trim().trim_end_matches('.').parse().ok())
+            .unwrap_or(0)
+    };
+    (pages("Pages active") + pages("Pages wired down") + pages("Pages occupied by compressor"))
+        * page_size
+}
+
+/// Total and used bytes from FreeBSD's `sysctl` lines, like `hw.pagesize: 4096`.
+/// Free, inactive and cached pages count as available.
+fn freebsd_memory(lines: &[&str]) -> (u64, u64) {
+    let values: HashMap<&str, u64> = lines
+        .iter()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            Some((key.trim(), value.trim().parse().ok()?))
+        })
+        .collect();
+    let total = values.get("hw.physmem").copied().unwrap_or(0);
+    let page_size = values.get("hw.pagesize").copied().unwrap_or(4096);
+    let available: u64 = [
+        "vm.stats.vm.v_free_count",
+        "vm.stats.vm.v_inactive_count",
+        "vm.stats.vm.v_cache_count",
+    ]
+    .iter()
+    .filter_map(|k| values.get(k))
+    .sum::<u64>()
+        * page_size;
+    (total, total.saturating_sub(available))
+}
+
+/// The first three numbers of `/proc/loadavg` (`0.52 0.58 0.59 1/123 4567`) or
+/// `vm.loadavg` (`{ 1.73 1.86 1.94 }`)
+fn load_average(line: &str) -> (f32, f32, f32) {
+    let mut loads = line
+        .split_whitespace()
+        .filter_map(|v| v.parse::<f32>().ok());
+    (
+        loads.next().unwrap_or(0.0),
+        loads.next().unwrap_or(0.0),
+        loads.next().unwrap_or(0.0),
+    )
+}
+
+/// Load averages from `uptime`, which says `load average: 0.52, 0.58, 0.59`
+/// on most systems and `load averages: 1.73 1.86 1.94` on some
+fn uptime_load(line: &str) -> (f32, f32, f32) {
+    match line.split_once("load average") {
+        Some((_, rest)) => {
+            let rest = rest.trim_start_matches('s').trim_start_matches(':');
+            load_average(&rest.replace(',', " "))
+        }
+        None => (0.0, 0.0, 0.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINUX_OUTPUT: &str = "\
+===OS===
+Linux
+===CORES===
+8
+===STAT===
+cpu  4705 356 584 3699 23 0 12 0 0 0
+cpu  4805 356 634 3999 73 0 12 0 0 0
+===MEMINFO===
+MemTotal:       16318412 kB
+MemFree:         1045236 kB
+MemAvailable:   12282764 kB
+Buffers:          427456 kB
+Cached:         10353456 kB
+SwapCached:            0 kB
+===LOADAVG===
+0.52 0.58 0.59 1/1234 56789
+===USERS===
+alice
+bob
+===NVIDIA===
+0, GPU-5c9a6f4e-0d1b-2c3d-8e9f-a0b1c2d3e4f5, NVIDIA A100-SXM4-80GB, 35, 1024, 81920, 47, 98.12, 400.00, 0
+===NVIDIA_APPS===
+GPU-5c9a6f4e-0d1b-2c3d-8e9f-a0b1c2d3e4f5, 48213, 1000, alice, python
+===ROCM===
+===XPU===
+===MOSH===
+/usr/bin/mosh-server
+";
+
+    const MACOS_OUTPUT: &str = "\
+===OS===
+Darwin
+===CORES===
+10
+===CPU===
+CPU usage: 5.55% user, 8.33% sys, 86.11% idle
+===MEMSIZE===
+17179869184
+===VMSTAT===
+Mach Virtual Memory Statistics: (page size of 16384 bytes)
+Pages free:                                6209.
+Pages active:                            316789.
+Pages inactive:                          307392.
+Pages speculative:                         3012.
+Pages throttled:                              0.
+Pages wired down:                        140826.
+Pages purgeable:                          11845.
+\"Translation faults\":                 902131275.
+Pages copy-on-write:                   23456010.
+Pages zero filled:                    432113987.
+Pages reactivated:                      4530331.
+Pages purged:                           1893013.
+File-backed pages:                       215317.
+Anonymous pages:                         411876.
+Pages stored in compressor:              512308.
+Pages occupied by compressor:            160001.
+Decompressions:                         3211011.
+Compressions:                           4812370.
+Pageins:                               11873320.
+Pageouts:                                 55331.
+Swapins:                                 187216.
+Swapouts:                                213551.
+===LOADAVG===
+{ 1.73 1.86 1.94 }
+===IOREG===
++-o AGXAcceleratorG13X  <class AGXAcceleratorG13X, id 0x1000002a1, registered, matched, active, busy 0 (0 ms), retain 74>
+  {
+    \"model\" = \"Apple M1 Pro\"
+    \"PerformanceStatistics\" = {\"Device Utilization %\"=41,\"In use system memory\"=1073741824}
+  }
+===USERS===
+admin
+===NVIDIA===
+===ROCM===
+===XPU===
+";
+
+    const FREEBSD_OUTPUT: &str = "\
+===OS===
+FreeBSD
+===CORES===
+4
+===CPTIME===
+150 0 60 5 785
+180 0 70 5 945
+===SYSCTL===
+hw.physmem: 8552042496
+hw.pagesize: 4096
+vm.stats.vm.v_free_count: 1218712
+vm.stats.vm.v_inactive_count: 212456
+===LOADAVG===
+{ 0.21 0.30 0.28 }
+===USERS===
+===NVIDIA===
+===ROCM===
+===XPU===
+";
+
+    #[test]
+    fn test_parse_linux_probe() {
+        let metrics = parse_probe_output(LINUX_OUTPUT).unwrap();
+        assert_eq!(metrics.cpu_cores, 8);
+        // 150 busy out of 500 ticks; iowait counts as idle
+        assert_eq!(metrics.cpu_usage, 30.0);
+        assert_eq!(metrics.ram_total, 16318412 * 1024);
+        assert_eq!(metrics.ram_used, (16318412 - 12282764) * 1024);
+        assert_eq!(metrics.load_average, (0.52, 0.58, 0.59));
+        assert_eq!(metrics.logged_in_users, vec!["alice", "bob"]);
+        assert_eq!(metrics.gpus.len(), 1);
+        assert_eq!(metrics.gpus[0].memory_total, 81920 * 1024 * 1024);
+        assert_eq!(metrics.gpus[0].processes[0].user.as_deref(), Some("alice"));
+        assert_eq!(
+            metrics.mosh_server_path.as_deref(),
+            Some("/usr/bin/mosh-server")
+        );
+
+        // Kernels before 3.14 have no MemAvailable
+        let old = LINUX_OUTPUT.replace("MemAvailable:   12282764 kB\n", "");
+        let metrics = parse_probe_output(&old).unwrap();
+        assert_eq!(
+            metrics.ram_used,
+            (16318412 - 1045236 - 427456 - 10353456) * 1024
+        );
+    }
+
+    #[test]
+    fn test_parse_macos_probe() {
+        let metrics = parse_probe_output(MACOS_OUTPUT).unwrap();
+        assert_eq!(metrics.cpu_cores, 10);
+        assert!((metrics.cpu_usage - 13.89).abs() < 0.01);
+        assert_eq!(metrics.ram_total, 17179869184);
+        assert_eq!(metrics.ram_used, (316789 + 140826 + 160001) * 16384);
+        assert_eq!(metrics.load_average, (1.73, 1.86, 1.94));
+        assert_eq!(metrics.logged_in_users, vec!["admin"]);
+        // The GPU shares the system's memory
+        assert_eq!(metrics.gpus.len(), 1);
+        assert_eq!(metrics.gpus[0].utilization, 41.0);
+        assert_eq!(metrics.gpus[0].memory_total, 17179869184);
+        assert!(metrics.mosh_server_path.is_none());
+    }
+
+    #[test]
+    fn test_parse_freebsd_probe() {
+        let metrics = parse_probe_output(FREEBSD_OUTPUT).unwrap();
+        assert_eq!(metrics.cpu_cores, 4);
+        // 40 busy out of 200 ticks
+        assert_eq!(metrics.cpu_usage, 20.0);
+        assert_eq!(metrics.ram_total, 8552042496);
+        assert_eq!(metrics.ram_used, 8552042496 - (1218712 + 212456) * 4096);
+        assert_eq!(metrics.load_average, (0.21, 0.30, 0.28));
+        assert!(metrics.logged_in_users.is_empty());
+    }
+
+    #[test]
+    fn test_parse_other_probe() {
+        let output = "===OS===\nOpenBSD\n===CORES===\n2\n===UPTIME===\n\
+                      10:42AM  up 3 days,  2:01, 1 user, load averages: 0.10, 0.20, 0.30\n";
+        let metrics = parse_probe_output(output).unwrap();
+        assert_eq!(metrics.cpu_cores, 2);
+        assert_eq!(metrics.load_average, (0.10, 0.20, 0.30));
+        assert_eq!(metrics.ram_total, 0);
+
+        assert_eq!(
+            uptime_load(" 10:42:01 up 42 days, load average: 0.52, 0.58, 0.59"),
+            (0.52, 0.58, 0.59)
+        );
+        assert!(parse_probe_output("bash: uname: command not found\n").is_err());
+    }
+
+    #[test]
+    fn test_probe_script() {
+        let script = probe_script(false);
+        assert!(script.contains("Linux) { \necho \"===CORES===\""));
+        assert!(script.contains("sleep 0.5"));
+        assert!(!script.contains("SAMPLE") && !script.contains("PROBE_"));
+        assert!(!script.contains("===MOSH==="));
+        // ioreg only on macOS, the GPU tools anywhere they're installed
+        assert!(script.contains("Darwin) { \n"));
+        assert!(script.find("===IOREG===") < script.find("FreeBSD)"));
+        assert!(script.rfind("===ROCM===") > script.find("esac"));
+        assert!(probe_script(true).contains("===MOSH==="));
+    }
+}
@@ -0,0 +1,376 @@
+use crate::history::History;
+use crate::server::{FailureKind, HealthStatus, Server};
+
+/// Comparison in a query predicate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn compare(self, left: f64, right: f64) -> bool {
+        match self {
+            Op::Eq => left == right,
+            Op::Ne => left != right,
+            Op::Lt => left < right,
+            Op::Le => left <= right,
+            Op::Gt => left > right,
+            Op::Ge => left >= right,
+        }
+    }
+}
+
+/// Numeric attribute a predicate compares
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NumberField {
+    Cpu,
+    Ram,
+    Load,
+    Cores,
+    Ping,
+    Users,
+    Gpus,
+    GpuFree,
+    GpuUtil,
+    GpuMem,
+    Connections,
+}
+
+/// Text or yes/no attribute a predicate matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextField {
+    Status,
+    User,
+    Who,
+    Tag,
+    Group,
+    Via,
+    Owner,
+    Mosh,
+    Favorite,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Predicate {
+    Number {
+        field: NumberField,
+        op: Op,
+        value: f64,
+    },
+    Text {
+        field: TextField,
+        negate: bool,
+        value: String,
+    },
+}
+
+/// A parsed `/` filter: predicates like `cpu<20` or `tag:prod`, plus the
+/// remaining free text for the fuzzy (or `re:`) search
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    predicates: Vec<Predicate>,
+    pub text: String,
+}
+
+impl Query {
+    /// Split a filter into predicates and free text. Words that don't parse as a
+    /// predicate on a known field are searched for as text.
+    pub fn parse(input: &str) -> Self {
+        let mut query = Query::default();
+        let mut text = Vec::new();
+        for word in input.split_whitespace() {
+            match parse_predicate(word) {
+                Some(predicate) => query.predicates.push(predicate),
+                None => text.push(word),
+            }
+        }
+        query.text = text.join(" ");
+        query
+    }
+
+    /// Check every predicate against a server. Metric predicates never match
+    /// servers without metrics.
+    pub fn matches(&self, server: &Server, history: &History) -> bool {
+        self.predicates.iter().all(|p| p.matches(server, history))
+    }
+}
+
+/// The filter without the predicates that need a health check (metrics, ping,
+/// status, who's logged in, mosh): what can narrow down servers before they're
+/// checked
+pub fn without_live_predicates(input: &str) -> String {
+    input
+        .split_whitespace()
+        .filter(|word| !parse_predicate(word).is_some_and(|p| p.needs_check()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn parse_predicate(word: &str) -> Option<Predicate> {
+    let op_start = word.find(['<', '>', '=', '!', ':'])?;
+    let (name, rest) = word.split_at(op_start);
+    let (op, value) = [
+        ("<=", Op::Le),
+        (">=", Op::Ge),
+        ("!=", Op::Ne),
+        ("<", Op::Lt),
+        (">", Op::Gt),
+        ("=", Op::Eq),
+        (":", Op::Eq),
+    ]
+    .iter()
+    .find_map(|(token, op)| rest.strip_prefix(token).map(|value| (*op, value)))?;
+    if value.is_empty() {
+        return None;
+    }
+
+    let name = name.to_lowercase();
+    if let Some(field) = number_field(&name) {
+        let number = value.trim_end_matches('%').trim_end_matches("ms");
+        return Some(Predicate::Number {
+            field,
+            op,
+            value: number.parse().ok()?,
+        });
+    }
+    let field = text_field(&name)?;
+    let negate = match op {
+        Op::Eq => false,
+        Op::Ne => true,
+        _ => return None,
+    };
+    Some(Predicate::Text {
+        field,
+        negate,
+        value: value.to_lowercase(),
+    })
+}
+
+fn number_field(name: &str) -> Option<NumberField> {
+    Some(match name {
+        "cpu" => NumberField::Cpu,
+        "ram" | "mem" => NumberField::Ram,
+        "load" => NumberField::Load,
+        "cores" => NumberField::Cores,
+        "ping" | "latency" => NumberField::Ping,
+        "users" => NumberField::Users,
+        "gpu" | "gpus" => NumberField::Gpus,
+        "gpu.free" => NumberField::GpuFree,
+        "gpu.util" => NumberField::GpuUtil,
+        "gpu.mem" => NumberField::GpuMem,
+        "conns" => NumberField::Connections,
+        _ => return None,
+    })
+}
+
+fn text_field(name: &str) -> Option<TextField> {
+    Some(match name {
+        "status" => TextField::Status,
+        "user" => TextField::User,
+        "who" => TextField::Who,
+        "tag" => TextField::Tag,
+        "group" => TextField::Group,
+        "via" | "bastion" => TextField::Via,
+        "owner" => TextField::Owner,
+        "mosh" => TextField::Mosh,
+        "fav" => TextField::Favorite,
+        _ => return None,
+    })
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "yes" | "y" | "true" | "1" => Some(true),
+        "no" | "n" | "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+impl Predicate {
+    /// Whether the predicate reads what a health check finds out
+    fn needs_check(&self) -> bool {
+        match self {
+            Predicate::Number { field, .. } => *field != NumberField::Connections,
+            Predicate::Text { field, .. } => {
+                matches!(field, TextField::Status | TextField::Who | TextField::Mosh)
+            }
+        }
+    }
+
+    fn matches(&self, server: &Server, history: &History) -> bool {
+        match self {
+            Predicate::Number { field, op, value } => {
+                number_value(*field, server, history).is_some_and(|v| op.compare(v, *value))
+            }
+            Predicate::Text {
+                field,
+                negate,
+                value,
+            } => text_matches(*field, value, server, history) == Some(!negate),
+        }
+    }
+}
+
+fn number_value(field: NumberField, server: &Server, history: &History) -> Option<f64> {
+    let metrics = server.metrics.as_ref();
+    let gpu_average = |f: fn(&crate::server::GpuInfo) -> f32| {
+        let gpus = &metrics?.gpus;
+        if gpus.is_empty() {
+            return None;
+        }
+        Some(gpus.iter().map(|g| f(g) as f64).sum::<f64>() / gpus.len() as f64)
+    };
+    match field {
+        NumberField::Cpu => metrics.map(|m| m.cpu_usage as f64),
+        NumberField::Ram => metrics.map(|m| m.ram_usage_percent() as f64),
+        NumberField::Load => metrics.map(|m| m.load_average.0 as f64),
+        NumberField::Cores => metrics.map(|m| m.cpu_cores as f64),
+        NumberField::Ping => server.latency_ms().map(|ms| ms as f64),
+        NumberField::Users => metrics.map(|m| m.logged_in_users.len() as f64),
+        NumberField::Gpus => metrics.map(|m| m.gpus.len() as f64),
+        NumberField::GpuFree => metrics.map(|m| m.free_gpus() as f64),
+        NumberField::GpuUtil => gpu_average(|g| g.utilization),
+        NumberField::GpuMem => gpu_average(|g| g.memory_percent()),
+        NumberField::Connections => Some(history.connect_count(&server.host) as f64),
+    }
+}
+
+/// Whether a server has the value; None when it can't tell (e.g. no metrics yet)
+fn text_matches(field: TextField, value: &str, server: &Server, history: &History) -> Option<bool> {
+    let eq = |s: &str| s.eq_ignore_ascii_case(value);
+    match field {
+        TextField::Status => {
+            let status = &server.status;
+            Some(match value {
+                "up" => status.is_up(),
+                "down" => status.is_down(),
+                "healthy" => *status == HealthStatus::Healthy,
+                "degraded" => matches!(status, HealthStatus::Degraded(_)),
+                "unreachable" => matches!(status, HealthStatus::Unreachable(_)),
+                "blocked" => matches!(status, HealthStatus::BlockedByBastion(_)),
+                "unknown" => *status == HealthStatus::Unknown,
+                // Why it's unreachable: status:auth, status:timeout, ...
+                reason => {
+                    let kind = FailureKind::ALL.into_iter().find(|k| k.label() == reason)?;
+                    matches!(status, HealthStatus::Unreachable(e) if e.kind == kind)
+                }
+            })
+        }
+        TextField::User => Some(server.user.as_deref().is_some_and(eq)),
+        TextField::Who => {
+            let metrics = server.metrics.as_ref()?;
+            Some(metrics.logged_in_users.iter().any(|u| eq(u)))
+        }
+        TextField::Tag => Some(server.tags.iter().any(|t| eq(t))),
+        TextField::Group => Some(server.in_group(value)),
+        TextField::Via => Some(server.jump_chain.iter().any(|hop| eq(&hop.host))),
+        TextField::Owner => Some(server.owner.as_deref().is_some_and(eq)),
+        TextField::Mosh => {
+            let metrics = server.metrics.as_ref()?;
+            Some(metrics.mosh_server_path.is_some() == parse_bool(value)?)
+        }
+        TextField::Favorite => Some(history.is_favorite(&server.host) == parse_bool(value)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{ConnectError, GpuInfo, SystemMetrics};
+
+    fn gpu_box(host: &str, cpu: f32, gpu_util: &[f32]) -> Server {
+        let mut server = Server::new(host.to_string(), "10.0.0.1".to_string());
+        server.user = Some("deploy".to_string());
+        server.status = HealthStatus::Healthy;
+        server.metrics = Some(SystemMetrics {
+            cpu_usage: cpu,
+            gpus: gpu_util
+                .iter()
+                .map(|&utilization| GpuInfo {
+                    name: "A100".to_string(),
+                    utilization,
+                    memory_used: 0,
+                    memory_total: 80_000_000_000,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        });
+        server
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let query = Query::parse("web cpu<20 gpu.free>=1 status:up tag!=prod bogus:1 cpu<abc");
+        assert_eq!(query.text, "web bogus:1 cpu<abc");
+        assert_eq!(query.predicates.len(), 4);
+        assert_eq!(
+            query.predicates[0],
+            Predicate::Number {
+                field: NumberField::Cpu,
+                op: Op::Lt,
+                value: 20.0
+            }
+        );
+        assert_eq!(
+            query.predicates[3],
+            Predicate::Text {
+                field: TextField::Tag,
+                negate: true,
+                value: "prod".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_without_live_predicates() {
+        assert_eq!(
+            without_live_predicates("a100 tag:gpu gpu.free>=2 status:up group:ml conns>3 mosh:yes"),
+            "a100 tag:gpu group:ml conns>3"
+        );
+        assert_eq!(without_live_predicates(""), "");
+    }
+
+    #[test]
+    fn test_query_matches() {
+        let history = History::default();
+        let idle = gpu_box("idle", 5.0, &[0.0, 95.0]);
+        let busy = gpu_box("busy", 80.0, &[99.0]);
+        let mut unchecked = Server::new("new".to_string(), "10.0.0.9".to_string());
+        unchecked.tags.insert("prod".to_string());
+
+        let matching = |q: &str| -> Vec<&str> {
+            let query = Query::parse(q);
+            [&idle, &busy, &unchecked]
+                .into_iter()
+                .filter(|s| query.matches(s, &history))
+                .map(|s| s.host.as_str())
+                .collect()
+        };
+
+        assert_eq!(matching("cpu<20 gpu.free>=1"), vec!["idle"]);
+        assert_eq!(matching("gpu.util>=40"), vec!["idle", "busy"]);
+        assert_eq!(matching("status:up user:DEPLOY"), vec!["idle", "busy"]);
+        assert_eq!(matching("status!=up"), vec!["new"]);
+        assert_eq!(matching("tag:prod"), vec!["new"]);
+        // Without metrics, metric predicates don't match either way
+        assert_eq!(matching("users:0"), vec!["idle", "busy"]);
+        assert_eq!(matching("mosh:no"), vec!["idle", "busy"]);
+        assert_eq!(matching("fav:no conns=0"), vec!["idle", "busy", "new"]);
+
+        // Unreachable servers also match the reason
+        let mut locked = Server::new("locked".to_string(), "10.0.0.8".to_string());
+        locked.status = HealthStatus::Unreachable(ConnectError::new(
+            FailureKind::Auth,
+            "Permission denied (publickey).",
+        ));
+        for (q, expected) in [("status:auth", true), ("status:down", true), ("status:timeout", false)] {
+            assert_eq!(Query::parse(q).matches(&locked, &history), expected, "{}", q);
+        }
+    }
+}
@@ -1,7 +1,13 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
+use crate::checks::{CheckDef, CheckLevel, CheckResult};
+
 /// Health status of a server
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
 #[allow(dead_code)]
@@ -9,17 +15,279 @@ pub enum HealthStatus {
     #[default]
     Unknown,
     Healthy,
-    Degraded,
-    Unreachable,
+    /// Reachable, with the problems listed
+    Degraded(Vec<Issue>),
+    Unreachable(ConnectError),
+    /// Not checked because a bastion in the jump chain is unreachable
+    BlockedByBastion(String),
+}
+
+impl HealthStatus {
+    /// Human-readable status for the details view
+    pub fn label(&self) -> String {
+        match self {
+            HealthStatus::Unknown => "Unknown".to_string(),
+            HealthStatus::Healthy => "Healthy".to_string(),
+            HealthStatus::Degraded(issues) => {
+                let issues: Vec<String> = issues.iter().map(|i| i.label()).collect();
+                format!("Degraded ({})", issues.join(", "))
+            }
+            HealthStatus::Unreachable(error) => format!("Unreachable ({})", error.kind.label()),
+            HealthStatus::BlockedByBastion(bastion) => {
+                format!("Blocked (bastion {} unreachable)", bastion)
+            }
+        }
+    }
+
+    /// Whether the last check reached the server
+    pub fn is_up(&self) -> bool {
+        matches!(self, HealthStatus::Healthy | HealthStatus::Degraded(_))
+    }
+
+    /// Whether the server couldn't be checked, itself or through its bastion
+    pub fn is_down(&self) -> bool {
+        matches!(
+            self,
+            HealthStatus::Unreachable(_) | HealthStatus::BlockedByBastion(_)
+        )
+    }
+}
+
+/// Something wrong with a server that still answers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// Round trip above the latency threshold
+    Slow(Duration),
+    /// The metrics script failed
+    NoMetrics(String),
+    /// A custom check warned or failed
+    Check { name: String, level: CheckLevel },
+}
+
+impl Issue {
+    pub fn label(&self) -> String {
+        match self {
+            Issue::Slow(latency) => format!("slow: {}ms", latency.as_millis()),
+            Issue::NoMetrics(_) => "no metrics".to_string(),
+            Issue::Check { name, level } => format!("{}: {}", name, level.label()),
+        }
+    }
+}
+
+/// Why a connection failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    /// The host name doesn't resolve
+    Dns,
+    /// No answer in time
+    Timeout,
+    /// Nothing listening on the SSH port
+    Refused,
+    /// The host key doesn't match known_hosts
+    HostKey,
+    /// No key or password was accepted
+    Auth,
+    /// The ProxyJump or ProxyCommand failed
+    Proxy,
+    Other,
+}
+
+impl FailureKind {
+    pub const ALL: [FailureKind; 7] = [
+        FailureKind::Dns,
+        FailureKind::Timeout,
+        FailureKind::Refused,
+        FailureKind::HostKey,
+        FailureKind::Auth,
+        FailureKind::Proxy,
+        FailureKind::Other,
+    ];
+
+    /// Short reason for the server list (fits the ping column)
+    pub fn label(&self) -> &'static str {
+        match self {
+            FailureKind::Dns => "dns",
+            FailureKind::Timeout => "timeout",
+            FailureKind::Refused => "refused",
+            FailureKind::HostKey => "hostkey",
+            FailureKind::Auth => "auth",
+            FailureKind::Proxy => "proxy",
+            FailureKind::Other => "error",
+        }
+    }
+}
+
+/// A failed connection: its kind, and the full message from ssh
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectError {
+    pub kind: FailureKind,
+    pub message: String,
+}
+
+impl ConnectError {
+    pub fn new(kind: FailureKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    /// Classify ssh's error output. `proxied` says whether the connection went
+    /// through a bastion or ProxyCommand, whose failures ssh reports vaguely.
+    pub fn classify(message: &str, proxied: bool) -> Self {
+        let text = message.to_lowercase();
+        let has = |patterns: &[&str]| patterns.iter().any(|p| text.contains(p));
+
+        let kind = if has(&[
+            "remote host identification has changed",
+            "host key verification failed",
+            "does not match known_hosts",
+        ]) {
+            FailureKind::HostKey
+        } else if has(&[
+            "permission denied",
+            "too many authentication failures",
+            "no supported authentication methods",
+        ]) {
+            FailureKind::Auth
+        } else if proxied
+            && has(&[
+                "stdio forwarding failed",
+                "unknown port 65535",
+                "channel 0: open failed",
+                "proxycommand",
+                "kex_exchange_identification",
+            ])
+        {
+            FailureKind::Proxy
+        } else if has(&[
+            "could not resolve",
+            "name or service not known",
+            "nodename nor servname",
+            "name resolution",
+        ]) {
+            FailureKind::Dns
+        } else if has(&["connection refused"]) {
+            FailureKind::Refused
+        } else if has(&["timed out", "timeout"]) {
+            FailureKind::Timeout
+        } else {
+            FailureKind::Other
+        };
+        Self::new(kind, message.trim())
+    }
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
 }
 
-/// GPU information
+impl std::error::Error for ConnectError {}
+
+/// Who made a GPU, which decides the tool it's read with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuVendor {
+    #[default]
+    Nvidia,
+    Amd,
+    Intel,
+    Apple,
+}
+
+impl GpuVendor {
+    pub fn label(self) -> &'static str {
+        match self {
+            GpuVendor::Nvidia => "NVIDIA",
+            GpuVendor::Amd => "AMD",
+            GpuVendor::Intel => "Intel",
+            GpuVendor::Apple => "Apple",
+        }
+    }
+
+    /// The variable that limits a job to some of the GPUs, by index. Apple
+    /// Silicon has a single GPU.
+    pub fn visible_devices_var(self) -> Option<&'static str> {
+        match self {
+            GpuVendor::Nvidia => Some("CUDA_VISIBLE_DEVICES"),
+            GpuVendor::Amd => Some("HIP_VISIBLE_DEVICES"),
+            GpuVendor::Intel => Some("ZE_AFFINITY_MASK"),
+            GpuVendor::Apple => None,
+        }
+    }
+}
+
+/// A compute process running on a GPU
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GpuProcess {
+    pub pid: u32,
+    /// Unknown when the process is in another PID namespace, like a container
+    pub user: Option<String>,
+    pub name: String,
+    pub memory_used: u64,
+}
+
+/// GPU information. Readings a vendor's tool doesn't report are `None`.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct GpuInfo {
+    #[serde(default)]
+    pub vendor: GpuVendor,
+    /// The vendor's device index, as used by `CUDA_VISIBLE_DEVICES` and friends
+    #[serde(default)]
+    pub index: u32,
     pub name: String,
     pub utilization: f32,
     pub memory_used: u64,
     pub memory_total: u64,
+    /// Degrees Celsius
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    /// Watts
+    #[serde(default)]
+    pub power_draw: Option<f32>,
+    #[serde(default)]
+    pub power_limit: Option<f32>,
+    /// Uncorrected ECC errors since the driver loaded
+    #[serde(default)]
+    pub ecc_errors: Option<u64>,
+    #[serde(default)]
+    pub processes: Vec<GpuProcess>,
+}
+
+/// A GPU counts as free below this utilization and memory use (percent)
+pub const FREE_GPU_MAX_UTILIZATION: f32 = 10.0;
+pub const FREE_GPU_MAX_MEMORY_PERCENT: f32 = 10.0;
+
+impl GpuInfo {
+    pub fn memory_percent(&self) -> f32 {
+        if self.memory_total == 0 {
+            0.0
+        } else {
+            (self.memory_used as f32 / self.memory_total as f32) * 100.0
+        }
+    }
+
+    /// Idle enough to hand to a new job
+    pub fn is_free(&self) -> bool {
+        self.utilization < FREE_GPU_MAX_UTILIZATION
+            && self.memory_percent() < FREE_GPU_MAX_MEMORY_PERCENT
+    }
+
+    /// Who is using the GPU and how much memory their processes hold, most first
+    pub fn owners(&self) -> Vec<(&str, u64)> {
+        let mut owners: Vec<(&str, u64)> = Vec::new();
+        for process in &self.processes {
+            let user = process.user.as_deref().unwrap_or("?");
+            match owners.iter_mut().find(|(u, _)| *u == user) {
+                Some((_, memory)) => *memory += process.memory_used,
+                None => owners.push((user, process.memory_used)),
+            }
+        }
+        owners.sort_by_key(|(_, memory)| std::cmp::Reverse(*memory));
+        owners
+    }
 }
 
 /// System metrics fetched from a remote server
@@ -43,38 +311,208 @@ impl SystemMetrics {
             (self.ram_used as f32 / self.ram_total as f32) * 100.0
         }
     }
+
+    /// Number of GPUs that are free (see [`GpuInfo::is_free`])
+    pub fn free_gpus(&self) -> usize {
+        self.gpus.iter().filter(|gpu| gpu.is_free()).count()
+    }
+}
+
+/// A hop in a ProxyJump chain (`[user@]host[:port]` or `ssh://[user@]host[:port]`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JumpHost {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl JumpHost {
+    /// Parse a single ProxyJump destination
+    pub fn parse(spec: &str) -> Option<Self> {
+        let spec = spec.trim();
+        let spec = spec.strip_prefix("ssh://").unwrap_or(spec);
+        if spec.is_empty() {
+            return None;
+        }
+
+        let (user, rest) = match spec.rsplit_once('@') {
+            Some((user, rest)) => (Some(user.to_string()), rest),
+            None => (None, spec),
+        };
+
+        // Bracketed IPv6 literal: [::1]:2222
+        let (host, port) = if let Some(inner) = rest.strip_prefix('[') {
+            let (host, after) = inner.split_once(']')?;
+            (host, after.strip_prefix(':'))
+        } else {
+            match rest.rsplit_once(':') {
+                Some((host, port)) if !host.contains(':') => (host, Some(port)),
+                _ => (rest, None),
+            }
+        };
+
+        if host.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            user,
+            host: host.to_string(),
+            port: port.and_then(|p| p.parse().ok()),
+        })
+    }
+
+    /// Parse a comma-separated ProxyJump value; `none` disables jumping
+    pub fn parse_chain(value: &str) -> Vec<Self> {
+        if value.eq_ignore_ascii_case("none") {
+            return Vec::new();
+        }
+        value.split(',').filter_map(Self::parse).collect()
+    }
+
+    /// Extract the bastion from the common `ssh -W %h:%p bastion` ProxyCommand form
+    pub fn from_proxy_command(command: &str) -> Option<Self> {
+        let mut tokens = command.split_whitespace();
+        let program = tokens.next()?;
+        if !(program == "ssh" || program.ends_with("/ssh")) || !command.contains("-W") {
+            return None;
+        }
+
+        // Options that consume the following token
+        const WITH_ARG: &[&str] = &["-W", "-p", "-l", "-i", "-F", "-o", "-J", "-b", "-c", "-m"];
+
+        let mut port = None;
+        let mut user = None;
+        while let Some(token) = tokens.next() {
+            if WITH_ARG.contains(&token) {
+                let arg = tokens.next();
+                match token {
+                    "-p" => port = arg.and_then(|p| p.parse().ok()),
+                    "-l" => user = arg.map(|u| u.to_string()),
+                    _ => {}
+                }
+            } else if !token.starts_with('-') {
+                let mut hop = Self::parse(token)?;
+                hop.port = hop.port.or(port);
+                hop.user = hop.user.or(user);
+                return Some(hop);
+            }
+        }
+        None
+    }
+}
+
+impl fmt::Display for JumpHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ref user) = self.user {
+            write!(f, "{}@", user)?;
+        }
+        // Bracket IPv6 literals so the port separator stays unambiguous
+        if self.host.contains(':') {
+            write!(f, "[{}]", self.host)?;
+        } else {
+            write!(f, "{}", self.host)?;
+        }
+        if let Some(port) = self.port {
+            write!(f, ":{}", port)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a host was defined: the file holding its `Host` line (possibly an included one)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: PathBuf,
+    /// 1-based line number of the `Host` line
+    pub line: usize,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", tilde_path(&self.file), self.line)
+    }
+}
+
+/// Display a path with the home directory abbreviated to `~`
+pub fn tilde_path(path: &Path) -> String {
+    match dirs::home_dir().and_then(|home| path.strip_prefix(home).ok().map(|p| p.to_path_buf())) {
+        Some(relative) => format!("~/{}", relative.display()),
+        None => path.display().to_string(),
+    }
+}
+
+/// Identifies a server for the whole session, however the list is sorted.
+/// Reloading the SSH config keeps the ID of every host that's still there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ServerId(u64);
+
+impl ServerId {
+    /// An ID no other server in this process has
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        ServerId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
 }
 
 /// Represents an SSH server from the config
 #[derive(Debug, Clone)]
 pub struct Server {
+    pub id: ServerId,
     pub host: String,
     pub hostname: String,
     pub user: Option<String>,
     pub port: u16,
     pub identity_file: Option<String>,
     pub group: Option<String>,
+    /// Bastions to hop through, in connection order (from ProxyJump or an `ssh -W` ProxyCommand)
+    pub jump_chain: Vec<JumpHost>,
+    pub proxy_command: Option<String>,
+    /// Config file passed to ssh with `-F`; None when ssh's default config defines the host
+    pub config_file: Option<PathBuf>,
+    pub defined_at: Option<SourceLocation>,
+    // Metadata from `#ggoto` comments
+    /// All tags: from annotations, grouping rules and ggoto's tag file
+    pub tags: BTreeSet<String>,
+    /// Tags only ggoto's tag file sets; the rest come from the SSH config or rules
+    pub local_tags: BTreeSet<String>,
+    pub description: Option<String>,
+    pub owner: Option<String>,
 
     // Health and metrics
     pub latency: Option<Duration>,
     pub status: HealthStatus,
     pub metrics: Option<SystemMetrics>,
     pub last_check: Option<std::time::Instant>,
+    /// Custom checks from the settings that run on this server
+    pub checks: Vec<CheckDef>,
+    pub check_results: Vec<CheckResult>,
 }
 
 impl Server {
     pub fn new(host: String, hostname: String) -> Self {
         Self {
+            id: ServerId::next(),
             host,
             hostname,
             user: None,
             port: 22,
             identity_file: None,
             group: None,
+            jump_chain: Vec::new(),
+            proxy_command: None,
+            config_file: None,
+            defined_at: None,
+            tags: BTreeSet::new(),
+            local_tags: BTreeSet::new(),
+            description: None,
+            owner: None,
             latency: None,
             status: HealthStatus::Unknown,
             metrics: None,
             last_check: None,
+            checks: Vec::new(),
+            check_results: Vec::new(),
         }
     }
 
@@ -83,74 +521,280 @@ impl Server {
         self.latency.map(|d| d.as_millis() as u64)
     }
 
-    /// Check if metrics are stale (older than 30 seconds)
-    #[allow(dead_code)]
-    pub fn metrics_stale(&self) -> bool {
-        match self.last_check {
-            Some(t) => t.elapsed() > Duration::from_secs(30),
+    /// Whether both entries connect the same way (same target, credentials and route)
+    pub fn same_endpoint(&self, other: &Server) -> bool {
+        self.hostname == other.hostname
+            && self.user == other.user
+            && self.port == other.port
+            && self.identity_file == other.identity_file
+            && self.jump_chain == other.jump_chain
+            && self.proxy_command == other.proxy_command
+            && self.config_file == other.config_file
+    }
+
+    /// The first hop used to reach this server, if it sits behind a bastion
+    pub fn bastion(&self) -> Option<&str> {
+        self.jump_chain.first().map(|hop| hop.host.as_str())
+    }
+
+    /// Whether the server is in the group or one of its subgroups: `prod` covers `prod-web`
+    pub fn in_group(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        self.group.as_deref().is_some_and(|group| {
+            let group = group.to_lowercase();
+            group == name
+                || group
+                    .strip_prefix(&name)
+                    .is_some_and(|rest| rest.starts_with(['-', '/']))
+        })
+    }
+
+    /// Whether connections go through a bastion or ProxyCommand
+    pub fn is_proxied(&self) -> bool {
+        !self.jump_chain.is_empty() || self.proxy_command.is_some()
+    }
+
+    /// Short description of how this server is reached, e.g. `bastion → inner`
+    pub fn via_label(&self) -> Option<String> {
+        if !self.jump_chain.is_empty() {
+            Some(
+                self.jump_chain
+                    .iter()
+                    .map(|hop| hop.host.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" → "),
+            )
+        } else {
+            self.proxy_command.as_ref().map(|_| "(proxy)".to_string())
+        }
+    }
+
+    /// Replace the tags from ggoto's tag file, keeping those from the config and rules
+    pub fn set_local_tags(&mut self, tags: &BTreeSet<String>) {
+        let config_tags: BTreeSet<String> = self.tags.difference(&self.local_tags).cloned().collect();
+        self.local_tags = tags.difference(&config_tags).cloned().collect();
+        self.tags = config_tags.union(tags).cloned().collect();
+    }
+
+    /// Time since the last health check result
+    pub fn data_age(&self) -> Option<Duration> {
+        self.last_check.map(|t| t.elapsed())
+    }
+
+    /// Check if metrics are older than `max_age`
+    pub fn metrics_stale(&self, max_age: Duration) -> bool {
+        match self.data_age() {
+            Some(age) => age > max_age,
             None => true,
         }
     }
 }
 
-/// A group of servers with a common prefix
+/// A node in the group tree, e.g. `prod` with children `web` and `db`
 #[derive(Debug, Clone)]
 pub struct ServerGroup {
+    /// Label shown in the tree (`web`, or `dev-server` for a folded chain)
     pub name: String,
+    /// Full group path, e.g. `prod-web`; identifies the node across rebuilds
+    pub path: String,
     pub servers: Vec<usize>, // Indices into the main server list
+    pub children: Vec<ServerGroup>,
 }
 
 impl ServerGroup {
     pub fn new(name: String) -> Self {
         Self {
+            path: name.clone(),
             name,
             servers: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Build a group tree from servers and their group paths (see [`group_path`]).
+    /// Groups are sorted by name; a group with one subgroup and no servers of its
+    /// own is folded into that subgroup.
+    pub fn tree<I>(entries: I) -> Vec<ServerGroup>
+    where
+        I: IntoIterator<Item = (usize, Vec<String>)>,
+    {
+        let mut root = ServerGroup::new(String::new());
+        for (idx, segments) in entries {
+            let mut node = &mut root;
+            for segment in segments {
+                let path = format!("{}{}", node.path, segment);
+                let pos = match node.children.iter().position(|c| c.path == path) {
+                    Some(pos) => pos,
+                    None => {
+                        let name = segment.trim_start_matches(['-', '/']).to_string();
+                        node.children.push(ServerGroup {
+                            name,
+                            path,
+                            servers: Vec::new(),
+                            children: Vec::new(),
+                        });
+                        node.children.len() - 1
+                    }
+                };
+                node = &mut node.children[pos];
+            }
+            node.servers.push(idx);
+        }
+        let mut groups: Vec<ServerGroup> = root.children.into_iter().map(ServerGroup::fold).collect();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+        groups
+    }
+
+    /// Sort children and fold single-child chains, recursively
+    fn fold(mut self) -> ServerGroup {
+        self.children = self.children.into_iter().map(ServerGroup::fold).collect();
+        self.children.sort_by(|a, b| a.name.cmp(&b.name));
+        if self.servers.is_empty() && self.children.len() == 1 {
+            let child = self.children.pop().unwrap();
+            let name = format!("{}{}", self.name, &child.path[self.path.len()..]);
+            return ServerGroup { name, ..child };
         }
+        self
+    }
+
+    /// Servers in this group and all of its subgroups
+    pub fn all_servers(&self) -> Vec<usize> {
+        let mut servers = self.servers.clone();
+        for child in &self.children {
+            servers.extend(child.all_servers());
+        }
+        servers
+    }
+
+    /// Number of groups in this subtree, including this one
+    pub fn group_count(&self) -> usize {
+        1 + self.children.iter().map(|c| c.group_count()).sum::<usize>()
     }
 }
 
-/// Generate demo servers with fake data for screenshots/demos
-pub fn generate_demo_servers() -> Vec<Server> {
-    use std::time::Duration;
+/// Split a group name into tree levels, each keeping its leading separator:
+/// `prod/eu/web` -> `prod`, `/eu`, `/web`. Names without `/` split on `-`, so
+/// `prod-web` and `prod-db` share a `prod` parent.
+pub fn group_path(name: &str) -> Vec<String> {
+    let sep = if name.contains('/') { '/' } else { '-' };
+    let mut segments: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for c in name.chars() {
+        // Only split before a separator that follows a real segment character
+        if c == sep && !current.is_empty() && !current.ends_with(sep) {
+            segments.push(std::mem::take(&mut current));
+        }
+        current.push(c);
+    }
+    match segments.last_mut() {
+        // Trailing separators stay on the last segment
+        Some(last) if current.chars().all(|c| c == sep) => last.push_str(&current),
+        _ => segments.push(current),
+    }
+    segments
+}
 
-    let demo_data = [
-        ("prod-web-01", "10.0.1.1", "deploy", 12, 23.0, 4_200_000_000u64, 8_000_000_000u64),
-        ("prod-web-02", "10.0.1.2", "deploy", 15, 45.0, 3_800_000_000, 8_000_000_000),
-        ("prod-web-03", "10.0.1.3", "deploy", 18, 67.0, 5_100_000_000, 8_000_000_000),
-        ("prod-db-01", "10.0.2.1", "admin", 8, 12.0, 8_100_000_000, 16_000_000_000),
-        ("prod-db-02", "10.0.2.2", "admin", 9, 15.0, 7_800_000_000, 16_000_000_000),
-        ("staging-api", "staging.example.com", "developer", 45, 5.0, 2_100_000_000, 4_000_000_000),
-        ("staging-web", "staging-web.example.com", "developer", 48, 8.0, 1_800_000_000, 4_000_000_000),
-        ("dev-server", "dev.example.com", "dev", 120, 67.0, 1_200_000_000, 2_000_000_000),
-        ("ci-runner-01", "ci-01.internal", "ci", 25, 89.0, 3_500_000_000, 4_000_000_000),
-        ("ci-runner-02", "ci-02.internal", "ci", 28, 45.0, 2_800_000_000, 4_000_000_000),
-        ("monitoring", "monitor.example.com", "ops", 35, 15.0, 1_500_000_000, 2_000_000_000),
-        ("bastion", "bastion.example.com", "admin", 5, 2.0, 500_000_000, 1_000_000_000),
-    ];
+/// Health and load rollup for the servers under a group header
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GroupSummary {
+    pub servers: usize,
+    pub up: usize,
+    pub down: usize,
+    /// Average CPU usage over servers that reported metrics
+    pub avg_cpu: Option<f32>,
+    pub gpus: usize,
+}
 
-    demo_data
-        .iter()
-        .map(|(host, hostname, user, latency_ms, cpu, ram_used, ram_total)| {
-            let mut server = Server::new(host.to_string(), hostname.to_string());
-            server.user = Some(user.to_string());
-            server.latency = Some(Duration::from_millis(*latency_ms));
-            server.status = if *latency_ms < 100 {
-                HealthStatus::Healthy
-            } else {
-                HealthStatus::Degraded
-            };
-            server.metrics = Some(SystemMetrics {
-                cpu_cores: 4,
-                cpu_usage: *cpu,
-                ram_used: *ram_used,
-                ram_total: *ram_total,
-                gpus: vec![],
-                logged_in_users: vec!["user".to_string()],
-                load_average: (cpu / 25.0, cpu / 30.0, cpu / 35.0),
-                mosh_server_path: Some("mosh-server".to_string()), // Demo servers have mosh
-            });
-            server.last_check = Some(std::time::Instant::now());
-            server
-        })
-        .collect()
+impl GroupSummary {
+    pub fn new(servers: &[Server], indices: &[usize]) -> Self {
+        let mut summary = GroupSummary {
+            servers: indices.len(),
+            ..Default::default()
+        };
+        let mut cpu_total = 0.0;
+        let mut with_metrics = 0;
+        for server in indices.iter().filter_map(|&i| servers.get(i)) {
+            if server.status.is_up() {
+                summary.up += 1;
+            } else if server.status.is_down() {
+                summary.down += 1;
+            }
+            if let Some(ref m) = server.metrics {
+                cpu_total += m.cpu_usage;
+                with_metrics += 1;
+                summary.gpus += m.gpus.len();
+            }
+        }
+        if with_metrics > 0 {
+            summary.avg_cpu = Some(cpu_total / with_metrics as f32);
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gpu_owners() {
+        let process = |pid, user: Option<&str>, memory_used| GpuProcess {
+            pid,
+            user: user.map(String::from),
+            name: "python".to_string(),
+            memory_used,
+        };
+        let gpu = GpuInfo {
+            processes: vec![
+                process(10, Some("alice"), 2000),
+                process(11, Some("bob"), 5000),
+                process(12, None, 100),
+                process(13, Some("alice"), 4000),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(gpu.owners(), vec![("alice", 6000), ("bob", 5000), ("?", 100)]);
+        assert!(GpuInfo::default().owners().is_empty());
+    }
+
+    #[test]
+    fn test_classify_connect_errors() {
+        let kind = |message: &str, proxied: bool| ConnectError::classify(message, proxied).kind;
+
+        assert_eq!(
+            kind("ssh: Could not resolve hostname web-9: Name or service not known", false),
+            FailureKind::Dns
+        );
+        assert_eq!(
+            kind("ssh: connect to host 10.0.0.9 port 22: Connection timed out", false),
+            FailureKind::Timeout
+        );
+        assert_eq!(
+            kind("ssh: connect to host 10.0.0.9 port 2222: Connection refused", false),
+            FailureKind::Refused
+        );
+        assert_eq!(
+            kind("deploy@10.0.0.9: Permission denied (publickey).", false),
+            FailureKind::Auth
+        );
+        let changed = "@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@\n\
+                       @    WARNING: REMOTE HOST IDENTIFICATION HAS CHANGED!     @\n\
+                       Host key verification failed.";
+        assert_eq!(kind(changed, false), FailureKind::HostKey);
+
+        // A broken jump hides the cause behind the proxy's failure
+        let jump = "channel 0: open failed: connect failed: Name or service not known\n\
+                    stdio forwarding failed\n\
+                    Connection closed by UNKNOWN port 65535";
+        assert_eq!(kind(jump, true), FailureKind::Proxy);
+        assert_eq!(kind(jump, false), FailureKind::Dns);
+
+        let error = ConnectError::classify("  kex_exchange_identification: read: reset\n", false);
+        assert_eq!(error.kind, FailureKind::Other);
+        assert_eq!(error.message, "kex_exchange_identification: read: reset");
+        assert_eq!(
+            HealthStatus::Unreachable(error).label(),
+            "Unreachable (error)"
+        );
+    }
 }
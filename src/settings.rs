@@ -0,0 +1,241 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+
+use crate::checks::CheckDef;
+use crate::server::Server;
+use crate::ssh::config::wildcard_match;
+
+/// Seconds between automatic health checks when the settings don't say
+const DEFAULT_REFRESH_INTERVAL_SECS: u64 = 60;
+
+/// ggoto's own settings, read from `~/.config/ggoto/config.json`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Grouping rules, tried in order before the numeric-suffix heuristic
+    pub groups: Vec<GroupRule>,
+    /// Seconds between automatic health checks of each server; 0 turns them off
+    pub refresh_interval: Option<u64>,
+    /// Share one SSH connection per server between checks, commands, tunnels
+    /// and sessions (on unless set to false)
+    pub multiplex: Option<bool>,
+    /// How ggoto reaches servers for checks, commands and tunnels
+    pub backend: BackendKind,
+    /// Custom health checks, run along with the metrics
+    pub checks: Vec<CheckDef>,
+}
+
+/// Which SSH implementation runs checks, commands and tunnels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// The `ssh` binary
+    #[default]
+    Ssh,
+    /// libssh2 inside ggoto, falling back to `ssh` for hosts it can't handle
+    Native,
+}
+
+/// A rule that puts matching servers in a group and/or tags them
+#[derive(Debug, Clone, Deserialize)]
+pub struct GroupRule {
+    /// Group name. Regex rules may leave it out and name the group with a `group` capture.
+    /// Rules with only tags don't affect grouping.
+    pub name: Option<String>,
+    /// Tags added to every matching server
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(flatten)]
+    pub matcher: GroupMatcher,
+}
+
+/// What a grouping rule matches on
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupMatcher {
+    /// Regex on the host alias, e.g. `^gpu-(?P<group>[a-z0-9]+)-\d+$`
+    #[serde(deserialize_with = "deserialize_regex")]
+    Regex(Regex),
+    /// Exact host aliases
+    Hosts(Vec<String>),
+    /// Glob pattern on the resolved hostname, e.g. `10.0.5.*`
+    Hostname(String),
+    /// Domain suffix of the resolved hostname, e.g. `lab.example.com`
+    Domain(String),
+}
+
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+impl GroupRule {
+    /// Check if the rule applies to a server
+    pub fn matches(&self, server: &Server) -> bool {
+        match &self.matcher {
+            GroupMatcher::Regex(re) => re.is_match(&server.host),
+            GroupMatcher::Hosts(hosts) => hosts.contains(&server.host),
+            GroupMatcher::Hostname(pattern) => wildcard_match(pattern, &server.hostname),
+            GroupMatcher::Domain(suffix) => {
+                let suffix = suffix.trim_start_matches('.').to_lowercase();
+                let hostname = server.hostname.to_lowercase();
+                hostname == suffix || hostname.ends_with(&format!(".{}", suffix))
+            }
+        }
+    }
+
+    /// The group this rule puts a server in, if it matches
+    pub fn group_for(&self, server: &Server) -> Option<String> {
+        if let GroupMatcher::Regex(re) = &self.matcher {
+            let caps = re.captures(&server.host)?;
+            if let Some(group) = caps.name("group") {
+                return Some(group.as_str().to_string());
+            }
+        }
+        if self.matches(server) {
+            self.name.clone()
+        } else {
+            None
+        }
+    }
+
+    /// Rules need a name unless a regex capture provides it or they only add tags
+    fn validate(&self) -> Result<()> {
+        let named_by_capture = match &self.matcher {
+            GroupMatcher::Regex(re) => re.capture_names().any(|n| n == Some("group")),
+            _ => false,
+        };
+        if self.name.is_none() && !named_by_capture && self.tags.is_empty() {
+            anyhow::bail!("needs a \"name\", \"tags\" or a regex with a `group` capture");
+        }
+        Ok(())
+    }
+}
+
+/// Add the tags of every rule a server matches
+pub fn apply_rule_tags(servers: &mut [Server], rules: &[GroupRule]) {
+    for server in servers.iter_mut() {
+        let tags: Vec<String> = rules
+            .iter()
+            .filter(|rule| rule.matches(server))
+            .flat_map(|rule| rule.tags.iter().cloned())
+            .collect();
+        server.tags.extend(tags);
+    }
+}
+
+impl Settings {
+    /// Get the settings file path
+    pub fn settings_path() -> Result<PathBuf> {
+        let config_dir =
+            dirs::config_dir().ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?;
+        Ok(config_dir.join("ggoto").join("config.json"))
+    }
+
+    /// Load settings from disk; a missing file means defaults
+    pub fn load() -> Result<Self> {
+        let path = Self::settings_path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)?;
+        Self::parse(&content).with_context(|| format!("Invalid settings in {:?}", path))
+    }
+
+    /// Time between automatic health checks, or `None` if they're turned off
+    pub fn refresh_interval(&self) -> Option<Duration> {
+        match self.refresh_interval.unwrap_or(DEFAULT_REFRESH_INTERVAL_SECS) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    /// Whether to reuse SSH connections through ControlMaster sockets
+    pub fn multiplex(&self) -> bool {
+        self.multiplex.unwrap_or(true)
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let settings: Settings = serde_json::from_str(content)?;
+        for (i, rule) in settings.groups.iter().enumerate() {
+            rule.validate()
+                .with_context(|| format!("Grouping rule #{}", i + 1))?;
+        }
+        CheckDef::validate(&settings.checks)?;
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssh::group_servers;
+
+    #[test]
+    fn test_group_rules() {
+        let settings = Settings::parse(
+            r#"{
+                "groups": [
+                    { "name": "db", "hosts": ["db-primary", "db-replica"] },
+                    { "regex": "^gpu-(?P<group>[a-z0-9]+)-\\d+$" },
+                    { "name": "lab", "domain": "lab.example.com" },
+                    { "name": "office", "hostname": "192.168.1.*" },
+                    { "tags": ["gpu"], "regex": "^gpu-" }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let mut servers = vec![
+            Server::new("db-primary".to_string(), "10.0.0.1".to_string()),
+            Server::new("db-replica".to_string(), "10.0.0.2".to_string()),
+            Server::new("gpu-a100-1".to_string(), "10.0.1.1".to_string()),
+            Server::new("bench".to_string(), "bench.LAB.example.com".to_string()),
+            Server::new("printer".to_string(), "192.168.1.20".to_string()),
+            Server::new("web-01".to_string(), "10.0.2.1".to_string()),
+        ];
+        group_servers(&mut servers, &settings.groups);
+        apply_rule_tags(&mut servers, &settings.groups);
+
+        let groups: Vec<&str> = servers
+            .iter()
+            .map(|s| s.group.as_deref().unwrap())
+            .collect();
+        // The last server falls through to the numeric-suffix heuristic
+        assert_eq!(groups, vec!["db", "db", "a100", "lab", "office", "web"]);
+
+        // Tag-only rules tag every match without changing its group
+        let tagged: Vec<&str> = servers
+            .iter()
+            .filter(|s| s.tags.contains("gpu"))
+            .map(|s| s.host.as_str())
+            .collect();
+        assert_eq!(tagged, vec!["gpu-a100-1"]);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        assert!(Settings::parse(r#"{ "groups": [ { "hosts": ["a"] } ] }"#).is_err());
+        assert!(Settings::parse(r#"{ "groups": [ { "name": "x", "regex": "(" } ] }"#).is_err());
+        assert!(Settings::parse("{}").unwrap().groups.is_empty());
+    }
+
+    #[test]
+    fn test_refresh_interval() {
+        let default = Settings::parse("{}").unwrap();
+        assert_eq!(default.refresh_interval(), Some(Duration::from_secs(60)));
+        let off = Settings::parse(r#"{ "refresh_interval": 0 }"#).unwrap();
+        assert_eq!(off.refresh_interval(), None);
+        assert_eq!(default.backend, BackendKind::Ssh);
+        let native = Settings::parse(r#"{ "backend": "native" }"#).unwrap();
+        assert_eq!(native.backend, BackendKind::Native);
+    }
+}
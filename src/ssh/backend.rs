@@ -0,0 +1,132 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+
+use anyhow::Result;
+
+use crate::server::Server;
+use crate::ssh::connection::ExternalSsh;
+use crate::ssh::native::NativeSsh;
+use crate::tunnel::TunnelHandle;
+
+/// A boxed future, so backends can be used as trait objects
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Everything ggoto does on servers goes through a backend: the real one runs
+/// `ssh` or libssh2, the scripted one answers from a script (tests and demo mode)
+pub trait Backend: Send + Sync {
+    /// Whether connections to the server are kept open between commands
+    fn keeps_connections(&self, server: &Server) -> bool;
+
+    /// Whether a connection to the server is open right now
+    fn is_connected<'a>(&'a self, server: &'a Server) -> BoxFuture<'a, bool>;
+
+    /// Run a command on the server and return its output
+    fn run_command<'a>(
+        &'a self,
+        server: &'a Server,
+        command: &'a str,
+    ) -> BoxFuture<'a, Result<String>>;
+
+    /// Forward `local_port` on localhost to `remote_host:remote_port` through the server
+    fn open_forward(
+        &self,
+        server: &Server,
+        local_port: u16,
+        remote_host: &str,
+        remote_port: u16,
+    ) -> Result<TunnelHandle>;
+
+    /// Hand the terminal to an interactive session, with mosh if asked, and
+    /// return when it ends. `env` is set in the remote login shell.
+    fn launch_session(&self, server: &Server, use_mosh: bool, env: &[(String, String)]) -> Result<()>;
+
+    /// Why interactive sessions can't be started, if they can't. Checked before
+    /// the TUI is torn down, so a refusal can be shown as a status instead.
+    fn session_refusal(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Returned by the native backend when a host needs something only the `ssh`
+/// binary does, such as accepting a new host key or an unusual auth setup
+#[derive(Debug)]
+pub struct NeedsExternal(pub String);
+
+impl fmt::Display for NeedsExternal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for NeedsExternal {}
+
+/// The real backend: libssh2 for the hosts it can handle when that's turned on,
+/// the `ssh` binary for everything else
+#[derive(Default)]
+pub struct SshBackend {
+    native: Option<NativeSsh>,
+}
+
+impl SshBackend {
+    pub fn new(native: bool) -> Self {
+        Self {
+            native: native.then(NativeSsh::default),
+        }
+    }
+
+    /// The backend for a server
+    fn pick(&self, server: &Server) -> &dyn Backend {
+        match self.native {
+            Some(ref native) if native.supports(server) => native,
+            _ => &ExternalSsh,
+        }
+    }
+}
+
+impl Backend for SshBackend {
+    fn keeps_connections(&self, server: &Server) -> bool {
+        self.pick(server).keeps_connections(server)
+    }
+
+    fn is_connected<'a>(&'a self, server: &'a Server) -> BoxFuture<'a, bool> {
+        self.pick(server).is_connected(server)
+    }
+
+    fn run_command<'a>(
+        &'a self,
+        server: &'a Server,
+        command: &'a str,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            match self.pick(server).run_command(server, command).await {
+                // The native backend hands hosts it can't handle over to ssh
+                Err(e) if e.is::<NeedsExternal>() => ExternalSsh.run_command(server, command).await,
+                result => result,
+            }
+        })
+    }
+
+    fn open_forward(
+        &self,
+        server: &Server,
+        local_port: u16,
+        remote_host: &str,
+        remote_port: u16,
+    ) -> Result<TunnelHandle> {
+        match self
+            .pick(server)
+            .open_forward(server, local_port, remote_host, remote_port)
+        {
+            Err(e) if e.is::<NeedsExternal>() => {
+                ExternalSsh.open_forward(server, local_port, remote_host, remote_port)
+            }
+            result => result,
+        }
+    }
+
+    fn launch_session(&self, server: &Server, use_mosh: bool, env: &[(String, String)]) -> Result<()> {
+        // Interactive sessions always use the ssh binary
+        ExternalSsh.launch_session(server, use_mosh, env)
+    }
+}
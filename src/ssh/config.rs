@@ -1,11 +1,16 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
 
 use anyhow::{Context, Result};
 use glob::glob;
 use regex::Regex;
 
-use crate::server::Server;
+use crate::server::{group_path, JumpHost, Server, ServerGroup, SourceLocation};
+use crate::settings::GroupRule;
 
 const MAX_INCLUDE_DEPTH: u32 = 10;
 
@@ -18,17 +23,159 @@ const GIT_HOSTING_DOMAINS: &[&str] = &[
     "codeberg.org",
 ];
 
-/// Parse the SSH config file and extract hosts
-pub fn parse_ssh_config() -> Result<Vec<Server>> {
-    let config_path = get_ssh_config_path()?;
-    let content = resolve_includes(&config_path, 0)?;
+/// Comment prefix for ggoto metadata inside a block, e.g.
+/// `#ggoto tags=gpu,prod desc="A100 training box" owner=ml-team`
+const ANNOTATION_PREFIX: &str = "#ggoto";
 
-    let mut servers = parse_config_content(&content)?;
+/// Option keys annotations are stored under; the `#` keeps them apart from ssh options
+const TAGS_KEY: &str = "#tags";
+const DESCRIPTION_KEY: &str = "#desc";
+const OWNER_KEY: &str = "#owner";
+const GROUP_KEY: &str = "#group";
+
+/// OpenSSH's system-wide client config
+const SYSTEM_CONFIG_PATH: &str = "/etc/ssh/ssh_config";
+
+/// Environment variable holding alternate config files to read instead of ~/.ssh/config
+pub const CONFIG_ENV_VAR: &str = "GGOTO_SSH_CONFIG";
+
+/// A config ggoto reads hosts from. Each source is evaluated on its own, the way
+/// a single `ssh` invocation would see it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigSource {
+    /// Files evaluated in order, e.g. ~/.ssh/config followed by /etc/ssh/ssh_config
+    pub files: Vec<PathBuf>,
+    /// File passed to ssh with `-F` when connecting; None for ssh's default config
+    pub ssh_config: Option<PathBuf>,
+}
+
+impl ConfigSource {
+    /// The config ssh reads when run without `-F`
+    fn default_config(include_system: bool) -> Result<Self> {
+        let mut files = vec![get_ssh_config_path()?];
+        if include_system {
+            files.push(PathBuf::from(SYSTEM_CONFIG_PATH));
+        }
+        Ok(Self {
+            files,
+            ssh_config: None,
+        })
+    }
+
+    /// A file that ssh has to be pointed at with `-F`
+    fn file(path: PathBuf) -> Self {
+        Self {
+            files: vec![path.clone()],
+            ssh_config: Some(path),
+        }
+    }
+
+    /// Short description for error messages
+    pub fn describe(&self) -> String {
+        self.files
+            .iter()
+            .map(|f| f.display().to_string())
+            .collect::<Vec<_>>()
+            .join(" + ")
+    }
+}
+
+/// Work out which configs to read. `GGOTO_SSH_CONFIG` (a `:`-separated list) replaces
+/// ~/.ssh/config; files given with `--config` are read in addition.
+/// The system config only applies to the default source, since `ssh -F` skips it.
+pub fn config_sources(extra: &[PathBuf], include_system: bool) -> Result<Vec<ConfigSource>> {
+    let mut sources = match std::env::var_os(CONFIG_ENV_VAR) {
+        Some(value) if !value.is_empty() => std::env::split_paths(&value)
+            .filter(|p| !p.as_os_str().is_empty())
+            .map(|p| ConfigSource::file(PathBuf::from(expand_tilde(&p.to_string_lossy()))))
+            .collect(),
+        _ => vec![ConfigSource::default_config(include_system)?],
+    };
+
+    for path in extra {
+        let source = ConfigSource::file(path.clone());
+        if !sources.contains(&source) {
+            sources.push(source);
+        }
+    }
+
+    Ok(sources)
+}
+
+/// The files a parse depended on and their modification times when they were read.
+/// Missing files and directories searched by `Include` globs are tracked too, so
+/// creating a config or dropping a file into an included directory is noticed.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSnapshot {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl ConfigSnapshot {
+    /// Remember a path and its current modification time
+    fn record(&mut self, path: &Path) {
+        if self.files.iter().any(|(p, _)| p == path) {
+            return;
+        }
+        self.files.push((path.to_path_buf(), modified_time(path)));
+    }
+
+    /// Whether any tracked path was modified, created or removed since it was recorded
+    pub fn is_stale(&self) -> bool {
+        self.files
+            .iter()
+            .any(|(path, modified)| modified_time(path) != *modified)
+    }
+
+    /// The same paths with their current modification times
+    pub fn refreshed(&self) -> Self {
+        Self {
+            files: self
+                .files
+                .iter()
+                .map(|(path, _)| (path.clone(), modified_time(path)))
+                .collect(),
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Parse the SSH config sources and extract hosts, along with a snapshot of
+/// the files that were read. When a host is defined in several sources, the first one wins.
+pub fn parse_ssh_config(
+    sources: &[ConfigSource],
+    parse_options: &ParseOptions,
+) -> Result<(Vec<Server>, ConfigSnapshot)> {
+    let mut servers: Vec<Server> = Vec::new();
+    let mut snapshot = ConfigSnapshot::default();
+    let state = ParseState::new(parse_options);
+
+    for source in sources {
+        let mut lines = Vec::new();
+        for file in &source.files {
+            // ssh's default files are optional, but an explicitly chosen one has to exist
+            if source.ssh_config.is_none() && !file.exists() {
+                snapshot.record(file);
+                continue;
+            }
+            lines.extend(resolve_includes(file, &include_base_dir(file)?, 0, &mut snapshot)?);
+        }
+
+        for mut server in parse_config_lines(&lines, &state)? {
+            if servers.iter().any(|s| s.host == server.host) {
+                continue;
+            }
+            server.config_file = source.ssh_config.clone();
+            servers.push(server);
+        }
+    }
 
     // Filter out known git hosting services
     servers.retain(|s| !GIT_HOSTING_DOMAINS.contains(&s.hostname.as_str()));
 
-    Ok(servers)
+    Ok((servers, snapshot))
 }
 
 /// Get the path to the SSH config file
@@ -37,35 +184,70 @@ fn get_ssh_config_path() -> Result<PathBuf> {
     Ok(home.join(".ssh").join("config"))
 }
 
-/// Read an SSH config file and recursively resolve Include directives
-fn resolve_includes(path: &Path, depth: u32) -> Result<String> {
+/// Directory relative `Include` paths are resolved against: /etc/ssh for the
+/// system config, ~/.ssh for everything else (including files given with `-F`)
+fn include_base_dir(config: &Path) -> Result<PathBuf> {
+    if config == Path::new(SYSTEM_CONFIG_PATH) {
+        return Ok(PathBuf::from("/etc/ssh"));
+    }
+    Ok(dirs::home_dir()
+        .context("Could not determine home directory")?
+        .join(".ssh"))
+}
+
+/// A config line together with the file and line number it was read from
+#[derive(Debug, Clone)]
+struct ConfigLine {
+    text: String,
+    file: Rc<Path>,
+    number: usize,
+}
+
+/// Split file content into lines tagged with their origin
+fn config_lines(content: &str, file: &Path) -> Vec<ConfigLine> {
+    let file: Rc<Path> = Rc::from(file);
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| ConfigLine {
+            text: line.to_string(),
+            file: file.clone(),
+            number: i + 1,
+        })
+        .collect()
+}
+
+/// Read an SSH config file and recursively resolve Include directives,
+/// keeping track of where every line came from and recording every file read
+fn resolve_includes(
+    path: &Path,
+    base_dir: &Path,
+    depth: u32,
+    snapshot: &mut ConfigSnapshot,
+) -> Result<Vec<ConfigLine>> {
     if depth > MAX_INCLUDE_DEPTH {
-        return Ok(String::new());
+        return Ok(Vec::new());
     }
 
+    snapshot.record(path);
+
     let content = fs::read_to_string(path)
         .with_context(|| format!("Failed to read SSH config at {:?}", path))?;
 
-    let ssh_dir = dirs::home_dir()
-        .context("Could not determine home directory")?
-        .join(".ssh");
-
-    let mut result = String::new();
+    let mut result = Vec::new();
 
-    for line in content.lines() {
-        let trimmed = line.trim();
+    for line in config_lines(&content, path) {
+        let trimmed = line.text.trim();
 
         // Skip comments and empty lines for Include detection only
         if trimmed.is_empty() || trimmed.starts_with('#') {
-            result.push_str(line);
-            result.push('\n');
+            result.push(line);
             continue;
         }
 
         let parts: Vec<&str> = trimmed.splitn(2, |c: char| c.is_whitespace() || c == '=').collect();
         if parts.len() < 2 {
-            result.push_str(line);
-            result.push('\n');
+            result.push(line);
             continue;
         }
 
@@ -83,16 +265,25 @@ fn resolve_includes(path: &Path, depth: u32) -> Result<String> {
                     continue;
                 }
             } else {
-                ssh_dir.join(value).to_string_lossy().to_string()
+                base_dir.join(value).to_string_lossy().to_string()
             };
 
+            // New files matching the pattern show up as a change to its directory
+            if let Some(dir) = Path::new(&pattern).parent() {
+                if is_pattern(&pattern) {
+                    snapshot.record(dir);
+                } else {
+                    snapshot.record(Path::new(&pattern));
+                }
+            }
+
             // Expand glob patterns
             match glob(&pattern) {
                 Ok(paths) => {
                     for entry in paths.flatten() {
                         if entry.is_file() {
-                            if let Ok(included) = resolve_includes(&entry, depth + 1) {
-                                result.push_str(&included);
+                            if let Ok(included) = resolve_includes(&entry, base_dir, depth + 1, snapshot) {
+                                result.extend(included);
                             }
                         }
                     }
@@ -101,140 +292,587 @@ fn resolve_includes(path: &Path, depth: u32) -> Result<String> {
                     // If glob fails, try as a plain path
                     let plain = PathBuf::from(&pattern);
                     if plain.is_file() {
-                        if let Ok(included) = resolve_includes(&plain, depth + 1) {
-                            result.push_str(&included);
+                        if let Ok(included) = resolve_includes(&plain, base_dir, depth + 1, snapshot) {
+                            result.extend(included);
                         }
                     }
                 }
             }
         } else {
-            result.push_str(line);
-            result.push('\n');
+            result.push(line);
         }
     }
 
     Ok(result)
 }
 
-/// Parse the content of an SSH config file
-fn parse_config_content(content: &str) -> Result<Vec<Server>> {
-    let mut servers = Vec::new();
-    let mut current_hosts: Vec<String> = Vec::new();
-    let mut current_hostname: Option<String> = None;
-    let mut current_user: Option<String> = None;
-    let mut current_port: Option<u16> = None;
-    let mut current_identity: Option<String> = None;
+/// Options controlling how the SSH config is evaluated
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Run `Match exec` commands locally. Off by default since it executes
+    /// arbitrary commands from the config; unevaluated `exec` criteria never match.
+    pub allow_match_exec: bool,
+}
+
+/// What every host in one parse is evaluated with
+struct ParseState<'a> {
+    options: &'a ParseOptions,
+    local_user: String,
+    /// Results of `Match exec` commands, keyed by the command after token expansion
+    /// so each host gets its own entry. Both passes and every source reuse them
+    /// instead of running the same command again.
+    exec_results: RefCell<HashMap<String, bool>>,
+}
 
-    for line in content.lines() {
-        let line = line.trim();
+impl<'a> ParseState<'a> {
+    fn new(options: &'a ParseOptions) -> Self {
+        Self {
+            options,
+            local_user: local_username(),
+            exec_results: RefCell::default(),
+        }
+    }
+}
 
-        // Skip empty lines and comments
-        if line.is_empty() || line.starts_with('#') {
+/// A single criterion on a `Match` line, e.g. `host *.prod` or `!user root`
+#[derive(Debug, Clone, PartialEq)]
+struct MatchCriterion {
+    negated: bool,
+    kind: MatchKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum MatchKind {
+    All,
+    Canonical,
+    Final,
+    Host(Vec<String>),
+    OriginalHost(Vec<String>),
+    User(Vec<String>),
+    LocalUser(Vec<String>),
+    Exec(String),
+    /// Criteria we can't evaluate (e.g. `localnetwork`, `tagged`) never match
+    Unsupported,
+}
+
+/// What decides whether a block applies to a host
+#[derive(Debug, Clone)]
+enum BlockCondition {
+    Host(Vec<String>),
+    Match(Vec<MatchCriterion>),
+}
+
+/// A `Host` or `Match` section of the config and the options set inside it, in file order.
+/// Options that appear before the first `Host` line live in an implicit `Host *` block.
+#[derive(Debug, Clone)]
+struct ConfigBlock {
+    condition: BlockCondition,
+    options: Vec<(String, String)>,
+}
+
+/// State a block is evaluated against while resolving a single host
+struct MatchContext<'a> {
+    /// Name used for `Host` patterns: the alias, or the resolved hostname in the final pass
+    host: &'a str,
+    /// The alias as it was given on the command line
+    original_host: &'a str,
+    /// Options obtained so far
+    options: &'a HashMap<String, String>,
+    final_pass: bool,
+    state: &'a ParseState<'a>,
+}
+
+impl MatchContext<'_> {
+    fn hostname(&self) -> String {
+        self.options
+            .get("hostname")
+            .map(|h| expand_tokens(h, &[('h', self.original_host)]))
+            .unwrap_or_else(|| self.original_host.to_string())
+    }
+
+    fn user(&self) -> &str {
+        self.options
+            .get("user")
+            .map(|u| u.as_str())
+            .unwrap_or(&self.state.local_user)
+    }
+}
+
+impl ConfigBlock {
+    fn new(condition: BlockCondition) -> Self {
+        Self {
+            condition,
+            options: Vec::new(),
+        }
+    }
+
+    /// Whether this block applies in the given context
+    fn matches(&self, ctx: &MatchContext) -> bool {
+        match &self.condition {
+            BlockCondition::Host(patterns) => match_pattern_list(ctx.host, patterns),
+            BlockCondition::Match(criteria) => criteria.iter().all(|c| c.matches(ctx)),
+        }
+    }
+
+    /// Whether this block asks for a second, final evaluation pass
+    fn wants_final_pass(&self) -> bool {
+        match &self.condition {
+            BlockCondition::Match(criteria) => criteria
+                .iter()
+                .any(|c| matches!(c.kind, MatchKind::Final | MatchKind::Canonical)),
+            BlockCondition::Host(_) => false,
+        }
+    }
+}
+
+impl MatchCriterion {
+    fn matches(&self, ctx: &MatchContext) -> bool {
+        let result = match &self.kind {
+            MatchKind::All => true,
+            // We don't canonicalize, so the final pass stands in for the canonical one
+            MatchKind::Canonical | MatchKind::Final => ctx.final_pass,
+            MatchKind::Host(patterns) => match_pattern_list(&ctx.hostname(), patterns),
+            MatchKind::OriginalHost(patterns) => match_pattern_list(ctx.original_host, patterns),
+            MatchKind::User(patterns) => match_pattern_list(ctx.user(), patterns),
+            MatchKind::LocalUser(patterns) => match_pattern_list(&ctx.state.local_user, patterns),
+            MatchKind::Exec(command) => {
+                if !ctx.state.options.allow_match_exec {
+                    return false;
+                }
+                run_match_exec(command, ctx)
+            }
+            MatchKind::Unsupported => return false,
+        };
+        result != self.negated
+    }
+}
+
+/// Run a `Match exec` command through the shell; exit status 0 means a match
+fn run_match_exec(command: &str, ctx: &MatchContext) -> bool {
+    let hostname = ctx.hostname();
+    let port = ctx.options.get("port").map(|p| p.as_str()).unwrap_or("22");
+    let home = dirs::home_dir()
+        .map(|h| h.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let command = expand_tokens(
+        command,
+        &[
+            ('h', &hostname),
+            ('n', ctx.original_host),
+            ('p', port),
+            ('r', ctx.user()),
+            ('u', &ctx.state.local_user),
+            ('d', &home),
+        ],
+    );
+
+    if let Some(&matched) = ctx.state.exec_results.borrow().get(&command) {
+        return matched;
+    }
+    let matched = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(&command)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+    ctx.state.exec_results.borrow_mut().insert(command, matched);
+    matched
+}
+
+/// Split a value into whitespace-separated arguments, honoring double quotes
+fn split_args(value: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut has_arg = false;
+
+    for c in value.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                has_arg = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if has_arg {
+                    args.push(std::mem::take(&mut current));
+                    has_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                has_arg = true;
+            }
+        }
+    }
+    if has_arg {
+        args.push(current);
+    }
+
+    args
+}
+
+/// Parse the criteria of a `Match` line
+fn parse_match_criteria(value: &str) -> Vec<MatchCriterion> {
+    let mut criteria = Vec::new();
+    let mut args = split_args(value).into_iter();
+
+    while let Some(arg) = args.next() {
+        let (negated, keyword) = match arg.strip_prefix('!') {
+            Some(rest) => (true, rest.to_lowercase()),
+            None => (false, arg.to_lowercase()),
+        };
+
+        let patterns = |arg: Option<String>| -> Vec<String> {
+            arg.map(|a| a.split(',').map(|p| p.to_string()).collect())
+                .unwrap_or_default()
+        };
+
+        let kind = match keyword.as_str() {
+            "all" => MatchKind::All,
+            "canonical" => MatchKind::Canonical,
+            "final" => MatchKind::Final,
+            "host" => MatchKind::Host(patterns(args.next())),
+            "originalhost" => MatchKind::OriginalHost(patterns(args.next())),
+            "user" => MatchKind::User(patterns(args.next())),
+            "localuser" => MatchKind::LocalUser(patterns(args.next())),
+            "exec" => MatchKind::Exec(args.next().unwrap_or_default()),
+            _ => {
+                // Skip the argument of criteria we don't understand
+                args.next();
+                MatchKind::Unsupported
+            }
+        };
+
+        criteria.push(MatchCriterion { negated, kind });
+    }
+
+    criteria
+}
+
+/// Parse a `#ggoto key=value ...` comment into block options.
+/// Returns None for lines that aren't annotations; unknown keys are ignored.
+fn parse_annotation(line: &str) -> Option<Vec<(String, String)>> {
+    let rest = line.strip_prefix(ANNOTATION_PREFIX)?;
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let annotations = split_args(rest)
+        .into_iter()
+        .filter_map(|arg| {
+            let (key, value) = arg.split_once('=')?;
+            let key = match key.to_lowercase().as_str() {
+                "tags" | "tag" => TAGS_KEY,
+                "desc" | "description" => DESCRIPTION_KEY,
+                "owner" => OWNER_KEY,
+                "group" => GROUP_KEY,
+                _ => return None,
+            };
+            Some((key.to_string(), value.to_string()))
+        })
+        .collect();
+    Some(annotations)
+}
+
+/// Name of the local user, as used by `Match localuser` and the default remote user
+pub(crate) fn local_username() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("LOGNAME"))
+        .unwrap_or_default()
+}
+
+/// Split a config line into a lowercased keyword and its value.
+/// Accepts `Key Value`, `Key=Value` and `Key = Value`.
+pub(crate) fn split_key_value(line: &str) -> Option<(String, String)> {
+    let line = line.trim();
+    let key_end = line.find(|c: char| c.is_whitespace() || c == '=')?;
+    let (key, rest) = line.split_at(key_end);
+    let rest = rest.trim_start();
+    let rest = rest.strip_prefix('=').unwrap_or(rest).trim();
+    if key.is_empty() || rest.is_empty() {
+        return None;
+    }
+    Some((key.to_lowercase(), rest.to_string()))
+}
+
+/// Returns true if the pattern contains glob metacharacters or is a negation
+pub(crate) fn is_pattern(host: &str) -> bool {
+    host.contains('*') || host.contains('?') || host.starts_with('!')
+}
+
+/// Match a string against an OpenSSH glob pattern (`*` and `?`), case-insensitively
+pub(crate) fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            // Let the last `*` swallow one more character and retry
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Match a host against a pattern list with OpenSSH semantics:
+/// a matching negated pattern (`!bastion`) rejects the host outright,
+/// otherwise at least one positive pattern has to match
+fn match_pattern_list(host: &str, patterns: &[String]) -> bool {
+    let mut matched = false;
+    for pattern in patterns {
+        if let Some(negated) = pattern.strip_prefix('!') {
+            if wildcard_match(negated, host) {
+                return false;
+            }
+        } else if wildcard_match(pattern, host) {
+            matched = true;
+        }
+    }
+    matched
+}
+
+/// Expand `~/` to the home directory
+fn expand_tilde(value: &str) -> String {
+    if let Some(stripped) = value.strip_prefix("~/") {
+        if let Some(home) = dirs::home_dir() {
+            return home.join(stripped).to_string_lossy().to_string();
+        }
+    }
+    value.to_string()
+}
+
+/// Expand OpenSSH `%` tokens (e.g. `%h`) using the given substitutions.
+/// `%%` becomes a literal `%`; unknown tokens are left untouched.
+fn expand_tokens(value: &str, tokens: &[(char, &str)]) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
             continue;
         }
+        match chars.next() {
+            Some('%') => result.push('%'),
+            Some(t) => match tokens.iter().find(|(token, _)| *token == t) {
+                Some((_, replacement)) => result.push_str(replacement),
+                None => {
+                    result.push('%');
+                    result.push(t);
+                }
+            },
+            None => result.push('%'),
+        }
+    }
 
-        // Parse key-value pairs
-        let parts: Vec<&str> = line
-            .splitn(2, |c: char| c.is_whitespace() || c == '=')
-            .collect();
-        if parts.len() < 2 {
+    result
+}
+
+/// Parse resolved config lines into servers
+fn parse_config_lines(lines: &[ConfigLine], state: &ParseState) -> Result<Vec<Server>> {
+    let (blocks, hosts) = parse_blocks(lines);
+
+    Ok(hosts
+        .into_iter()
+        .map(|(host, location)| {
+            let mut server = resolve_host(&host, &blocks, state);
+            server.defined_at = Some(location);
+            server
+        })
+        .collect())
+}
+
+/// Split config lines into `Host`/`Match` blocks and collect the literal host aliases,
+/// each with the location of the first `Host` line naming it
+fn parse_blocks(lines: &[ConfigLine]) -> (Vec<ConfigBlock>, Vec<(String, SourceLocation)>) {
+    let mut blocks = vec![ConfigBlock::new(BlockCondition::Host(vec!["*".to_string()]))];
+    let mut hosts: Vec<(String, SourceLocation)> = Vec::new();
+
+    for config_line in lines {
+        let line = config_line.text.trim();
+
+        // Annotations apply to hosts matching the block, like options do
+        if let Some(annotations) = parse_annotation(line) {
+            if let Some(block) = blocks.last_mut() {
+                block.options.extend(annotations);
+            }
             continue;
         }
 
-        let key = parts[0].to_lowercase();
-        let value = parts[1].trim().trim_matches('"').to_string();
+        // Skip empty lines and comments
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((key, value)) = split_key_value(line) else {
+            continue;
+        };
 
         match key.as_str() {
             "host" => {
-                // Save previous hosts if any
-                flush_hosts(
-                    &current_hosts,
-                    &mut current_hostname,
-                    &mut current_user,
-                    &mut current_port,
-                    &mut current_identity,
-                    &mut servers,
-                );
-
                 // Split multi-value Host lines (e.g., "Host git github.com")
-                current_hosts = value
+                let patterns: Vec<String> = value
                     .split_whitespace()
-                    .map(|s| s.to_string())
+                    .map(|s| s.trim_matches('"').to_string())
                     .collect();
+
+                // Every literal (non-wildcard, non-negated) name is a connectable host
+                for pattern in &patterns {
+                    if !is_pattern(pattern) && !hosts.iter().any(|(h, _)| h == pattern) {
+                        let location = SourceLocation {
+                            file: config_line.file.to_path_buf(),
+                            line: config_line.number,
+                        };
+                        hosts.push((pattern.clone(), location));
+                    }
+                }
+
+                blocks.push(ConfigBlock::new(BlockCondition::Host(patterns)));
             }
-            "hostname" => {
-                current_hostname = Some(value);
-            }
-            "user" => {
-                current_user = Some(value);
-            }
-            "port" => {
-                current_port = value.parse().ok();
+            "match" => {
+                blocks.push(ConfigBlock::new(BlockCondition::Match(
+                    parse_match_criteria(&value),
+                )));
             }
-            "identityfile" => {
-                // Expand ~ to home directory
-                let expanded = if let Some(stripped) = value.strip_prefix("~/") {
-                    if let Some(home) = dirs::home_dir() {
-                        home.join(stripped).to_string_lossy().to_string()
-                    } else {
-                        value
-                    }
-                } else {
-                    value
-                };
-                current_identity = Some(expanded);
+            _ => {
+                if let Some(block) = blocks.last_mut() {
+                    block
+                        .options
+                        .push((key, value.trim_matches('"').to_string()));
+                }
             }
-            _ => {}
         }
     }
 
-    // Don't forget the last host(s)
-    flush_hosts(
-        &current_hosts,
-        &mut current_hostname,
-        &mut current_user,
-        &mut current_port,
-        &mut current_identity,
-        &mut servers,
-    );
-
-    Ok(servers)
+    (blocks, hosts)
 }
 
-/// Flush accumulated host entries into the servers list
-fn flush_hosts(
-    hosts: &[String],
-    hostname: &mut Option<String>,
-    user: &mut Option<String>,
-    port: &mut Option<u16>,
-    identity: &mut Option<String>,
-    servers: &mut Vec<Server>,
+/// Apply every block matching the context, keeping the first value obtained for each option
+fn apply_blocks(
+    blocks: &[ConfigBlock],
+    host: &str,
+    original_host: &str,
+    final_pass: bool,
+    state: &ParseState,
+    options: &mut HashMap<String, String>,
 ) {
-    for host in hosts {
-        // Skip wildcard hosts and patterns
-        if host.contains('*') || host.contains('?') {
+    for block in blocks {
+        let ctx = MatchContext {
+            host,
+            original_host,
+            options,
+            final_pass,
+            state,
+        };
+        if !block.matches(&ctx) {
             continue;
         }
-        let hn = hostname.clone().unwrap_or_else(|| host.clone());
-        let mut server = Server::new(host.clone(), hn);
-        server.user = user.clone();
-        server.port = port.unwrap_or(22);
-        server.identity_file = identity.clone();
-        servers.push(server);
-    }
-    *hostname = None;
-    *user = None;
-    *port = None;
-    *identity = None;
-}
-
-/// Group servers by their name prefix
-/// e.g., prod-web-01, prod-web-02 -> group "prod-web"
-pub fn group_servers(servers: &mut [Server]) {
+        for (key, value) in &block.options {
+            // Tags from every matching block add up
+            if key == TAGS_KEY {
+                let tags = options.entry(key.clone()).or_default();
+                if !tags.is_empty() {
+                    tags.push(',');
+                }
+                tags.push_str(value);
+                continue;
+            }
+
+            // ProxyJump and ProxyCommand compete: whichever is seen first wins
+            let competing = match key.as_str() {
+                "proxyjump" => Some("proxycommand"),
+                "proxycommand" => Some("proxyjump"),
+                _ => None,
+            };
+            if competing.is_some_and(|other| options.contains_key(other)) {
+                continue;
+            }
+            options.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+    }
+}
+
+/// Build the server for a host alias by evaluating every `Host` and `Match` block in order.
+/// Like OpenSSH, the first value obtained for each option wins, and a second pass runs
+/// against the resolved hostname when `Match final`/`canonical` or `CanonicalizeHostname` ask for it.
+fn resolve_host(host: &str, blocks: &[ConfigBlock], state: &ParseState) -> Server {
+    let mut options: HashMap<String, String> = HashMap::new();
+    apply_blocks(blocks, host, host, false, state, &mut options);
+
+    let hostname = options
+        .get("hostname")
+        .map(|h| expand_tokens(h, &[('h', host)]))
+        .unwrap_or_else(|| host.to_string());
+
+    let canonicalize = options
+        .get("canonicalizehostname")
+        .is_some_and(|v| matches!(v.to_lowercase().as_str(), "yes" | "always"));
+    if canonicalize || blocks.iter().any(|b| b.wants_final_pass()) {
+        apply_blocks(blocks, &hostname, host, true, state, &mut options);
+    }
+
+    let mut server = Server::new(host.to_string(), hostname);
+    server.user = options.get("user").cloned();
+    server.port = options
+        .get("port")
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(22);
+    server.identity_file = options.get("identityfile").map(|i| expand_tilde(i));
+
+    if let Some(jump) = options.get("proxyjump") {
+        server.jump_chain = JumpHost::parse_chain(jump);
+    } else if let Some(command) = options.get("proxycommand") {
+        if !command.eq_ignore_ascii_case("none") {
+            server.jump_chain = JumpHost::from_proxy_command(command).into_iter().collect();
+            server.proxy_command = Some(command.clone());
+        }
+    }
+
+    if let Some(tags) = options.get(TAGS_KEY) {
+        server.tags = tags
+            .split(',')
+            .map(|t| t.trim())
+            .filter(|t| !t.is_empty())
+            .map(|t| t.to_string())
+            .collect();
+    }
+    server.description = options.get(DESCRIPTION_KEY).cloned();
+    server.owner = options.get(OWNER_KEY).cloned();
+    server.group = options.get(GROUP_KEY).cloned();
+
+    server
+}
+
+/// Group servers using the user's rules, falling back to the name prefix
+/// e.g., prod-web-01, prod-web-02 -> group "prod-web".
+/// Servers that already have a group from a `#ggoto group=` annotation keep it.
+pub fn group_servers(servers: &mut [Server], rules: &[GroupRule]) {
     let re = Regex::new(r"^(.+?)[-_]?\d+$").unwrap();
 
     for server in servers.iter_mut() {
-        if let Some(caps) = re.captures(&server.host) {
+        if server.group.is_some() {
+            continue;
+        }
+        if let Some(group) = rules.iter().find_map(|rule| rule.group_for(server)) {
+            server.group = Some(group);
+        } else if let Some(caps) = re.captures(&server.host) {
             server.group = Some(caps[1].to_string());
         } else {
             // No numeric suffix - use the host itself as group
@@ -243,35 +881,34 @@ pub fn group_servers(servers: &mut [Server]) {
     }
 }
 
-/// Build server groups from the servers list
-pub fn build_groups(servers: &[Server]) -> Vec<crate::server::ServerGroup> {
-    use std::collections::HashMap;
-
-    let mut group_map: HashMap<String, Vec<usize>> = HashMap::new();
-
-    for (idx, server) in servers.iter().enumerate() {
-        if let Some(ref group_name) = server.group {
-            group_map.entry(group_name.clone()).or_default().push(idx);
-        }
-    }
-
-    let mut groups: Vec<crate::server::ServerGroup> = group_map
-        .into_iter()
-        .map(|(name, servers)| {
-            let mut group = crate::server::ServerGroup::new(name);
-            group.servers = servers;
-            group
-        })
-        .collect();
+/// Build the server group tree from the servers list
+pub fn build_groups(servers: &[Server]) -> Vec<ServerGroup> {
+    build_groups_by(servers, |server| server.group.as_deref().map(group_path).into_iter().collect())
+}
 
-    groups.sort_by(|a, b| a.name.cmp(&b.name));
-    groups
+/// Build a server group tree using an arbitrary grouping key: the path segments
+/// of each group a server belongs to
+pub fn build_groups_by<F>(servers: &[Server], key: F) -> Vec<ServerGroup>
+where
+    F: Fn(&Server) -> Vec<Vec<String>>,
+{
+    ServerGroup::tree(
+        servers
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, server)| key(server).into_iter().map(move |path| (idx, path))),
+    )
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn parse_config_content(content: &str, parse_options: &ParseOptions) -> Result<Vec<Server>> {
+        let state = ParseState::new(parse_options);
+        parse_config_lines(&config_lines(content, Path::new("config")), &state)
+    }
+
     #[test]
     fn test_parse_simple_config() {
         let config = r#"
@@ -284,7 +921,7 @@ Host server2
     HostName example.com
     IdentityFile ~/.ssh/id_rsa
 "#;
-        let servers = parse_config_content(config).unwrap();
+        let servers = parse_config_content(config, &ParseOptions::default()).unwrap();
         assert_eq!(servers.len(), 2);
 
         assert_eq!(servers[0].host, "server1");
@@ -309,11 +946,352 @@ Host prod-*
 Host myserver
     HostName 10.0.0.1
 "#;
-        let servers = parse_config_content(config).unwrap();
+        let servers = parse_config_content(config, &ParseOptions::default()).unwrap();
         assert_eq!(servers.len(), 1);
         assert_eq!(servers[0].host, "myserver");
     }
 
+    #[test]
+    fn test_wildcard_defaults_applied() {
+        let config = r#"
+Host prod-db-01
+    HostName 10.0.2.1
+
+Host prod-db-*
+    User admin
+    Port 2200
+    IdentityFile /keys/db
+
+Host *
+    User fallback
+"#;
+        let servers = parse_config_content(config, &ParseOptions::default()).unwrap();
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].hostname, "10.0.2.1");
+        assert_eq!(servers[0].user, Some("admin".to_string()));
+        assert_eq!(servers[0].port, 2200);
+        assert_eq!(servers[0].identity_file, Some("/keys/db".to_string()));
+    }
+
+    #[test]
+    fn test_first_match_wins() {
+        let config = r#"
+User global
+
+Host *
+    User star
+    Port 2222
+
+Host web1
+    User deploy
+    Port 22
+"#;
+        let servers = parse_config_content(config, &ParseOptions::default()).unwrap();
+        assert_eq!(servers.len(), 1);
+        // Options before the first Host line and in earlier blocks take precedence
+        assert_eq!(servers[0].user, Some("global".to_string()));
+        assert_eq!(servers[0].port, 2222);
+    }
+
+    #[test]
+    fn test_negated_and_multi_patterns() {
+        let config = r#"
+Host bastion web1 db1
+
+Host web? db? !bastion
+    User deploy
+
+Host *.internal !db*
+    Port 2022
+
+Host db1.internal
+"#;
+        let servers = parse_config_content(config, &ParseOptions::default()).unwrap();
+        assert_eq!(servers.len(), 4);
+
+        let get = |name: &str| servers.iter().find(|s| s.host == name).unwrap();
+        assert_eq!(get("bastion").user, None);
+        assert_eq!(get("web1").user, Some("deploy".to_string()));
+        assert_eq!(get("db1").user, Some("deploy".to_string()));
+        assert_eq!(get("db1.internal").port, 22);
+    }
+
+    #[test]
+    fn test_hostname_token_expansion() {
+        let config = r#"
+Host app1 app2
+    HostName %h.corp.example.com
+"#;
+        let servers = parse_config_content(config, &ParseOptions::default()).unwrap();
+        assert_eq!(servers[0].hostname, "app1.corp.example.com");
+        assert_eq!(servers[1].hostname, "app2.corp.example.com");
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("prod-*", "prod-web-01"));
+        assert!(wildcard_match("prod-*-01", "prod-web-01"));
+        assert!(wildcard_match("web?", "WEB1"));
+        assert!(!wildcard_match("web?", "web10"));
+        assert!(!wildcard_match("prod-*", "staging-web"));
+    }
+
+    #[test]
+    fn test_key_equals_value() {
+        let config = "Host eq\n    HostName = 10.0.0.5\n    User=root\n";
+        let servers = parse_config_content(config, &ParseOptions::default()).unwrap();
+        assert_eq!(servers[0].hostname, "10.0.0.5");
+        assert_eq!(servers[0].user, Some("root".to_string()));
+    }
+
+    #[test]
+    fn test_match_host_and_originalhost() {
+        let config = r#"
+Host web1
+    HostName web1.prod.example.com
+
+Host db1
+    HostName db1.staging.example.com
+
+Match host *.prod.example.com
+    User deploy
+
+Match originalhost db*
+    User dba
+    Port 5022
+"#;
+        let servers = parse_config_content(config, &ParseOptions::default()).unwrap();
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[0].user, Some("deploy".to_string()));
+        assert_eq!(servers[0].port, 22);
+        assert_eq!(servers[1].user, Some("dba".to_string()));
+        assert_eq!(servers[1].port, 5022);
+    }
+
+    #[test]
+    fn test_match_does_not_leak_into_previous_host() {
+        let config = r#"
+Host plain
+    HostName 10.0.0.1
+
+Match originalhost other
+    User nobody
+"#;
+        let servers = parse_config_content(config, &ParseOptions::default()).unwrap();
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].user, None);
+    }
+
+    #[test]
+    fn test_match_user_localuser_negation_and_all() {
+        let config = r#"
+Host svc
+    User root
+
+Match user root !localuser alice
+    Port 2201
+
+Match localuser alice user root
+    IdentityFile /keys/root
+
+Match all
+    Port 2299
+"#;
+        let (blocks, hosts) = parse_blocks(&config_lines(config, Path::new("config")));
+        let options = ParseOptions::default();
+        let state = ParseState {
+            local_user: "alice".to_string(),
+            ..ParseState::new(&options)
+        };
+        let server = resolve_host(&hosts[0].0, &blocks, &state);
+        assert_eq!(server.port, 2299);
+        assert_eq!(server.identity_file, Some("/keys/root".to_string()));
+    }
+
+    #[test]
+    fn test_match_final_pass() {
+        let config = r#"
+Host short
+    HostName short.example.com
+
+Host *.example.com
+    User from-final-pass
+
+Match canonical
+    Port 2022
+"#;
+        let servers = parse_config_content(config, &ParseOptions::default()).unwrap();
+        // The final pass matches Host patterns against the resolved hostname
+        assert_eq!(servers[0].user, Some("from-final-pass".to_string()));
+        assert_eq!(servers[0].port, 2022);
+    }
+
+    #[test]
+    fn test_match_exec_opt_in() {
+        let config = r#"
+Host box
+    HostName box.example.com
+
+Match exec "test %h = box.example.com"
+    User via-exec
+"#;
+        let servers = parse_config_content(config, &ParseOptions::default()).unwrap();
+        assert_eq!(servers[0].user, None);
+
+        let options = ParseOptions {
+            allow_match_exec: true,
+        };
+        let servers = parse_config_content(config, &options).unwrap();
+        assert_eq!(servers[0].user, Some("via-exec".to_string()));
+    }
+
+    #[test]
+    fn test_match_exec_runs_once_per_host() {
+        let log = std::env::temp_dir().join("ggoto_test_match_exec.log");
+        let _ = fs::remove_file(&log);
+        let config = format!(
+            r#"
+Host a b
+    Port 2200
+
+Match exec "echo %n >> {}; false"
+    User never
+
+Match final
+    User final
+"#,
+            log.display()
+        );
+        let options = ParseOptions {
+            allow_match_exec: true,
+        };
+        let servers = parse_config_content(&config, &options).unwrap();
+        assert_eq!(servers.len(), 2);
+        // Once per host, not once per pass
+        assert_eq!(fs::read_to_string(&log).unwrap(), "a\nb\n");
+        let _ = fs::remove_file(&log);
+    }
+
+    #[test]
+    fn test_parse_match_criteria() {
+        let criteria =
+            parse_match_criteria(r#"host a,b !user root exec "echo hi" localnetwork 10.0.0.0/8"#);
+        assert_eq!(criteria.len(), 4);
+        assert_eq!(
+            criteria[0].kind,
+            MatchKind::Host(vec!["a".to_string(), "b".to_string()])
+        );
+        assert!(criteria[1].negated);
+        assert_eq!(criteria[2].kind, MatchKind::Exec("echo hi".to_string()));
+        assert_eq!(criteria[3].kind, MatchKind::Unsupported);
+    }
+
+    #[test]
+    fn test_proxy_jump_chain() {
+        let config = r#"
+Host inner
+    HostName 10.1.0.5
+    ProxyJump admin@bastion:2222,ssh://jump2
+
+Host *.internal !bastion
+    ProxyJump bastion
+
+Host db.internal
+
+Host direct
+    ProxyJump none
+"#;
+        let servers = parse_config_content(config, &ParseOptions::default()).unwrap();
+        assert_eq!(servers.len(), 3);
+
+        let chain = &servers[0].jump_chain;
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain[0].user, Some("admin".to_string()));
+        assert_eq!(chain[0].host, "bastion");
+        assert_eq!(chain[0].port, Some(2222));
+        assert_eq!(chain[1].host, "jump2");
+        assert_eq!(servers[0].bastion(), Some("bastion"));
+
+        assert_eq!(servers[1].bastion(), Some("bastion"));
+        assert!(servers[2].jump_chain.is_empty());
+    }
+
+    #[test]
+    fn test_proxy_command() {
+        let config = r#"
+Host via-cmd
+    ProxyCommand ssh -q -l ops -W %h:%p gateway.example.com
+    ProxyJump ignored
+
+Host via-nc
+    ProxyCommand nc -X 5 -x socks:1080 %h %p
+"#;
+        let servers = parse_config_content(config, &ParseOptions::default()).unwrap();
+
+        // ProxyCommand came first, so the later ProxyJump is ignored
+        assert_eq!(servers[0].jump_chain.len(), 1);
+        assert_eq!(servers[0].jump_chain[0].host, "gateway.example.com");
+        assert_eq!(servers[0].jump_chain[0].user, Some("ops".to_string()));
+        assert!(servers[0].proxy_command.is_some());
+
+        // Non-ssh proxies are kept as-is without a structured chain
+        assert!(servers[1].jump_chain.is_empty());
+        assert_eq!(servers[1].via_label(), Some("(proxy)".to_string()));
+    }
+
+    #[test]
+    fn test_jump_host_parse() {
+        let hop = JumpHost::parse("[fe80::1]:2200").unwrap();
+        assert_eq!(hop.host, "fe80::1");
+        assert_eq!(hop.port, Some(2200));
+        assert_eq!(hop.to_string(), "[fe80::1]:2200");
+        assert_eq!(JumpHost::parse(&hop.to_string()), Some(hop));
+
+        let hop = JumpHost::parse("me@gw").unwrap();
+        assert_eq!(hop.to_string(), "me@gw");
+        assert!(JumpHost::parse_chain("NONE").is_empty());
+    }
+
+    #[test]
+    fn test_annotations() {
+        let config = r#"
+Host train1 train2
+    #ggoto tags=gpu,a100 desc="A100 training box" owner=ml-team
+    HostName %h.lab
+
+Host train2
+    # ggoto tags=ignored (not an annotation)
+    #ggoto tags=spare desc="Overridden? No, first wins"
+
+Host train*
+    #ggoto tags=lab
+
+Host plain
+    #ggoto group=lab/misc
+"#;
+        let servers = parse_config_content(config, &ParseOptions::default()).unwrap();
+        let get = |name: &str| servers.iter().find(|s| s.host == name).unwrap();
+
+        let train1 = get("train1");
+        assert_eq!(
+            train1.tags.iter().map(|t| t.as_str()).collect::<Vec<_>>(),
+            vec!["a100", "gpu", "lab"]
+        );
+        assert_eq!(train1.description, Some("A100 training box".to_string()));
+        assert_eq!(train1.owner, Some("ml-team".to_string()));
+
+        let train2 = get("train2");
+        assert!(train2.tags.contains("spare"));
+        assert!(!train2.tags.contains("ignored"));
+        assert_eq!(train2.description, Some("A100 training box".to_string()));
+
+        assert!(get("plain").tags.is_empty());
+        assert_eq!(get("plain").owner, None);
+        assert_eq!(get("plain").group, Some("lab/misc".to_string()));
+        assert_eq!(train1.group, None);
+    }
+
     #[test]
     fn test_grouping() {
         let mut servers = vec![
@@ -323,12 +1301,46 @@ Host myserver
             Server::new("standalone".to_string(), "10.0.2.1".to_string()),
         ];
 
-        group_servers(&mut servers);
+        group_servers(&mut servers, &[]);
 
         assert_eq!(servers[0].group, Some("prod-web".to_string()));
         assert_eq!(servers[1].group, Some("prod-web".to_string()));
         assert_eq!(servers[2].group, Some("prod-db".to_string()));
         assert_eq!(servers[3].group, Some("standalone".to_string()));
+
+        // Annotated groups are kept
+        let mut annotated = vec![Server::new("web-01".to_string(), "10.0.0.1".to_string())];
+        annotated[0].group = Some("prod/eu/web".to_string());
+        group_servers(&mut annotated, &[]);
+        assert_eq!(annotated[0].group, Some("prod/eu/web".to_string()));
+    }
+
+    #[test]
+    fn test_group_tree() {
+        let mut servers: Vec<Server> = ["prod-web-01", "prod-web-02", "prod-db-01", "dev-server", "prod"]
+            .iter()
+            .map(|h| Server::new(h.to_string(), "10.0.0.1".to_string()))
+            .collect();
+        servers.push(Server::new("eu-1".to_string(), "10.0.0.2".to_string()));
+        servers[5].group = Some("prod/eu/web".to_string());
+        group_servers(&mut servers, &[]);
+
+        let groups = build_groups(&servers);
+        let names: Vec<&str> = groups.iter().map(|g| g.name.as_str()).collect();
+        // `dev` has a single subgroup and no servers, so it folds into `dev-server`
+        assert_eq!(names, vec!["dev-server", "prod"]);
+        assert_eq!(groups[0].path, "dev-server");
+
+        let prod = &groups[1];
+        assert_eq!(prod.servers, vec![4]);
+        let children: Vec<(&str, &str)> = prod
+            .children
+            .iter()
+            .map(|g| (g.name.as_str(), g.path.as_str()))
+            .collect();
+        assert_eq!(children, vec![("db", "prod-db"), ("eu/web", "prod/eu/web"), ("web", "prod-web")]);
+        assert_eq!(prod.all_servers().len(), 5);
+        assert_eq!(prod.group_count(), 4);
     }
 
     #[test]
@@ -339,7 +1351,7 @@ Host alias1 alias2
     User admin
     Port 2222
 "#;
-        let servers = parse_config_content(config).unwrap();
+        let servers = parse_config_content(config, &ParseOptions::default()).unwrap();
         assert_eq!(servers.len(), 2);
         assert_eq!(servers[0].host, "alias1");
         assert_eq!(servers[0].hostname, "10.0.0.1");
@@ -363,7 +1375,7 @@ Host myserver
     HostName 10.0.0.1
     User admin
 "#;
-        let mut servers = parse_config_content(config).unwrap();
+        let mut servers = parse_config_content(config, &ParseOptions::default()).unwrap();
         servers.retain(|s| !GIT_HOSTING_DOMAINS.contains(&s.hostname.as_str()));
 
         assert_eq!(servers.len(), 1);
@@ -395,8 +1407,10 @@ Host myserver
         );
         fs::write(&main_config, &main_content).unwrap();
 
-        let content = resolve_includes(&main_config, 0).unwrap();
-        let servers = parse_config_content(&content).unwrap();
+        let mut snapshot = ConfigSnapshot::default();
+        let lines = resolve_includes(&main_config, &tmp_dir, 0, &mut snapshot).unwrap();
+        let options = ParseOptions::default();
+        let servers = parse_config_lines(&lines, &ParseState::new(&options)).unwrap();
 
         assert_eq!(servers.len(), 2);
 
@@ -404,7 +1418,56 @@ Host myserver
         assert!(hosts.contains(&"included-server"));
         assert!(hosts.contains(&"main-server"));
 
+        // Hosts remember the file and line they were defined at
+        let included_server = servers.iter().find(|s| s.host == "included-server").unwrap();
+        let location = included_server.defined_at.as_ref().unwrap();
+        assert_eq!(location.file, included);
+        assert_eq!(location.line, 1);
+        let main_server = servers.iter().find(|s| s.host == "main-server").unwrap();
+        assert_eq!(main_server.defined_at.as_ref().unwrap().line, 3);
+
         // Cleanup
         let _ = fs::remove_dir_all(&tmp_dir);
     }
+
+    #[test]
+    fn test_multiple_sources() {
+        use std::fs;
+
+        let tmp_dir = std::env::temp_dir().join("ggoto_test_sources");
+        let _ = fs::remove_dir_all(&tmp_dir);
+        fs::create_dir_all(&tmp_dir).unwrap();
+
+        let work = tmp_dir.join("work");
+        let lab = tmp_dir.join("lab");
+        fs::write(&work, "Host shared\n    User work\n\nHost work-box\n").unwrap();
+        fs::write(&lab, "Host *\n    Port 2222\n\nHost shared lab-box\n").unwrap();
+
+        let sources = vec![ConfigSource::file(work.clone()), ConfigSource::file(lab.clone())];
+        let (servers, snapshot) = parse_ssh_config(&sources, &ParseOptions::default()).unwrap();
+
+        let hosts: Vec<&str> = servers.iter().map(|s| s.host.as_str()).collect();
+        assert_eq!(hosts, vec!["shared", "work-box", "lab-box"]);
+
+        // Sources don't share options, and the first source defining a host wins
+        assert_eq!(servers[0].user, Some("work".to_string()));
+        assert_eq!(servers[0].port, 22);
+        assert_eq!(servers[0].config_file, Some(work));
+        assert_eq!(servers[2].port, 2222);
+        assert_eq!(servers[2].config_file, Some(lab.clone()));
+
+        // Touching one of the files makes the snapshot stale
+        assert!(!snapshot.is_stale());
+        let file = fs::File::options().write(true).open(&lab).unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(60))
+            .unwrap();
+        assert!(snapshot.is_stale());
+        assert!(!snapshot.refreshed().is_stale());
+
+        // An explicitly chosen config has to exist
+        let missing = vec![ConfigSource::file(tmp_dir.join("missing"))];
+        assert!(parse_ssh_config(&missing, &ParseOptions::default()).is_err());
+
+        let _ = fs::remove_dir_all(&tmp_dir);
+    }
 }
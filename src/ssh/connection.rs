@@ -1,15 +1,38 @@
-use std::process::Command;
+use std::process::{Child, Command, Stdio};
 use std::time::Duration;
 
 use anyhow::{Context, Result};
 use tokio::time::timeout;
 
-use crate::server::Server;
+use crate::server::{ConnectError, FailureKind, Server};
+use crate::ssh::backend::{Backend, BoxFuture};
+use crate::ssh::mosh::launch_mosh_session;
+use crate::ssh::mux;
+use crate::tunnel::TunnelHandle;
+
+/// `-F` arguments pointing ssh at the config file that defines the server,
+/// so hosts from non-default configs resolve the same way ggoto parsed them
+pub fn config_file_args(server: &Server) -> Vec<String> {
+    match server.config_file {
+        Some(ref path) => vec!["-F".to_string(), path.to_string_lossy().to_string()],
+        None => Vec::new(),
+    }
+}
+
+/// Starts the user's login shell on the server with variables set. `env` and
+/// `sh` behave the same under any login shell, so this works from fish or csh too.
+pub fn login_shell_command(env: &[(String, String)]) -> Vec<String> {
+    let mut command = vec!["env".to_string()];
+    command.extend(env.iter().map(|(name, value)| format!("{}={}", name, value)));
+    command.extend(["sh", "-c", "exec \"${SHELL:-sh}\" -l"].map(String::from));
+    command
+}
 
 /// Launch an SSH session to the given server
 /// This replaces the current process with the ssh command
-pub fn launch_ssh_session(server: &Server) -> Result<()> {
-    let mut args = Vec::new();
+pub fn launch_ssh_session(server: &Server, env: &[(String, String)]) -> Result<()> {
+    let mut args = config_file_args(server);
+    args.extend(mux::control_args());
 
     // Add user if specified
     if let Some(ref user) = server.user {
@@ -32,6 +55,17 @@ pub fn launch_ssh_session(server: &Server) -> Result<()> {
     // Add the host (use the Host alias from config, SSH will resolve it)
     args.push(server.host.clone());
 
+    // Variables need a command run in a terminal, which ssh joins into one
+    // string for the remote shell
+    if !env.is_empty() {
+        let mut command = login_shell_command(env);
+        if let Some(script) = command.last_mut() {
+            *script = format!("'{}'", script);
+        }
+        args.insert(0, "-t".to_string());
+        args.push(command.join(" "));
+    }
+
     // Execute SSH
     let status = Command::new("ssh")
         .args(&args)
@@ -46,13 +80,16 @@ pub fn launch_ssh_session(server: &Server) -> Result<()> {
 }
 
 /// SSH connection timeout in seconds (for health checks)
-const CONNECT_TIMEOUT_SECS: u64 = 3;
+pub const CONNECT_TIMEOUT_SECS: u64 = 3;
 
 /// Command execution timeout in seconds
-const COMMAND_TIMEOUT_SECS: u64 = 10;
+pub const COMMAND_TIMEOUT_SECS: u64 = 10;
+
+/// Exit code ssh uses for its own errors
+const SSH_ERROR_EXIT: i32 = 255;
 
-/// Run a command on a remote server and return the output
-pub async fn run_remote_command(server: &Server, command: &str) -> Result<String> {
+/// Run a command on a remote server through the `ssh` binary and return the output
+async fn run_remote_command(server: &Server, command: &str) -> Result<String> {
     // SSH options for non-interactive use
     let mut args = vec![
         "-o".to_string(),
@@ -64,6 +101,8 @@ pub async fn run_remote_command(server: &Server, command: &str) -> Result<String
         "-o".to_string(),
         "LogLevel=ERROR".to_string(), // Suppress warnings (e.g., post-quantum)
     ];
+    args.extend(config_file_args(server));
+    args.extend(mux::control_args());
 
     // Add user if specified
     if let Some(ref user) = server.user {
@@ -94,18 +133,34 @@ pub async fn run_remote_command(server: &Server, command: &str) -> Result<String
         Duration::from_secs(COMMAND_TIMEOUT_SECS),
         tokio::process::Command::new("ssh")
             .args(&args)
+            .stdin(Stdio::null())
             .output(),
     )
     .await
-    .context("Command timed out")?
+    .map_err(|_| {
+        ConnectError::new(
+            FailureKind::Timeout,
+            format!("No answer within {}s", COMMAND_TIMEOUT_SECS),
+        )
+    })?
     .context("Failed to execute SSH command")?;
 
-    if output.status.success() {
-        Ok(String::from_utf8_lossy(&output.stdout).to_string())
-    } else {
+    // ssh reports its own failures, rather than the command's, with 255
+    if output.status.code() == Some(SSH_ERROR_EXIT) {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let code = output.status.code().map(|c| c.to_string()).unwrap_or_else(|| "unknown".to_string());
+        return Err(ConnectError::classify(&stderr, server.is_proxied()).into());
+    }
+    command_result(output.status.code(), &output.stdout, &output.stderr)
+}
+
+/// A remote command's stdout, or an error with its exit code and output
+pub fn command_result(code: Option<i32>, stdout: &[u8], stderr: &[u8]) -> Result<String> {
+    if code == Some(0) {
+        Ok(String::from_utf8_lossy(stdout).to_string())
+    } else {
+        let stderr = String::from_utf8_lossy(stderr);
+        let stdout = String::from_utf8_lossy(stdout);
+        let code = code.map(|c| c.to_string()).unwrap_or_else(|| "unknown".to_string());
 
         // Provide useful error info
         if !stderr.is_empty() {
@@ -117,3 +172,127 @@ pub async fn run_remote_command(server: &Server, command: &str) -> Result<String
         }
     }
 }
+
+/// Reaches servers by running the `ssh` binary, sharing connections through
+/// ControlMaster when that's on. Handles every option ssh itself does.
+pub struct ExternalSsh;
+
+impl Backend for ExternalSsh {
+    fn keeps_connections(&self, _server: &Server) -> bool {
+        mux::is_enabled()
+    }
+
+    fn is_connected<'a>(&'a self, server: &'a Server) -> BoxFuture<'a, bool> {
+        Box::pin(mux::master_running_async(server))
+    }
+
+    fn run_command<'a>(
+        &'a self,
+        server: &'a Server,
+        command: &'a str,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(run_remote_command(server, command))
+    }
+
+    fn open_forward(
+        &self,
+        server: &Server,
+        local_port: u16,
+        remote_host: &str,
+        remote_port: u16,
+    ) -> Result<TunnelHandle> {
+        let forward = format!("{}:{}:{}", local_port, remote_host, remote_port);
+
+        // Add the forward to the shared connection when there is one
+        if mux::master_running(server) {
+            forward_on_master(server, &forward)
+        } else {
+            Ok(TunnelHandle::Process(spawn_tunnel_process(server, forward)?))
+        }
+    }
+
+    fn launch_session(&self, server: &Server, use_mosh: bool, env: &[(String, String)]) -> Result<()> {
+        if use_mosh {
+            println!("Connecting to {} via mosh...", server.host);
+            match launch_mosh_session(server, env) {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    eprintln!("Mosh failed: {}", e);
+                    eprintln!("Falling back to SSH...");
+                }
+            }
+        }
+        println!("Connecting to {}...", server.host);
+        launch_ssh_session(server, env)
+    }
+}
+
+/// Ask the server's master to add a local forward
+fn forward_on_master(server: &Server, forward: &str) -> Result<TunnelHandle> {
+    let master_args = mux::master_args(server);
+    let hold = mux::hold_master(server)?;
+    let output = Command::new("ssh")
+        .args(&master_args)
+        .args(["-O", "forward", "-L", forward, &server.host])
+        .stdin(Stdio::null())
+        .output()
+        .context("Failed to start SSH tunnel")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Port forward failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(TunnelHandle::Shared {
+        master_args,
+        hold,
+    })
+}
+
+/// Start a dedicated ssh process for a forward
+fn spawn_tunnel_process(server: &Server, forward: String) -> Result<Child> {
+    // Build SSH tunnel command
+    let mut args = vec![
+        "-N".to_string(),        // No remote command
+        "-L".to_string(),        // Local port forwarding
+        forward,
+        "-o".to_string(),
+        "BatchMode=yes".to_string(),
+        "-o".to_string(),
+        "ExitOnForwardFailure=yes".to_string(),
+        "-o".to_string(),
+        "ServerAliveInterval=30".to_string(),
+        "-o".to_string(),
+        "ServerAliveCountMax=3".to_string(),
+    ];
+    args.extend(config_file_args(server));
+
+    // Add user if specified
+    if let Some(ref user) = server.user {
+        args.push("-l".to_string());
+        args.push(user.clone());
+    }
+
+    // Add port if not default
+    if server.port != 22 {
+        args.push("-p".to_string());
+        args.push(server.port.to_string());
+    }
+
+    // Add identity file if specified
+    if let Some(ref identity) = server.identity_file {
+        args.push("-i".to_string());
+        args.push(identity.clone());
+    }
+
+    // Add the host
+    args.push(server.host.clone());
+
+    Command::new("ssh")
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to start SSH tunnel")
+}
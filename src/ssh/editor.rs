@@ -0,0 +1,621 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tokio::time::timeout;
+
+use super::config::{is_pattern, split_key_value};
+use crate::server::{tilde_path, SourceLocation};
+
+/// How long validating an edited config with `ssh -G` may take
+const VALIDATE_TIMEOUT_SECS: u64 = 5;
+
+/// Indentation for options when the block has none to copy
+const DEFAULT_INDENT: &str = "    ";
+
+/// Options the host editor manages, spelled the way new lines are written
+pub const EDITABLE_OPTIONS: [&str; 5] = ["HostName", "User", "Port", "IdentityFile", "ProxyJump"];
+
+/// The editable part of a `Host` block. Empty values mean the block doesn't set the option.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostEntry {
+    pub host: String,
+    /// Values for `EDITABLE_OPTIONS`, in the same order
+    pub options: [String; 5],
+}
+
+impl HostEntry {
+    /// Number of form fields: the alias followed by the options
+    pub const FIELD_COUNT: usize = EDITABLE_OPTIONS.len() + 1;
+
+    /// Label of a form field
+    pub fn field_label(field: usize) -> &'static str {
+        match field {
+            0 => "Host",
+            n => EDITABLE_OPTIONS[n - 1],
+        }
+    }
+
+    pub fn field(&self, field: usize) -> &str {
+        match field {
+            0 => &self.host,
+            n => &self.options[n - 1],
+        }
+    }
+
+    pub fn field_mut(&mut self, field: usize) -> &mut String {
+        match field {
+            0 => &mut self.host,
+            n => &mut self.options[n - 1],
+        }
+    }
+
+    /// Check the values before they are written to a config file
+    pub fn validate(&self) -> Result<()> {
+        let host = self.host.trim();
+        if host.is_empty() {
+            anyhow::bail!("Host alias is required");
+        }
+        if host.contains(char::is_whitespace) || is_pattern(host) {
+            anyhow::bail!("Host alias must be a single name without wildcards");
+        }
+        let port = self.options[2].trim();
+        if !port.is_empty() && !port.parse::<u16>().is_ok_and(|p| p > 0) {
+            anyhow::bail!("Invalid port: {}", port);
+        }
+        Ok(())
+    }
+}
+
+/// A change requested from the host editor
+#[derive(Debug, Clone)]
+pub enum HostEdit {
+    Add { file: PathBuf, entry: HostEntry },
+    Update { at: SourceLocation, host: String, entry: HostEntry },
+    Duplicate { at: SourceLocation, host: String, entry: HostEntry },
+    Delete { at: SourceLocation, host: String },
+}
+
+/// Config text split into lines. The line ending style and final newline are
+/// remembered so untouched lines are written back exactly as they were.
+struct ConfigText {
+    lines: Vec<String>,
+    newline: &'static str,
+    trailing_newline: bool,
+}
+
+impl ConfigText {
+    fn parse(content: &str) -> Self {
+        Self {
+            lines: content.lines().map(|l| l.to_string()).collect(),
+            newline: if content.contains("\r\n") { "\r\n" } else { "\n" },
+            trailing_newline: content.ends_with('\n') || content.is_empty(),
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut content = self.lines.join(self.newline);
+        if self.trailing_newline && !self.lines.is_empty() {
+            content.push_str(self.newline);
+        }
+        content
+    }
+}
+
+/// A `Host` block: its header line and the lines up to (not including) the first
+/// line after its last option, so trailing blank lines and comments stay put
+struct BlockSpan {
+    header: usize,
+    end: usize,
+    patterns: Vec<String>,
+}
+
+/// Lowercased keyword of a config line, if it has one
+fn keyword(line: &str) -> Option<String> {
+    let trimmed = line.trim();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        return None;
+    }
+    split_key_value(trimmed).map(|(key, _)| key)
+}
+
+fn is_block_header(line: &str) -> bool {
+    matches!(keyword(line).as_deref(), Some("host" | "match"))
+}
+
+/// Index just past the last option of the block whose header is at `header`
+fn block_end(lines: &[String], header: usize) -> usize {
+    let mut end = header + 1;
+    for (i, line) in lines.iter().enumerate().skip(header + 1) {
+        if is_block_header(line) {
+            break;
+        }
+        if keyword(line).is_some() {
+            end = i + 1;
+        }
+    }
+    end
+}
+
+/// Patterns on a `Host` line
+fn host_patterns(line: &str) -> Option<Vec<String>> {
+    let (key, value) = split_key_value(line.trim())?;
+    (key == "host").then(|| {
+        value
+            .split_whitespace()
+            .map(|s| s.trim_matches('"').to_string())
+            .collect()
+    })
+}
+
+/// Find the block for `host` whose header is at 1-based `line`
+fn find_block(text: &ConfigText, line: usize, host: &str) -> Result<BlockSpan> {
+    let header = line.saturating_sub(1);
+    let patterns = text
+        .lines
+        .get(header)
+        .and_then(|l| host_patterns(l))
+        .filter(|patterns| patterns.iter().any(|p| p == host))
+        .with_context(|| {
+            format!("Line {} no longer defines Host {}; was the file changed?", line, host)
+        })?;
+
+    Ok(BlockSpan {
+        header,
+        end: block_end(&text.lines, header),
+        patterns,
+    })
+}
+
+/// The part of a line before its value: indentation, keyword and separator
+fn value_prefix(line: &str) -> &str {
+    let indent = line.len() - line.trim_start().len();
+    let rest = &line[indent..];
+    let key_end = rest
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or(rest.len());
+    let after_key = &rest[key_end..];
+    let separator = after_key.len()
+        - after_key
+            .trim_start()
+            .trim_start_matches('=')
+            .trim_start()
+            .len();
+    &line[..indent + key_end + separator]
+}
+
+/// Quote values containing whitespace
+fn quote(value: &str) -> String {
+    if value.contains(char::is_whitespace) && !value.starts_with('"') {
+        format!("\"{}\"", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Rewrite the patterns of a `Host` line, keeping its indentation and spelling
+fn rewrite_header(line: &str, patterns: &[String]) -> String {
+    format!("{}{}", value_prefix(line), patterns.join(" "))
+}
+
+/// Indentation of the first option line in a block
+fn option_indent(block: &[String]) -> Option<String> {
+    block
+        .iter()
+        .find(|l| keyword(l).is_some())
+        .map(|l| l[..l.len() - l.trim_start().len()].to_string())
+}
+
+/// Set, replace or remove the editable options among a block's option lines.
+/// Lines that don't change are left untouched; new options are appended using
+/// the block's indentation, or `default_indent` if it has no options yet.
+fn apply_options(block: &mut Vec<String>, options: &[String; 5], default_indent: &str) {
+    let indent = option_indent(block).unwrap_or_else(|| default_indent.to_string());
+
+    let mut remove = Vec::new();
+    for (name, value) in EDITABLE_OPTIONS.iter().zip(options) {
+        let value = value.trim();
+        let key = name.to_lowercase();
+        let existing = block
+            .iter()
+            .position(|l| keyword(l).as_deref() == Some(key.as_str()));
+
+        match existing {
+            Some(i) if value.is_empty() => remove.push(i),
+            Some(i) => {
+                let current = split_key_value(block[i].trim()).map(|(_, v)| v).unwrap_or_default();
+                if current.trim_matches('"') != value {
+                    block[i] = format!("{}{}", value_prefix(&block[i]), quote(value));
+                }
+            }
+            None if !value.is_empty() => {
+                let end = block.iter().rposition(|l| keyword(l).is_some()).map_or(0, |i| i + 1);
+                block.insert(end, format!("{}{} {}", indent, name, quote(value)));
+            }
+            None => {}
+        }
+    }
+
+    remove.sort_unstable();
+    for i in remove.into_iter().rev() {
+        block.remove(i);
+    }
+}
+
+/// Read the options a host's own block sets (inherited values are not included)
+pub fn read_host_entry(content: &str, line: usize, host: &str) -> Result<HostEntry> {
+    let text = ConfigText::parse(content);
+    let span = find_block(&text, line, host)?;
+
+    let mut entry = HostEntry {
+        host: host.to_string(),
+        ..Default::default()
+    };
+    for line in &text.lines[span.header + 1..span.end] {
+        let Some((key, value)) = split_key_value(line.trim()) else {
+            continue;
+        };
+        if let Some(i) = EDITABLE_OPTIONS.iter().position(|o| o.to_lowercase() == key) {
+            if entry.options[i].is_empty() {
+                entry.options[i] = value.trim_matches('"').to_string();
+            }
+        }
+    }
+    Ok(entry)
+}
+
+/// Change the alias and options of a single-host block
+pub fn update_host(content: &str, line: usize, host: &str, entry: &HostEntry) -> Result<String> {
+    let mut text = ConfigText::parse(content);
+    let span = find_block(&text, line, host)?;
+    if span.patterns.len() > 1 {
+        anyhow::bail!(
+            "Host {} shares its block with other patterns ({}); edit it by hand",
+            host,
+            span.patterns.join(" ")
+        );
+    }
+
+    let mut block = text.lines[span.header + 1..span.end].to_vec();
+    apply_options(&mut block, &entry.options, DEFAULT_INDENT);
+    text.lines.splice(span.header + 1..span.end, block);
+
+    if entry.host != host {
+        text.lines[span.header] = rewrite_header(&text.lines[span.header], std::slice::from_ref(&entry.host));
+    }
+    Ok(text.render())
+}
+
+/// Add a new block after the last block for concrete hosts, so wildcard and
+/// `Match` blocks further down keep acting as defaults
+pub fn add_host(content: &str, entry: &HostEntry) -> String {
+    let mut text = ConfigText::parse(content);
+
+    let last_literal = (0..text.lines.len()).rev().find(|&i| {
+        host_patterns(&text.lines[i]).is_some_and(|p| p.iter().all(|p| !is_pattern(p)))
+    });
+
+    // Borrow the indentation of the block we're inserting after
+    let (at, indent) = match last_literal {
+        Some(header) => {
+            let end = block_end(&text.lines, header);
+            (end, option_indent(&text.lines[header + 1..end]))
+        }
+        None => (text.lines.len(), None),
+    };
+
+    let mut new_block = Vec::new();
+    apply_options(
+        &mut new_block,
+        &entry.options,
+        indent.as_deref().unwrap_or(DEFAULT_INDENT),
+    );
+
+    let mut lines = Vec::new();
+    if text.lines[..at].last().is_some_and(|l| !l.trim().is_empty()) {
+        lines.push(String::new());
+    }
+    lines.push(format!("Host {}", entry.host));
+    lines.extend(new_block);
+
+    if at == text.lines.len() {
+        text.trailing_newline = true;
+    }
+    text.lines.splice(at..at, lines);
+    text.render()
+}
+
+/// Copy a host's block (including options the editor doesn't manage) under a new
+/// alias, right after the original, with the entry's values applied
+pub fn duplicate_host(content: &str, line: usize, host: &str, entry: &HostEntry) -> Result<String> {
+    let mut text = ConfigText::parse(content);
+    let span = find_block(&text, line, host)?;
+
+    let mut block = text.lines[span.header + 1..span.end].to_vec();
+    apply_options(&mut block, &entry.options, DEFAULT_INDENT);
+
+    let mut lines = vec![
+        String::new(),
+        rewrite_header(&text.lines[span.header], std::slice::from_ref(&entry.host)),
+    ];
+    lines.extend(block);
+    text.lines.splice(span.end..span.end, lines);
+    Ok(text.render())
+}
+
+/// Remove a host: its whole block, or just its alias when the block names other hosts too
+pub fn delete_host(content: &str, line: usize, host: &str) -> Result<String> {
+    let mut text = ConfigText::parse(content);
+    let span = find_block(&text, line, host)?;
+
+    if span.patterns.len() > 1 {
+        let remaining: Vec<String> = span.patterns.into_iter().filter(|p| p != host).collect();
+        text.lines[span.header] = rewrite_header(&text.lines[span.header], &remaining);
+        return Ok(text.render());
+    }
+
+    text.lines.drain(span.header..span.end);
+
+    // Don't leave two blank lines where the block used to be
+    let header = span.header;
+    let blank = |l: &String| l.trim().is_empty();
+    if header > 0
+        && blank(&text.lines[header - 1])
+        && text.lines.get(header).is_none_or(blank)
+    {
+        text.lines.remove(header - 1);
+    }
+    Ok(text.render())
+}
+
+/// Directory edited configs are staged in and backed up to. It lives outside
+/// ~/.ssh so `Include` globs (e.g. `config.d/*`) can't pick up stale copies.
+fn backup_dir() -> Result<PathBuf> {
+    let config_dir = dirs::config_dir().context("Could not find config directory")?;
+    Ok(config_dir.join("ggoto").join("backups"))
+}
+
+/// File name for a config's backup, derived from its full path so configs with
+/// the same name in different directories don't share one
+fn backup_name(path: &Path) -> String {
+    let flat: String = path
+        .to_string_lossy()
+        .trim_start_matches(['/', '\\'])
+        .chars()
+        .map(|c| if matches!(c, '/' | '\\' | ':') { '%' } else { c })
+        .collect();
+    format!("{}.bak", flat)
+}
+
+/// Write `content` over `path` once `ssh -G` accepts it. The previous version is kept
+/// under ~/.config/ggoto/backups/. Returns the backup path.
+pub async fn write_config(path: &Path, content: &str, check_host: &str) -> Result<PathBuf> {
+    write_config_in(path, content, check_host, &backup_dir()?).await
+}
+
+async fn write_config_in(path: &Path, content: &str, check_host: &str, dir: &Path) -> Result<PathBuf> {
+    // Write through symlinks (e.g. configs managed in a dotfiles repo)
+    let path = fs::canonicalize(path).with_context(|| format!("Failed to resolve {:?}", path))?;
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    let name = backup_name(&path);
+    let tmp = dir.join(format!("{}.tmp", name));
+    let backup = dir.join(name);
+
+    fs::write(&tmp, content).with_context(|| format!("Failed to write {:?}", tmp))?;
+    if let Ok(metadata) = fs::metadata(&path) {
+        // ssh refuses configs that others can write, so keep the original permissions
+        let _ = fs::set_permissions(&tmp, metadata.permissions());
+    }
+
+    if let Err(e) = validate_config(&tmp, check_host).await {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+
+    fs::copy(&path, &backup).with_context(|| format!("Failed to back up {:?}", path))?;
+    // Renaming is atomic, but only works within one filesystem; otherwise write in place
+    if fs::rename(&tmp, &path).is_err() {
+        let _ = fs::remove_file(&tmp);
+        fs::write(&path, content).with_context(|| format!("Failed to replace {:?}", path))?;
+    }
+    Ok(backup)
+}
+
+/// Check that ssh can parse a config file by resolving a host with it
+async fn validate_config(path: &Path, check_host: &str) -> Result<()> {
+    let output = timeout(
+        Duration::from_secs(VALIDATE_TIMEOUT_SECS),
+        tokio::process::Command::new("ssh")
+            .arg("-F")
+            .arg(path)
+            .arg("-G")
+            .arg(check_host)
+            .output(),
+    )
+    .await
+    .context("ssh -G timed out while validating the config")?
+    .context("Failed to execute ssh -G")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("ssh rejected the edited config: {}", stderr.trim());
+    }
+    Ok(())
+}
+
+/// Apply an edit to its config file and describe the result for the status bar
+pub async fn apply_host_edit(edit: &HostEdit) -> Result<String> {
+    let (file, host) = match edit {
+        HostEdit::Add { file, entry } => (file, &entry.host),
+        HostEdit::Update { at, host, .. }
+        | HostEdit::Duplicate { at, host, .. }
+        | HostEdit::Delete { at, host } => (&at.file, host),
+    };
+
+    let content = if file.exists() {
+        fs::read_to_string(file).with_context(|| format!("Failed to read {:?}", file))?
+    } else {
+        String::new()
+    };
+
+    let (updated, summary) = match edit {
+        HostEdit::Add { entry, .. } => (add_host(&content, entry), format!("Added {}", entry.host)),
+        HostEdit::Update { at, host, entry } => (
+            update_host(&content, at.line, host, entry)?,
+            format!("Updated {}", entry.host),
+        ),
+        HostEdit::Duplicate { at, host, entry } => (
+            duplicate_host(&content, at.line, host, entry)?,
+            format!("Duplicated {} as {}", host, entry.host),
+        ),
+        HostEdit::Delete { at, host } => {
+            (delete_host(&content, at.line, host)?, format!("Deleted {}", host))
+        }
+    };
+
+    if !file.exists() {
+        fs::write(file, "").with_context(|| format!("Failed to create {:?}", file))?;
+    }
+    let backup = write_config(file, &updated, host).await?;
+    Ok(format!(
+        "{} in {} (backup: {})",
+        summary,
+        tilde_path(file),
+        tilde_path(&backup)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+# Work servers
+Host web1
+  HostName 10.0.0.1
+  # deploy user
+  User deploy
+  ForwardAgent yes
+
+Host db1 db2
+\tHostName=db.internal
+
+Host *
+  User fallback
+";
+
+    fn entry(host: &str, options: [&str; 5]) -> HostEntry {
+        HostEntry {
+            host: host.to_string(),
+            options: options.map(|o| o.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_read_host_entry() {
+        let entry = read_host_entry(CONFIG, 2, "web1").unwrap();
+        assert_eq!(entry.options[0], "10.0.0.1");
+        assert_eq!(entry.options[1], "deploy");
+        assert_eq!(entry.options[2], "");
+
+        // The location has to still point at the host's block
+        assert!(read_host_entry(CONFIG, 3, "web1").is_err());
+    }
+
+    #[test]
+    fn test_update_preserves_layout() {
+        let edited = entry("web1", ["10.0.0.9", "", "2222", "", ""]);
+        let updated = update_host(CONFIG, 2, "web1", &edited).unwrap();
+        assert_eq!(
+            updated,
+            "\
+# Work servers
+Host web1
+  HostName 10.0.0.9
+  # deploy user
+  ForwardAgent yes
+  Port 2222
+
+Host db1 db2
+\tHostName=db.internal
+
+Host *
+  User fallback
+"
+        );
+
+        // Blocks shared by several hosts are left alone
+        let shared = entry("db1", ["other", "", "", "", ""]);
+        assert!(update_host(CONFIG, 8, "db1", &shared).is_err());
+    }
+
+    #[test]
+    fn test_add_before_wildcards() {
+        let new = entry("cache1", ["10.0.0.5", "ops", "", "~/.ssh/my key", ""]);
+        let updated = add_host(CONFIG, &new);
+        assert!(updated.contains(
+            "\tHostName=db.internal\n\nHost cache1\n\tHostName 10.0.0.5\n\tUser ops\n\tIdentityFile \"~/.ssh/my key\"\n\nHost *\n"
+        ));
+
+        let updated = add_host("", &new);
+        assert!(updated.starts_with("Host cache1\n    HostName 10.0.0.5\n"));
+    }
+
+    #[test]
+    fn test_duplicate_and_delete() {
+        let copy = entry("web2", ["10.0.0.2", "deploy", "", "", ""]);
+        let updated = duplicate_host(CONFIG, 2, "web1", &copy).unwrap();
+        assert!(updated.contains(
+            "  ForwardAgent yes\n\nHost web2\n  HostName 10.0.0.2\n  # deploy user\n  User deploy\n  ForwardAgent yes\n\nHost db1 db2\n"
+        ));
+
+        // Removing one alias of a shared block only touches the Host line
+        let updated = delete_host(CONFIG, 8, "db1").unwrap();
+        assert!(updated.contains("\nHost db2\n\tHostName=db.internal\n"));
+
+        let updated = delete_host(CONFIG, 2, "web1").unwrap();
+        assert!(updated.starts_with("# Work servers\n\nHost db1 db2\n"));
+        assert!(!updated.contains("deploy"));
+    }
+
+    #[tokio::test]
+    async fn test_delete_from_included_file() {
+        use super::super::config::{parse_ssh_config, ConfigSource, ParseOptions};
+
+        let tmp_dir = std::env::temp_dir().join("ggoto_test_editor_include");
+        let _ = fs::remove_dir_all(&tmp_dir);
+        let conf_d = tmp_dir.join("config.d");
+        fs::create_dir_all(&conf_d).unwrap();
+
+        let main = tmp_dir.join("config");
+        let work = conf_d.join("work");
+        fs::write(&main, format!("Include {}/*\n\nHost main-box\n", conf_d.display())).unwrap();
+        fs::write(&work, "Host web1\n    HostName 10.0.0.1\n\nHost web2\n    HostName 10.0.0.2\n").unwrap();
+
+        let sources = vec![ConfigSource {
+            files: vec![main.clone()],
+            ssh_config: Some(main.clone()),
+        }];
+        let (servers, _) = parse_ssh_config(&sources, &ParseOptions::default()).unwrap();
+        let web1 = servers.iter().find(|s| s.host == "web1").unwrap();
+
+        let content = fs::read_to_string(&work).unwrap();
+        let at = web1.defined_at.clone().unwrap();
+        let updated = delete_host(&content, at.line, "web1").unwrap();
+        let backup = write_config_in(&work, &updated, "web2", &tmp_dir.join("backups"))
+            .await
+            .unwrap();
+
+        // Neither the backup nor the staged file lands where the Include glob looks
+        assert!(!backup.starts_with(&conf_d));
+        assert_eq!(fs::read_dir(&conf_d).unwrap().count(), 1);
+        assert!(fs::read_to_string(&backup).unwrap().contains("web1"));
+
+        let (servers, _) = parse_ssh_config(&sources, &ParseOptions::default()).unwrap();
+        let hosts: Vec<&str> = servers.iter().map(|s| s.host.as_str()).collect();
+        assert_eq!(hosts, vec!["web2", "main-box"]);
+
+        let _ = fs::remove_dir_all(&tmp_dir);
+    }
+}
@@ -0,0 +1,235 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+
+use super::config::local_username;
+use super::connection::config_file_args;
+use crate::server::{JumpHost, Server};
+
+/// How long `ssh -G` may take (it can run `Match exec` commands or canonicalize)
+const RESOLVE_TIMEOUT_SECS: u64 = 5;
+
+/// Maximum concurrent `ssh -G` processes when resolving the whole list
+const MAX_CONCURRENT_RESOLVES: usize = 8;
+
+/// Identity files OpenSSH tries by default; `ssh -G` lists them even when none is configured
+const DEFAULT_IDENTITY_FILES: &[&str] = &[
+    "id_rsa",
+    "id_ecdsa",
+    "id_ecdsa_sk",
+    "id_ed25519",
+    "id_ed25519_sk",
+    "id_xmss",
+    "id_dsa",
+];
+
+/// Options OpenSSH resolved for a host, as reported by `ssh -G`
+#[derive(Debug, Clone, Default)]
+pub struct EffectiveConfig {
+    /// Lowercase option names and values, in the order ssh printed them
+    pub options: Vec<(String, String)>,
+}
+
+impl EffectiveConfig {
+    /// Parse the `key value` lines printed by `ssh -G`
+    pub fn parse(output: &str) -> Self {
+        let options = output
+            .lines()
+            .filter_map(|line| {
+                let (key, value) = line.trim().split_once(char::is_whitespace)?;
+                Some((key.to_lowercase(), value.trim().to_string()))
+            })
+            .collect();
+        Self { options }
+    }
+
+    /// First value of an option
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// All values of a repeatable option (e.g. `identityfile`)
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.options
+            .iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    /// Configured identity files, without the defaults ssh always lists
+    fn explicit_identity_files(&self) -> Vec<String> {
+        self.get_all("identityfile")
+            .into_iter()
+            .filter(|path| {
+                let name = path.rsplit('/').next().unwrap_or(path);
+                let in_ssh_dir = path.starts_with("~/.ssh/") || path.contains("/.ssh/");
+                !(in_ssh_dir && DEFAULT_IDENTITY_FILES.contains(&name))
+            })
+            .map(expand_home)
+            .collect()
+    }
+
+    /// Whether ggoto's parsed value for `key` disagrees with what ssh resolved.
+    /// Only the options ggoto itself parses are compared.
+    pub fn differs(&self, server: &Server, key: &str) -> bool {
+        let value = self.get(key);
+        match key {
+            "hostname" => value.is_some_and(|v| !v.eq_ignore_ascii_case(&server.hostname)),
+            "user" => {
+                let parsed = server.user.clone().unwrap_or_else(local_username);
+                value.is_some_and(|v| v != parsed)
+            }
+            "port" => value.is_some_and(|v| v != server.port.to_string()),
+            "identityfile" => match server.identity_file {
+                Some(ref parsed) => !self
+                    .get_all("identityfile")
+                    .into_iter()
+                    .any(|v| expand_home(v) == *parsed),
+                None => !self.explicit_identity_files().is_empty(),
+            },
+            "proxyjump" => {
+                let parsed = if server.proxy_command.is_some() {
+                    String::new()
+                } else {
+                    server
+                        .jump_chain
+                        .iter()
+                        .map(|hop| hop.to_string())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                };
+                value.unwrap_or("") != parsed
+            }
+            "proxycommand" => value != server.proxy_command.as_deref(),
+            _ => false,
+        }
+    }
+
+    /// Overwrite the server's connection settings with the resolved ones
+    pub fn apply_to(&self, server: &mut Server) {
+        if let Some(hostname) = self.get("hostname") {
+            server.hostname = hostname.to_string();
+        }
+        if let Some(user) = self.get("user") {
+            server.user = Some(user.to_string());
+        }
+        if let Some(port) = self.get("port").and_then(|p| p.parse().ok()) {
+            server.port = port;
+        }
+        server.identity_file = self.explicit_identity_files().into_iter().next();
+
+        server.proxy_command = None;
+        server.jump_chain = Vec::new();
+        if let Some(jump) = self.get("proxyjump") {
+            server.jump_chain = JumpHost::parse_chain(jump);
+        } else if let Some(command) = self.get("proxycommand") {
+            server.jump_chain = JumpHost::from_proxy_command(command).into_iter().collect();
+            server.proxy_command = Some(command.to_string());
+        }
+    }
+}
+
+/// Expand a leading `~/` to the home directory
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest).to_string_lossy().to_string(),
+        _ => path.to_string(),
+    }
+}
+
+/// Ask OpenSSH for the effective configuration of a host (`ssh -G <host>`)
+pub async fn resolve_effective_config(server: &Server) -> Result<EffectiveConfig> {
+    let output = timeout(
+        Duration::from_secs(RESOLVE_TIMEOUT_SECS),
+        tokio::process::Command::new("ssh")
+            .args(config_file_args(server))
+            .arg("-G")
+            .arg(&server.host)
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .context("ssh -G timed out")?
+    .context("Failed to execute ssh -G")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("ssh -G failed: {}", stderr.trim());
+    }
+
+    Ok(EffectiveConfig::parse(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Make `ssh -G` the source of truth: resolve every server and overwrite its
+/// parsed settings. Servers that fail to resolve keep their parsed values.
+pub async fn apply_effective_configs(servers: &mut [Server]) {
+    let semaphore = std::sync::Arc::new(tokio::sync::Semaphore::new(MAX_CONCURRENT_RESOLVES));
+    let mut tasks = JoinSet::new();
+
+    for (idx, server) in servers.iter().enumerate() {
+        let server = server.clone();
+        let semaphore = semaphore.clone();
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire().await;
+            (idx, resolve_effective_config(&server).await)
+        });
+    }
+
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok((idx, Ok(config))) = joined {
+            config.apply_to(&mut servers[idx]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SSH_G_OUTPUT: &str = "\
+host web1
+user deploy
+hostname 10.0.0.7
+port 2222
+identityfile ~/.ssh/id_rsa
+identityfile /keys/web
+proxyjump admin@bastion:22
+forwardagent no
+";
+
+    #[test]
+    fn test_parse_ssh_g_output() {
+        let config = EffectiveConfig::parse(SSH_G_OUTPUT);
+        assert_eq!(config.get("hostname"), Some("10.0.0.7"));
+        assert_eq!(config.get_all("identityfile").len(), 2);
+        assert_eq!(config.explicit_identity_files(), vec!["/keys/web".to_string()]);
+    }
+
+    #[test]
+    fn test_differs_and_apply() {
+        let config = EffectiveConfig::parse(SSH_G_OUTPUT);
+        let mut server = Server::new("web1".to_string(), "10.0.0.7".to_string());
+        server.user = Some("deploy".to_string());
+
+        assert!(!config.differs(&server, "hostname"));
+        assert!(!config.differs(&server, "user"));
+        assert!(config.differs(&server, "port"));
+        assert!(config.differs(&server, "identityfile"));
+        assert!(config.differs(&server, "proxyjump"));
+        assert!(!config.differs(&server, "forwardagent"));
+
+        config.apply_to(&mut server);
+        assert_eq!(server.port, 2222);
+        assert_eq!(server.identity_file, Some("/keys/web".to_string()));
+        assert_eq!(server.bastion(), Some("bastion"));
+        for key in ["hostname", "user", "port", "identityfile", "proxyjump"] {
+            assert!(!config.differs(&server, key), "{} still differs", key);
+        }
+    }
+}
@@ -1,10 +1,22 @@
+pub mod backend;
 pub mod config;
 pub mod connection;
+pub mod editor;
+pub mod effective;
 pub mod mosh;
+pub mod mux;
+pub mod native;
+pub mod scripted;
 
-pub use config::{build_groups, group_servers, parse_ssh_config};
-pub use connection::{launch_ssh_session, run_remote_command};
+pub use backend::{Backend, SshBackend};
+pub use config::{
+    build_groups, build_groups_by, config_sources, group_servers, parse_ssh_config, ConfigSnapshot,
+    ConfigSource, ParseOptions,
+};
+pub use editor::{apply_host_edit, read_host_entry, HostEdit, HostEntry};
+pub use effective::{apply_effective_configs, resolve_effective_config, EffectiveConfig};
+pub use connection::config_file_args;
 pub use mosh::{
     get_install_instructions, install_mosh_locally, install_mosh_remotely, is_mosh_installed,
-    launch_mosh_session,
 };
+pub use scripted::ScriptedBackend;
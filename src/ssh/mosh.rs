@@ -3,6 +3,8 @@ use std::process::Command;
 use anyhow::{Context, Result};
 
 use crate::server::Server;
+use crate::ssh::connection::{config_file_args, login_shell_command};
+use crate::ssh::{mux, Backend};
 
 /// Check if mosh is installed locally
 pub fn is_mosh_installed() -> bool {
@@ -16,9 +18,9 @@ pub fn is_mosh_installed() -> bool {
 /// Check if mosh-server is available on a remote server
 /// This runs `which mosh-server` via SSH
 #[allow(dead_code)]
-pub async fn check_server_mosh(server: &Server) -> bool {
-    use crate::ssh::run_remote_command;
-    run_remote_command(server, "which mosh-server")
+pub async fn check_server_mosh(backend: &dyn Backend, server: &Server) -> bool {
+    backend
+        .run_command(server, "which mosh-server")
         .await
         .is_ok()
 }
@@ -126,9 +128,7 @@ pub fn install_mosh_locally() -> (bool, String) {
 
 /// Install mosh on a remote server
 /// Returns (success, output_message)
-pub async fn install_mosh_remotely(server: &Server) -> (bool, String) {
-    use crate::ssh::run_remote_command;
-
+pub async fn install_mosh_remotely(backend: &dyn Backend, server: &Server) -> (bool, String) {
     // Detect ALL available package managers
     // Note: Non-interactive SSH doesn't source .bashrc, so check common paths directly
     let detect_script = r#"
@@ -157,7 +157,7 @@ command -v apk >/dev/null 2>&1 && echo "apk"
 true
 "#;
 
-    let output = match run_remote_command(server, detect_script).await {
+    let output = match backend.run_command(server, detect_script).await {
         Ok(o) => o,
         Err(e) => return (false, format!("Failed to detect package manager: {}", e)),
     };
@@ -188,11 +188,9 @@ true
     // Try user-space package managers first (no sudo needed)
     for pm in &user_space {
         // Handle "conda:/path/to/conda" format
-        let (pm_name, install_cmd) = if pm.starts_with("conda:") {
-            let path = &pm[6..]; // Skip "conda:"
+        let (pm_name, install_cmd) = if let Some(path) = pm.strip_prefix("conda:") {
             ("conda", format!("{} install -y -c conda-forge mosh", path))
-        } else if pm.starts_with("mamba:") {
-            let path = &pm[6..]; // Skip "mamba:"
+        } else if let Some(path) = pm.strip_prefix("mamba:") {
             ("mamba", format!("{} install -y -c conda-forge mosh", path))
         } else {
             let cmd = match *pm {
@@ -205,10 +203,10 @@ true
             (*pm, cmd.to_string())
         };
 
-        match run_remote_command(server, &install_cmd).await {
+        match backend.run_command(server, &install_cmd).await {
             Ok(_) => {
                 // Verify installation
-                if run_remote_command(server, "which mosh-server").await.is_ok() {
+                if backend.run_command(server, "which mosh-server").await.is_ok() {
                     return (true, format!("Successfully installed mosh via {} on {}", pm_name, server.host));
                 }
             }
@@ -228,11 +226,11 @@ true
         };
 
         let install_cmd = format!("sudo -n {} 2>&1", cmd);
-        match run_remote_command(server, &install_cmd).await {
+        match backend.run_command(server, &install_cmd).await {
             Ok(output) => {
                 if !output.contains("sudo:") && !output.contains("permission denied") {
                     // Verify installation
-                    if run_remote_command(server, "which mosh-server").await.is_ok() {
+                    if backend.run_command(server, "which mosh-server").await.is_ok() {
                         return (true, format!("Successfully installed mosh via {} on {}", pm, server.host));
                     }
                 }
@@ -257,26 +255,53 @@ true
     (false, suggestions)
 }
 
-/// Launch a mosh session to the given server
-/// This replaces the current process with the mosh command
-pub fn launch_mosh_session(server: &Server) -> Result<()> {
-    let mut args = Vec::new();
+/// Quote a word for a shell-like splitter, leaving plain ones as they are
+fn shell_quote(word: &str) -> String {
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+    if plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
 
-    // Build SSH options string for non-default settings
-    let mut ssh_opts = Vec::new();
+/// The `--ssh` command for non-default settings. mosh splits it into words
+/// like a shell does, so paths with spaces are quoted.
+fn mosh_ssh_command(server: &Server) -> Option<String> {
+    let mut ssh_opts = config_file_args(server);
 
     if server.port != 22 {
-        ssh_opts.push(format!("-p {}", server.port));
+        ssh_opts.push("-p".to_string());
+        ssh_opts.push(server.port.to_string());
     }
 
     if let Some(ref identity) = server.identity_file {
-        ssh_opts.push(format!("-i {}", identity));
+        ssh_opts.push("-i".to_string());
+        ssh_opts.push(identity.clone());
     }
 
+    // Start mosh-server over the shared connection
+    ssh_opts.extend(mux::control_args());
+
+    if ssh_opts.is_empty() {
+        return None;
+    }
+    let words: Vec<String> = ssh_opts.iter().map(|opt| shell_quote(opt)).collect();
+    Some(format!("ssh {}", words.join(" ")))
+}
+
+/// Launch a mosh session to the given server
+/// This replaces the current process with the mosh command
+pub fn launch_mosh_session(server: &Server, env: &[(String, String)]) -> Result<()> {
+    let mut args = Vec::new();
+
     // Add --ssh option if we have custom SSH settings
-    if !ssh_opts.is_empty() {
+    if let Some(ssh) = mosh_ssh_command(server) {
         args.push("--ssh".to_string());
-        args.push(format!("ssh {}", ssh_opts.join(" ")));
+        args.push(ssh);
     }
 
     // If we know the mosh-server path (e.g., in conda), use --server flag
@@ -304,6 +329,12 @@ pub fn launch_mosh_session(server: &Server) -> Result<()> {
     };
     args.push(target);
 
+    // mosh-server runs a command as is, without a shell
+    if !env.is_empty() {
+        args.push("--".to_string());
+        args.extend(login_shell_command(env));
+    }
+
     // Execute mosh
     let status = Command::new("mosh")
         .args(&args)
@@ -320,6 +351,7 @@ pub fn launch_mosh_session(server: &Server) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ssh::ScriptedBackend;
 
     #[test]
     fn test_is_mosh_installed_runs() {
@@ -334,4 +366,41 @@ mod tests {
         assert!(instructions.contains("apt install mosh"));
         assert!(instructions.contains("60000-61000"));
     }
+
+    #[test]
+    fn test_mosh_ssh_command() {
+        let mut server = Server::new("web".to_string(), "10.0.0.1".to_string());
+        assert_eq!(mosh_ssh_command(&server), None);
+
+        server.port = 2222;
+        server.config_file = Some("/home/me/My Configs/ssh config".into());
+        server.identity_file = Some("/home/me/keys/it's mine".to_string());
+        assert_eq!(
+            mosh_ssh_command(&server).unwrap(),
+            r#"ssh -F '/home/me/My Configs/ssh config' -p 2222 -i '/home/me/keys/it'\''s mine'"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_install_mosh_remotely() {
+        let server = Server::new("web".to_string(), "10.0.0.1".to_string());
+
+        // A user-space conda install is tried first and verified
+        let conda = ScriptedBackend::new()
+            .reply("web", "echo \"user:\"", "user:\nconda:/opt/conda/bin/conda\nsystem:\napt\n")
+            .reply("web", "/opt/conda/bin/conda install -y -c conda-forge mosh", "done\n")
+            .reply("web", "which mosh-server", "/opt/conda/bin/mosh-server\n");
+        let (success, msg) = install_mosh_remotely(&conda, &server).await;
+        assert!(success, "{}", msg);
+        assert!(msg.contains("via conda"));
+        assert!(conda.calls().iter().all(|c| !c.contains("sudo")));
+
+        // Without passwordless sudo, apt fails and manual steps are suggested
+        let apt = ScriptedBackend::new()
+            .reply("web", "echo \"user:\"", "user:\nsystem:\napt\n")
+            .reply("web", "sudo -n apt install -y mosh", "sudo: a password is required\n");
+        let (success, msg) = install_mosh_remotely(&apt, &server).await;
+        assert!(!success);
+        assert!(msg.contains("ssh web 'sudo apt install mosh'"));
+    }
 }
@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, Weak};
+
+use anyhow::{Context, Result};
+
+use crate::server::Server;
+use crate::ssh::config_file_args;
+
+/// How long an idle master stays up. Longer than the refresh interval so
+/// periodic checks keep reusing it.
+const CONTROL_PERSIST_SECS: u64 = 300;
+
+/// Socket directory for shared connections (OpenSSH ControlMaster), set once
+/// multiplexing is turned on. The first ssh to a host becomes the master and
+/// later checks, commands, tunnels and sessions reuse it without a handshake.
+static CONTROL_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Holds on the masters that tunnels use, by the arguments that reach them
+static HOLDS: OnceLock<Mutex<HashMap<Vec<String>, Weak<MasterHold>>>> = OnceLock::new();
+
+/// Keeps a master up while tunnels forward through it. ControlPersist only
+/// counts open channels, not forwarded ports, so an idle tunnel would lose its
+/// master after a few minutes. This holds a session open running `cat`, which
+/// exits on its own once the hold is dropped and its input closes.
+#[derive(Debug)]
+pub struct MasterHold {
+    process: Mutex<Child>,
+}
+
+impl Drop for MasterHold {
+    fn drop(&mut self) {
+        let process = self.process.get_mut().unwrap_or_else(PoisonError::into_inner);
+        let _ = process.kill();
+        let _ = process.wait();
+    }
+}
+
+impl MasterHold {
+    /// Whether the session holding the master is still open
+    pub fn is_alive(&self) -> bool {
+        let mut process = self.process.lock().unwrap_or_else(PoisonError::into_inner);
+        matches!(process.try_wait(), Ok(None))
+    }
+}
+
+/// A hold on the server's master, shared by all of its tunnels
+pub fn hold_master(server: &Server) -> Result<Arc<MasterHold>> {
+    let master_args = master_args(server);
+    let mut holds = HOLDS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some(hold) = holds.get(&master_args).and_then(Weak::upgrade) {
+        if hold.is_alive() {
+            return Ok(hold);
+        }
+    }
+
+    let process = Command::new("ssh")
+        .args(&master_args)
+        .args(["-T", &server.host, "cat >/dev/null"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to hold the shared connection open")?;
+    let hold = Arc::new(MasterHold {
+        process: Mutex::new(process),
+    });
+    holds.retain(|_, hold| hold.strong_count() > 0);
+    holds.insert(master_args, Arc::downgrade(&hold));
+    Ok(hold)
+}
+
+/// Shuts down the masters and removes the socket dir when dropped
+pub struct MuxGuard;
+
+impl Drop for MuxGuard {
+    fn drop(&mut self) {
+        cleanup();
+    }
+}
+
+/// Create the socket dir and turn multiplexing on for this process
+pub fn init() -> Result<MuxGuard> {
+    let dir = control_dir_path();
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+    }
+    let _ = CONTROL_DIR.set(dir);
+    Ok(MuxGuard)
+}
+
+/// Where this process keeps its sockets. Unix socket paths are limited to about
+/// 100 bytes, so this stays short: the user runtime dir, or /tmp.
+fn control_dir_path() -> PathBuf {
+    let base = dirs::runtime_dir().unwrap_or_else(|| PathBuf::from("/tmp"));
+    base.join(format!("ggoto-{}", std::process::id()))
+}
+
+/// Whether connections are being shared
+pub fn is_enabled() -> bool {
+    CONTROL_DIR.get().is_some()
+}
+
+/// ssh options that create or reuse the shared connection; empty when
+/// multiplexing is off
+pub fn control_args() -> Vec<String> {
+    CONTROL_DIR
+        .get()
+        .map(|dir| control_args_for(dir))
+        .unwrap_or_default()
+}
+
+fn control_args_for(dir: &Path) -> Vec<String> {
+    vec![
+        "-o".to_string(),
+        "ControlMaster=auto".to_string(),
+        "-o".to_string(),
+        format!("ControlPath={}", dir.join("%C").display()),
+        "-o".to_string(),
+        format!("ControlPersist={}", CONTROL_PERSIST_SECS),
+    ]
+}
+
+/// Arguments that reach the server's master with `ssh -O <op> <host>`. They
+/// must resolve to the same ControlPath as the connection, so user, port and
+/// config file match.
+pub fn master_args(server: &Server) -> Vec<String> {
+    let mut args = config_file_args(server);
+    args.extend(control_args());
+    if let Some(ref user) = server.user {
+        args.push("-l".to_string());
+        args.push(user.clone());
+    }
+    if server.port != 22 {
+        args.push("-p".to_string());
+        args.push(server.port.to_string());
+    }
+    args
+}
+
+/// Whether a master is up for the server
+pub fn master_running(server: &Server) -> bool {
+    if !is_enabled() {
+        return false;
+    }
+    Command::new("ssh")
+        .args(master_args(server))
+        .args(["-O", "check", &server.host])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Same as `master_running`, without blocking the runtime
+pub async fn master_running_async(server: &Server) -> bool {
+    if !is_enabled() {
+        return false;
+    }
+    tokio::process::Command::new("ssh")
+        .args(master_args(server))
+        .args(["-O", "check", &server.host])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .is_ok_and(|s| s.success())
+}
+
+/// Shut down every master and remove the socket dir
+fn cleanup() {
+    let Some(dir) = CONTROL_DIR.get() else {
+        return;
+    };
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            // The socket path is given literally, so the host name is a placeholder
+            let _ = Command::new("ssh")
+                .args(["-F", "/dev/null", "-o"])
+                .arg(format!("ControlPath={}", entry.path().display()))
+                .args(["-O", "exit", "ggoto"])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+    }
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_control_args() {
+        let args = control_args_for(Path::new("/run/user/1000/ggoto-42"));
+        assert_eq!(
+            args,
+            vec![
+                "-o",
+                "ControlMaster=auto",
+                "-o",
+                "ControlPath=/run/user/1000/ggoto-42/%C",
+                "-o",
+                "ControlPersist=300",
+            ]
+        );
+
+        let path = control_dir_path();
+        assert!(path.ends_with(format!("ggoto-{}", std::process::id())));
+        // Leave room for the 40-character %C hash within the socket path limit
+        assert!(path.as_os_str().len() + 41 < 104);
+    }
+}
@@ -0,0 +1,661 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use ssh2::{Channel, CheckResult, ErrorCode, KnownHostFileKind, Session};
+
+use crate::server::{ConnectError, FailureKind, Server};
+use crate::ssh::backend::{Backend, BoxFuture, NeedsExternal};
+use crate::ssh::connection::{
+    command_result, config_file_args, ExternalSsh, COMMAND_TIMEOUT_SECS, CONNECT_TIMEOUT_SECS,
+};
+use crate::ssh::effective::EffectiveConfig;
+use crate::tunnel::TunnelHandle;
+
+/// Keys tried after ssh-agent when the config doesn't name one, as OpenSSH does
+const DEFAULT_IDENTITIES: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// Seconds between keepalives on a forwarding session
+const KEEPALIVE_SECS: u32 = 30;
+
+/// How long a forwarding thread sleeps when no data is moving
+const FORWARD_IDLE: Duration = Duration::from_millis(10);
+
+/// libssh2's "would block" error in non-blocking mode
+const LIBSSH2_ERROR_EAGAIN: i32 = -37;
+
+/// Where a session goes. Servers with the same destination share a session.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Destination {
+    user: String,
+    hostname: String,
+    port: u16,
+    identity_file: Option<PathBuf>,
+}
+
+impl Destination {
+    fn of(server: &Server) -> Result<Self> {
+        let user = match server.user {
+            Some(ref user) => user.clone(),
+            None => std::env::var("USER")
+                .or_else(|_| std::env::var("LOGNAME"))
+                .map_err(|_| NeedsExternal("Unknown local user name".to_string()))?,
+        };
+        Ok(Self {
+            user,
+            hostname: server.hostname.clone(),
+            port: server.port,
+            identity_file: server.identity_file.as_deref().map(expand_home),
+        })
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Reaches servers with libssh2 inside ggoto, keeping one authenticated session
+/// per destination. Hosts behind a ProxyJump or ProxyCommand, and hosts it can't
+/// verify or log in to, are left to the `ssh` binary.
+#[derive(Default)]
+pub struct NativeSsh {
+    sessions: Arc<Mutex<HashMap<Destination, Arc<Mutex<Session>>>>>,
+    /// Destinations that needed `ssh`; they keep using it until ggoto restarts
+    handed_off: Mutex<HashSet<Destination>>,
+    /// Per config file and host: whether `ssh -G` shows options libssh2 can't honor
+    needs_openssh: Mutex<HashMap<(Option<PathBuf>, String), bool>>,
+}
+
+impl NativeSsh {
+    /// Whether the server can be reached without the `ssh` binary
+    pub fn supports(&self, server: &Server) -> bool {
+        if server.is_proxied() || self.needs_openssh(server) {
+            return false;
+        }
+        Destination::of(server).is_ok_and(|dest| !lock(&self.handed_off).contains(&dest))
+    }
+
+    /// Whether the host's effective config uses an option only `ssh` honors.
+    /// Asked once per host; a host `ssh -G` can't resolve is left to `ssh` too.
+    fn needs_openssh(&self, server: &Server) -> bool {
+        let key = (server.config_file.clone(), server.host.clone());
+        if let Some(&needs) = lock(&self.needs_openssh).get(&key) {
+            return needs;
+        }
+        let output = Command::new("ssh")
+            .args(config_file_args(server))
+            .arg("-G")
+            .arg(&server.host)
+            .stdin(Stdio::null())
+            .output();
+        let needs = match output {
+            Ok(output) if output.status.success() => {
+                let config = EffectiveConfig::parse(&String::from_utf8_lossy(&output.stdout));
+                unsupported_option(&config).is_some()
+            }
+            _ => true,
+        };
+        lock(&self.needs_openssh).insert(key, needs);
+        needs
+    }
+
+    /// Remember that a destination needs `ssh` when the error says so
+    fn note_error<T>(&self, dest: &Destination, result: Result<T>) -> Result<T> {
+        if let Err(ref e) = result {
+            if e.is::<NeedsExternal>() {
+                lock(&self.handed_off).insert(dest.clone());
+            }
+        }
+        result
+    }
+}
+
+impl Backend for NativeSsh {
+    fn keeps_connections(&self, _server: &Server) -> bool {
+        true
+    }
+
+    fn is_connected<'a>(&'a self, server: &'a Server) -> BoxFuture<'a, bool> {
+        let connected =
+            Destination::of(server).is_ok_and(|dest| lock(&self.sessions).contains_key(&dest));
+        Box::pin(async move { connected })
+    }
+
+    fn run_command<'a>(
+        &'a self,
+        server: &'a Server,
+        command: &'a str,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            let dest = Destination::of(server)?;
+            let sessions = self.sessions.clone();
+            let (task_dest, command) = (dest.clone(), command.to_string());
+            let result =
+                tokio::task::spawn_blocking(move || run_pooled(&sessions, &task_dest, &command))
+                    .await
+                    .context("SSH task failed")?;
+            self.note_error(&dest, result)
+        })
+    }
+
+    fn open_forward(
+        &self,
+        server: &Server,
+        local_port: u16,
+        remote_host: &str,
+        remote_port: u16,
+    ) -> Result<TunnelHandle> {
+        let dest = Destination::of(server)?;
+        let listener = TcpListener::bind(("127.0.0.1", local_port))
+            .with_context(|| format!("Port {} is in use", local_port))?;
+        // Forwarding switches the session to non-blocking mode, so it gets its own
+        let session = self.note_error(&dest, connect(&dest))?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            let remote_host = remote_host.to_string();
+            thread::spawn(move || forward_loop(session, listener, &remote_host, remote_port, &stop))
+        };
+        Ok(TunnelHandle::Native(NativeForward {
+            stop,
+            thread: Some(thread),
+        }))
+    }
+
+    fn launch_session(&self, server: &Server, use_mosh: bool, env: &[(String, String)]) -> Result<()> {
+        // A terminal session is the ssh binary's job
+        ExternalSsh.launch_session(server, use_mosh, env)
+    }
+}
+
+/// The first option in an effective config that libssh2 can't honor: certificates,
+/// host key aliases, PKCS#11 tokens, GSSAPI, keys limited to the configured ones,
+/// or a known_hosts file other than ~/.ssh/known_hosts
+fn unsupported_option(config: &EffectiveConfig) -> Option<&'static str> {
+    if config.get("certificatefile").is_some() {
+        return Some("CertificateFile");
+    }
+    if config.get("hostkeyalias").is_some() {
+        return Some("HostKeyAlias");
+    }
+    if config.get("pkcs11provider").is_some_and(|v| v != "none") {
+        return Some("PKCS11Provider");
+    }
+    if config.get("identitiesonly") == Some("yes") {
+        return Some("IdentitiesOnly");
+    }
+    if config.get("gssapiauthentication") == Some("yes") {
+        return Some("GSSAPIAuthentication");
+    }
+    let default_known_hosts = dirs::home_dir().map(|home| {
+        let ssh_dir = home.join(".ssh");
+        [ssh_dir.join("known_hosts"), ssh_dir.join("known_hosts2")]
+    });
+    let custom_known_hosts = config.get("userknownhostsfile").is_some_and(|files| {
+        files.split_whitespace().any(|file| {
+            let file = expand_home(file);
+            default_known_hosts.as_ref().is_none_or(|d| !d.contains(&file))
+        })
+    });
+    if custom_known_hosts {
+        return Some("UserKnownHostsFile");
+    }
+    None
+}
+
+/// Why a command couldn't be run on a session
+enum ExecError {
+    /// The channel couldn't be opened or the command wasn't accepted, so nothing ran
+    Start(anyhow::Error),
+    /// The command was started; running it again could repeat its side effects
+    Run(anyhow::Error),
+}
+
+impl From<ExecError> for anyhow::Error {
+    fn from(e: ExecError) -> Self {
+        match e {
+            ExecError::Start(e) | ExecError::Run(e) => e,
+        }
+    }
+}
+
+/// Run a command on the pooled session, reconnecting once if the server
+/// closed it since the last use. A command that already started (e.g. one
+/// that timed out) is never retried.
+fn run_pooled(
+    sessions: &Mutex<HashMap<Destination, Arc<Mutex<Session>>>>,
+    dest: &Destination,
+    command: &str,
+) -> Result<String> {
+    let pooled = lock(sessions).get(dest).cloned();
+    if let Some(session) = pooled {
+        let result = exec(&lock(&session), command);
+        match result {
+            Ok((code, stdout, stderr)) => return command_result(Some(code), &stdout, &stderr),
+            Err(ExecError::Start(_)) => {
+                lock(sessions).remove(dest);
+            }
+            Err(ExecError::Run(e)) => {
+                lock(sessions).remove(dest);
+                return Err(e);
+            }
+        }
+    }
+
+    let session = Arc::new(Mutex::new(connect(dest)?));
+    lock(sessions).insert(dest.clone(), session.clone());
+    let result = exec(&lock(&session), command);
+    match result {
+        Ok((code, stdout, stderr)) => command_result(Some(code), &stdout, &stderr),
+        Err(e) => {
+            lock(sessions).remove(dest);
+            Err(e.into())
+        }
+    }
+}
+
+/// Run a command on a session; returns the exit code, stdout and stderr
+fn exec(session: &Session, command: &str) -> Result<(i32, Vec<u8>, Vec<u8>), ExecError> {
+    let mut channel = session
+        .channel_session()
+        .map_err(|e| ExecError::Start(e.into()))?;
+    channel.exec(command).map_err(|e| ExecError::Start(e.into()))?;
+
+    // Read both streams without blocking, so a command that fills stderr
+    // before closing stdout can't stall on a full window
+    session.set_blocking(false);
+    let output = read_output(&mut channel);
+    session.set_blocking(true);
+
+    let run = |channel: &mut Channel| -> Result<(i32, Vec<u8>, Vec<u8>)> {
+        let (stdout, stderr) = output?;
+        channel.wait_close()?;
+        Ok((channel.exit_status()?, stdout, stderr))
+    };
+    run(&mut channel).map_err(ExecError::Run)
+}
+
+/// Read stdout and stderr from a non-blocking channel until the command closes
+/// both, or until the command timeout
+fn read_output(channel: &mut Channel) -> Result<(Vec<u8>, Vec<u8>)> {
+    let deadline = Instant::now() + Duration::from_secs(COMMAND_TIMEOUT_SECS);
+    let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+    let mut buf = vec![0u8; 32 * 1024];
+    loop {
+        let mut moved = false;
+        for (stream, out) in [(0, &mut stdout), (1, &mut stderr)] {
+            match channel.stream(stream).read(&mut buf) {
+                Ok(n) => {
+                    out.extend_from_slice(&buf[..n]);
+                    moved |= n > 0;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if moved {
+            continue;
+        }
+        if channel.eof() {
+            return Ok((stdout, stderr));
+        }
+        if Instant::now() >= deadline {
+            return Err(ConnectError::new(
+                FailureKind::Timeout,
+                format!("No answer within {}s", COMMAND_TIMEOUT_SECS),
+            )
+            .into());
+        }
+        thread::sleep(FORWARD_IDLE);
+    }
+}
+
+/// Open, verify and authenticate a session
+fn connect(dest: &Destination) -> Result<Session> {
+    let addr = (dest.hostname.as_str(), dest.port)
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| {
+            ConnectError::new(
+                FailureKind::Dns,
+                format!("Could not resolve {}", dest.hostname),
+            )
+        })?;
+    let tcp = TcpStream::connect_timeout(&addr, Duration::from_secs(CONNECT_TIMEOUT_SECS))
+        .map_err(|e| {
+            let kind = match e.kind() {
+                io::ErrorKind::TimedOut => FailureKind::Timeout,
+                io::ErrorKind::ConnectionRefused => FailureKind::Refused,
+                _ => FailureKind::Other,
+            };
+            ConnectError::new(kind, format!("Could not connect to {}: {}", addr, e))
+        })?;
+
+    let mut session = Session::new()?;
+    session.set_timeout((COMMAND_TIMEOUT_SECS * 1000) as u32);
+    session.set_tcp_stream(tcp);
+    session.handshake().map_err(|e| {
+        ConnectError::new(FailureKind::Other, format!("SSH handshake failed: {}", e))
+    })?;
+    check_host_key(&session, dest)?;
+    authenticate(&session, dest)?;
+    Ok(session)
+}
+
+/// Check the host key against ~/.ssh/known_hosts. Unknown hosts go to `ssh`,
+/// which asks about them; a changed key is an error.
+fn check_host_key(session: &Session, dest: &Destination) -> Result<()> {
+    let (key, _) = session.host_key().context("Server sent no host key")?;
+    let mut known_hosts = session.known_hosts()?;
+    let path = dirs::home_dir().map(|home| home.join(".ssh").join("known_hosts"));
+    if let Some(path) = path.filter(|p| p.exists()) {
+        if known_hosts
+            .read_file(&path, KnownHostFileKind::OpenSSH)
+            .is_err()
+        {
+            return Err(NeedsExternal(format!("Could not read {:?}", path)).into());
+        }
+    }
+    match known_hosts.check_port(&dest.hostname, dest.port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => Err(ConnectError::new(
+            FailureKind::HostKey,
+            format!("Host key for {} does not match known_hosts", dest.hostname),
+        )
+        .into()),
+        CheckResult::NotFound | CheckResult::Failure => {
+            Err(NeedsExternal(format!("{} is not in known_hosts", dest.hostname)).into())
+        }
+    }
+}
+
+/// Log in with ssh-agent, then with the configured or default identity files
+fn authenticate(session: &Session, dest: &Destination) -> Result<()> {
+    let _ = session.userauth_agent(&dest.user);
+
+    let identities = match dest.identity_file {
+        Some(ref path) => vec![path.clone()],
+        None => dirs::home_dir()
+            .map(|home| {
+                DEFAULT_IDENTITIES
+                    .iter()
+                    .map(|name| home.join(".ssh").join(name))
+                    .filter(|path| path.exists())
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+    for identity in identities {
+        if session.authenticated() {
+            break;
+        }
+        let _ = session.userauth_pubkey_file(&dest.user, None, &identity, None);
+    }
+
+    if session.authenticated() {
+        Ok(())
+    } else {
+        Err(NeedsExternal(format!(
+            "No key from ssh-agent or identity files was accepted for {}@{}",
+            dest.user, dest.hostname
+        ))
+        .into())
+    }
+}
+
+/// A local port forward running on its own thread and session
+#[derive(Debug)]
+pub struct NativeForward {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl NativeForward {
+    /// Whether the forward is still accepting connections
+    pub fn is_alive(&self) -> bool {
+        self.thread.as_ref().is_some_and(|t| !t.is_finished())
+    }
+
+    /// Stop forwarding and wait for the thread
+    pub fn close(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Accept local connections and move data between them and direct-tcpip
+/// channels until stopped or the session dies
+fn forward_loop(
+    session: Session,
+    listener: TcpListener,
+    remote_host: &str,
+    remote_port: u16,
+    stop: &AtomicBool,
+) {
+    if listener.set_nonblocking(true).is_err() {
+        return;
+    }
+    session.set_keepalive(false, KEEPALIVE_SECS);
+    session.set_blocking(false);
+
+    let mut pipes: Vec<Pipe> = Vec::new();
+    let mut buf = vec![0u8; 32 * 1024];
+    while !stop.load(Ordering::Relaxed) {
+        let mut busy = false;
+
+        if let Ok((socket, _)) = listener.accept() {
+            busy = true;
+            // Opening the channel is a request/response; do it blocking
+            session.set_blocking(true);
+            let channel = session.channel_direct_tcpip(remote_host, remote_port, None);
+            session.set_blocking(false);
+            if let Ok(channel) = channel {
+                if socket.set_nonblocking(true).is_ok() {
+                    pipes.push(Pipe::new(socket, channel));
+                }
+            }
+        }
+
+        pipes.retain_mut(|pipe| match pipe.pump(&mut buf) {
+            Ok(Pump::Moved) => {
+                busy = true;
+                true
+            }
+            Ok(Pump::Idle) => true,
+            Ok(Pump::Closed) | Err(_) => false,
+        });
+
+        if !busy {
+            match session.keepalive_send() {
+                Err(e) if e.code() != ErrorCode::Session(LIBSSH2_ERROR_EAGAIN) => break,
+                _ => thread::sleep(FORWARD_IDLE),
+            }
+        }
+    }
+}
+
+/// What one round of pumping did
+enum Pump {
+    Moved,
+    Idle,
+    Closed,
+}
+
+/// One forwarded connection: a local socket and its channel
+struct Pipe {
+    socket: TcpStream,
+    channel: Channel,
+    to_remote: Vec<u8>,
+    to_local: Vec<u8>,
+    /// The local side closed and the channel was sent EOF once `to_remote` drained
+    local_eof: bool,
+    eof_sent: bool,
+    /// The remote side closed; the socket's write half is shut once `to_local` drains
+    remote_eof: bool,
+    socket_shut: bool,
+}
+
+impl Pipe {
+    fn new(socket: TcpStream, channel: Channel) -> Self {
+        Self {
+            socket,
+            channel,
+            to_remote: Vec::new(),
+            to_local: Vec::new(),
+            local_eof: false,
+            eof_sent: false,
+            remote_eof: false,
+            socket_shut: false,
+        }
+    }
+
+    /// Move whatever data is ready in both directions without blocking. When
+    /// one side closes, its data is flushed and the close passed on while the
+    /// other direction keeps going; the pipe closes once both are done.
+    fn pump(&mut self, buf: &mut [u8]) -> io::Result<Pump> {
+        let mut moved = false;
+
+        if !self.local_eof && self.to_remote.is_empty() {
+            match self.socket.read(buf) {
+                Ok(0) => {
+                    self.local_eof = true;
+                    moved = true;
+                }
+                Ok(n) => self.to_remote.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if !self.to_remote.is_empty() {
+            moved |= drain_into(&mut self.channel, &mut self.to_remote)?;
+        }
+        if self.local_eof && !self.eof_sent && self.to_remote.is_empty() {
+            match self.channel.send_eof() {
+                Ok(()) => {
+                    self.eof_sent = true;
+                    moved = true;
+                }
+                Err(e) if e.code() == ErrorCode::Session(LIBSSH2_ERROR_EAGAIN) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        if !self.remote_eof && self.to_local.is_empty() {
+            match self.channel.read(buf) {
+                Ok(0) if self.channel.eof() => {
+                    self.remote_eof = true;
+                    moved = true;
+                }
+                Ok(n) => self.to_local.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if !self.to_local.is_empty() {
+            moved |= drain_into(&mut self.socket, &mut self.to_local)?;
+        }
+        if self.remote_eof && !self.socket_shut && self.to_local.is_empty() {
+            self.socket.shutdown(Shutdown::Write)?;
+            self.socket_shut = true;
+            moved = true;
+        }
+
+        Ok(if self.eof_sent && self.socket_shut {
+            Pump::Closed
+        } else if moved {
+            Pump::Moved
+        } else {
+            Pump::Idle
+        })
+    }
+}
+
+/// Write as much of `pending` as the writer takes; true if anything was written
+fn drain_into(writer: &mut impl Write, pending: &mut Vec<u8>) -> io::Result<bool> {
+    match writer.write(pending) {
+        Ok(n) => {
+            pending.drain(..n);
+            Ok(n > 0)
+        }
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_native_support() {
+        let native = NativeSsh::default();
+        let mut direct = Server::new("web".to_string(), "10.0.0.1".to_string());
+        direct.user = Some("deploy".to_string());
+        direct.identity_file = Some("~/.ssh/deploy".to_string());
+        // Stand in for `ssh -G`, which reads the machine's own config
+        lock(&native.needs_openssh).insert((None, "web".to_string()), false);
+        assert!(native.supports(&direct));
+
+        let dest = Destination::of(&direct).unwrap();
+        assert_eq!(dest.user, "deploy");
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(dest.identity_file, Some(home.join(".ssh/deploy")));
+        }
+
+        // Proxies are left to ssh
+        let mut proxied = direct.clone();
+        proxied.proxy_command = Some("nc -X 5 -x socks:1080 %h %p".to_string());
+        assert!(!native.supports(&proxied));
+
+        // And hosts whose config uses options libssh2 can't honor
+        let mut certified = direct.clone();
+        certified.host = "web-cert".to_string();
+        lock(&native.needs_openssh).insert((None, "web-cert".to_string()), true);
+        assert!(!native.supports(&certified));
+
+        // So are hosts that needed ssh once
+        let result: Result<()> = Err(NeedsExternal("unknown host key".to_string()).into());
+        assert!(native.note_error(&dest, result).is_err());
+        assert!(!native.supports(&direct));
+    }
+
+    #[test]
+    fn test_unsupported_options() {
+        let plain = EffectiveConfig::parse(
+            "identitiesonly no\n\
+             gssapiauthentication no\n\
+             userknownhostsfile ~/.ssh/known_hosts ~/.ssh/known_hosts2\n",
+        );
+        assert_eq!(unsupported_option(&plain), None);
+
+        for (line, option) in [
+            ("certificatefile ~/.ssh/id_ed25519-cert.pub", "CertificateFile"),
+            ("hostkeyalias web", "HostKeyAlias"),
+            ("pkcs11provider /usr/lib/opensc-pkcs11.so", "PKCS11Provider"),
+            ("identitiesonly yes", "IdentitiesOnly"),
+            ("gssapiauthentication yes", "GSSAPIAuthentication"),
+            ("userknownhostsfile /etc/ggoto/known_hosts", "UserKnownHostsFile"),
+        ] {
+            let config = EffectiveConfig::parse(line);
+            assert_eq!(unsupported_option(&config), Some(option), "{}", line);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::server::Server;
+use crate::ssh::backend::{Backend, BoxFuture};
+use crate::tunnel::TunnelHandle;
+
+/// A canned answer to commands on a host
+#[derive(Debug, Clone)]
+struct Reply {
+    /// Host alias, or `*` for any host
+    host: String,
+    /// Matches commands containing this text
+    command: String,
+    delay: Duration,
+    result: Result<String, String>,
+}
+
+/// A backend that answers from a script instead of connecting anywhere. The
+/// first reply whose host and command match is used; commands without one fail.
+/// Every call is recorded, so tests can check what ggoto did.
+#[derive(Debug, Default)]
+pub struct ScriptedBackend {
+    replies: Vec<Reply>,
+    session_error: Option<String>,
+    calls: Mutex<Vec<String>>,
+}
+
+impl ScriptedBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer commands containing `command` on `host` (`*` for any) with `output`
+    pub fn reply(mut self, host: &str, command: &str, output: &str) -> Self {
+        self.replies.push(Reply {
+            host: host.to_string(),
+            command: command.to_string(),
+            delay: Duration::ZERO,
+            result: Ok(output.to_string()),
+        });
+        self
+    }
+
+    /// Fail commands containing `command` on `host` (`*` for any) with `error`
+    pub fn fail(mut self, host: &str, command: &str, error: &str) -> Self {
+        self.replies.push(Reply {
+            host: host.to_string(),
+            command: command.to_string(),
+            delay: Duration::ZERO,
+            result: Err(error.to_string()),
+        });
+        self
+    }
+
+    /// Make the last reply take this long, like a round trip would
+    pub fn after(mut self, delay: Duration) -> Self {
+        if let Some(reply) = self.replies.last_mut() {
+            reply.delay = delay;
+        }
+        self
+    }
+
+    /// Fail interactive sessions with `error` instead of pretending they ran
+    pub fn refuse_sessions(mut self, error: &str) -> Self {
+        self.session_error = Some(error.to_string());
+        self
+    }
+
+    /// Calls made so far, like `run web: uptime`, `forward web 8000:localhost:80`
+    /// or `session web`
+    #[cfg(test)]
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn record(&self, call: String) {
+        self.calls.lock().unwrap().push(call);
+    }
+}
+
+impl Backend for ScriptedBackend {
+    fn keeps_connections(&self, _server: &Server) -> bool {
+        false
+    }
+
+    fn is_connected<'a>(&'a self, _server: &'a Server) -> BoxFuture<'a, bool> {
+        Box::pin(async { false })
+    }
+
+    fn run_command<'a>(
+        &'a self,
+        server: &'a Server,
+        command: &'a str,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            self.record(format!("run {}: {}", server.host, command.trim()));
+            let reply = self
+                .replies
+                .iter()
+                .find(|r| (r.host == "*" || r.host == server.host) && command.contains(&r.command));
+            let Some(reply) = reply else {
+                anyhow::bail!(
+                    "No scripted reply for `{}` on {}",
+                    command.trim(),
+                    server.host
+                );
+            };
+            if !reply.delay.is_zero() {
+                tokio::time::sleep(reply.delay).await;
+            }
+            reply.result.clone().map_err(anyhow::Error::msg)
+        })
+    }
+
+    fn open_forward(
+        &self,
+        server: &Server,
+        local_port: u16,
+        remote_host: &str,
+        remote_port: u16,
+    ) -> Result<TunnelHandle> {
+        self.record(format!(
+            "forward {} {}:{}:{}",
+            server.host, local_port, remote_host, remote_port
+        ));
+        Ok(TunnelHandle::Scripted)
+    }
+
+    fn launch_session(&self, server: &Server, _use_mosh: bool, env: &[(String, String)]) -> Result<()> {
+        let mut call = format!("session {}", server.host);
+        for (name, value) in env {
+            call.push_str(&format!(" {}={}", name, value));
+        }
+        self.record(call);
+        match self.session_error {
+            Some(ref error) => anyhow::bail!("{}", error),
+            None => Ok(()),
+        }
+    }
+
+    fn session_refusal(&self) -> Option<String> {
+        self.session_error.clone()
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::server::Server;
+
+/// Tags assigned from within ggoto, on top of those in the SSH config
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TagFile {
+    /// Map from server host to its tags
+    pub hosts: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl TagFile {
+    /// Get the tag file path
+    fn tags_path() -> Result<PathBuf> {
+        let config_dir = dirs::config_dir()
+            .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?;
+        let ggoto_dir = config_dir.join("ggoto");
+        fs::create_dir_all(&ggoto_dir)?;
+        Ok(ggoto_dir.join("tags.json"))
+    }
+
+    /// Load tags from disk
+    pub fn load() -> Result<Self> {
+        let path = Self::tags_path()?;
+        if path.exists() {
+            let content = fs::read_to_string(&path)?;
+            serde_json::from_str(&content).with_context(|| format!("Invalid tags in {:?}", path))
+        } else {
+            Ok(TagFile::default())
+        }
+    }
+
+    /// Save tags to disk
+    pub fn save(&self) -> Result<()> {
+        let path = Self::tags_path()?;
+        let content = serde_json::to_string_pretty(&self)?;
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Tags set for a server
+    pub fn get(&self, host: &str) -> BTreeSet<String> {
+        self.hosts.get(host).cloned().unwrap_or_default()
+    }
+
+    /// Replace a server's tags; an empty set removes its entry
+    pub fn set(&mut self, host: &str, tags: BTreeSet<String>) {
+        if tags.is_empty() {
+            self.hosts.remove(host);
+        } else {
+            self.hosts.insert(host.to_string(), tags);
+        }
+    }
+
+    /// Add the file's tags to freshly parsed servers
+    pub fn apply(&self, servers: &mut [Server]) {
+        for server in servers.iter_mut() {
+            if let Some(tags) = self.hosts.get(&server.host) {
+                server.set_local_tags(tags);
+            }
+        }
+    }
+}
+
+/// Parse a tag list typed by the user: `gpu, a100 team-vision`
+pub fn parse_tags(input: &str) -> BTreeSet<String> {
+    input
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_tags() {
+        let mut server = Server::new("train1".to_string(), "10.0.0.1".to_string());
+        server.tags = parse_tags("gpu,a100");
+
+        let mut file = TagFile::default();
+        file.set("train1", parse_tags("gpu team-vision"));
+        file.apply(std::slice::from_mut(&mut server));
+        assert_eq!(server.tags, parse_tags("a100 gpu team-vision"));
+
+        // Dropping a tag from the file keeps it when the config sets it too
+        server.set_local_tags(&parse_tags(""));
+        assert_eq!(server.tags, parse_tags("a100 gpu"));
+
+        file.set("train1", BTreeSet::new());
+        assert!(file.hosts.is_empty());
+    }
+}
@@ -3,8 +3,10 @@ use std::time::Duration;
 use anyhow::Result;
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
 
-use crate::app::{App, ViewMode};
-use crate::ssh::get_install_instructions;
+use crate::app::{App, GroupBy, HostFormMode, ListRow, ViewMode};
+use crate::free_gpu::pick_free_gpus;
+use crate::server::ServerId;
+use crate::ssh::{get_install_instructions, HostEdit};
 use crate::tunnel::TunnelDisplayItem;
 
 /// Poll for terminal events with timeout
@@ -43,11 +45,31 @@ pub fn handle_key_event(app: &mut App, key: KeyEvent) -> HandleResult {
         return handle_tunnel_input(app, key);
     }
 
+    // Handle tag input mode
+    if app.is_editing_tags {
+        return handle_tag_input(app, key);
+    }
+
+    // Handle view name input mode
+    if app.is_naming_view {
+        return handle_view_input(app, key);
+    }
+
     // Handle install menu
     if app.is_showing_install_menu {
         return handle_install_menu_input(app, key);
     }
 
+    // Handle host editor form
+    if app.host_form.is_some() {
+        return handle_host_form_input(app, key);
+    }
+
+    // Handle host delete confirmation
+    if app.is_confirming_delete {
+        return handle_delete_confirmation(app, key);
+    }
+
     // Global shortcuts
     match key.code {
         KeyCode::Char('q') | KeyCode::Esc => {
@@ -85,22 +107,28 @@ pub fn handle_key_event(app: &mut App, key: KeyEvent) -> HandleResult {
 /// Result of handling an event
 pub enum HandleResult {
     Continue,
-    LaunchSsh(usize), // Index of server to connect to
+    LaunchSsh(ServerId), // Server to connect to
+    LaunchSshWithEnv(ServerId, Vec<(String, String)>), // Server, and variables for the remote shell
     RefreshAll,
-    RefreshServer(usize),
+    RefreshServer(ServerId),
     ToggleFavorite,
     SortOrderChanged,
-    RunCommand(usize, String), // Server index and command to run
+    RunCommand(ServerId, String), // Server and command to run
     CopyToClipboard,
-    SaveToFile(String),        // File path to save output
-    PipeToCommand(String),     // Local command to pipe output to
-    OpenTunnel(usize, String), // Server index, tunnel spec (host:port or just port)
-    CloseTunnel(u16),          // Local port to close
-    CloseTunnelGroup(u32),     // Group ID to close
+    SaveToFile(String),           // File path to save output
+    PipeToCommand(String),        // Local command to pipe output to
+    OpenTunnel(ServerId, String), // Server, tunnel spec (host:port or just port)
+    CloseTunnel(u16),             // Local port to close
+    CloseTunnelGroup(u32),        // Group ID to close
     CloseAllTunnels,
     InstallMoshLocally,
-    InstallMoshOnServer(usize),  // Server index
+    InstallMoshOnServer(ServerId),
     InstallMoshOnAllServers,
+    ResolveEffectiveConfig(ServerId), // Server to run `ssh -G` for
+    EditHost(HostEdit),               // Change to write to the SSH config
+    GroupsCollapsed,                  // Collapsed groups changed; save them to history
+    TagsChanged,                      // Tag file changed; save it
+    ViewsChanged,                     // Saved views changed; save them
 }
 
 fn handle_filter_input(app: &mut App, key: KeyEvent) -> HandleResult {
@@ -130,12 +158,12 @@ fn handle_command_input(app: &mut App, key: KeyEvent) -> HandleResult {
         }
         KeyCode::Enter => {
             if !app.command_text.is_empty() {
-                let display_order = app.display_order_servers();
-                if let Some(&idx) = display_order.get(app.selected_index) {
+                if let Some(server) = app.selected_server() {
+                    let (id, host) = (server.id, server.host.clone());
                     let cmd = app.command_text.clone();
                     app.stop_command_input();
-                    app.command_server = Some(app.servers[idx].host.clone());
-                    return HandleResult::RunCommand(idx, cmd);
+                    app.command_server = Some(host);
+                    return HandleResult::RunCommand(id, cmd);
                 }
             }
             app.stop_command_input();
@@ -151,6 +179,47 @@ fn handle_command_input(app: &mut App, key: KeyEvent) -> HandleResult {
     HandleResult::Continue
 }
 
+fn handle_host_form_input(app: &mut App, key: KeyEvent) -> HandleResult {
+    match key.code {
+        KeyCode::Esc => {
+            app.stop_host_form();
+        }
+        KeyCode::Enter => {
+            if let Some(edit) = app.submit_host_form() {
+                return HandleResult::EditHost(edit);
+            }
+        }
+        KeyCode::Tab | KeyCode::Down => {
+            app.host_form_focus_next(true);
+        }
+        KeyCode::BackTab | KeyCode::Up => {
+            app.host_form_focus_next(false);
+        }
+        KeyCode::Backspace => {
+            app.host_form_pop();
+        }
+        KeyCode::Char(c) => {
+            app.host_form_push(c);
+        }
+        _ => {}
+    }
+    HandleResult::Continue
+}
+
+fn handle_delete_confirmation(app: &mut App, key: KeyEvent) -> HandleResult {
+    match key.code {
+        KeyCode::Char('y') | KeyCode::Char('Y') => {
+            if let Some(edit) = app.confirm_delete() {
+                return HandleResult::EditHost(edit);
+            }
+        }
+        _ => {
+            app.is_confirming_delete = false;
+        }
+    }
+    HandleResult::Continue
+}
+
 fn handle_server_list_input(app: &mut App, key: KeyEvent) -> HandleResult {
     match key.code {
         KeyCode::Up | KeyCode::Char('k') => {
@@ -160,11 +229,24 @@ fn handle_server_list_input(app: &mut App, key: KeyEvent) -> HandleResult {
             app.select_next();
         }
         KeyCode::Enter => {
-            let display_order = app.display_order_servers();
-            if let Some(&idx) = display_order.get(app.selected_index) {
-                return HandleResult::LaunchSsh(idx);
+            // Connect to a server, or fold/unfold a group header
+            match app.list_rows().get(app.selected_index) {
+                Some(ListRow::Server { index, .. }) => {
+                    return HandleResult::LaunchSsh(app.servers[*index].id)
+                }
+                Some(ListRow::Group { .. }) => return collapse_result(app.toggle_selected_group()),
+                None => {}
             }
         }
+        KeyCode::Left => {
+            return collapse_result(app.collapse_selected_group());
+        }
+        KeyCode::Right => {
+            return collapse_result(app.expand_selected_group());
+        }
+        KeyCode::Char('Z') => {
+            return collapse_result(app.toggle_all_groups());
+        }
         KeyCode::Char('/') => {
             app.start_filtering();
         }
@@ -180,6 +262,21 @@ fn handle_server_list_input(app: &mut App, key: KeyEvent) -> HandleResult {
             app.view_mode = ViewMode::GroupList;
             app.selected_group = 0;
         }
+        KeyCode::Char('B') => {
+            app.toggle_group_by(GroupBy::Bastion);
+        }
+        KeyCode::Char('g') => {
+            app.toggle_group_by(GroupBy::Tag);
+        }
+        KeyCode::Char('#') => {
+            app.start_tag_input();
+        }
+        KeyCode::Char('V') => {
+            app.cycle_view();
+        }
+        KeyCode::Char('W') => {
+            app.start_view_input();
+        }
         KeyCode::Char('s') => {
             app.cycle_sort_order();
             return HandleResult::SortOrderChanged;
@@ -211,32 +308,54 @@ fn handle_server_list_input(app: &mut App, key: KeyEvent) -> HandleResult {
             app.is_showing_install_menu = true;
             app.install_menu_selection = 0;
         }
+        KeyCode::Char('A') => {
+            app.start_host_form(HostFormMode::Add);
+        }
+        KeyCode::Char('E') => {
+            app.start_host_form(HostFormMode::Edit);
+        }
+        KeyCode::Char('C') => {
+            app.start_host_form(HostFormMode::Duplicate);
+        }
+        KeyCode::Char('X') => {
+            app.start_delete_confirmation();
+        }
+        KeyCode::Char('F') => {
+            // Connect to the freest GPU in the selected group or the filtered list
+            let scope = app.free_gpu_scope();
+            match pick_free_gpus(&app.servers, &scope, 1) {
+                Some(pick) => {
+                    app.select_server(pick.server);
+                    return HandleResult::LaunchSshWithEnv(app.servers[pick.server].id, vec![pick.env()]);
+                }
+                None => app.set_status(format!("No free GPU on {} servers", scope.len())),
+            }
+        }
         KeyCode::Char(ch) if ch.is_ascii_lowercase() && ch != 's' && ch != 'j' && ch != 'k' && ch != 'n' && ch != 'q' && ch != 'r' && ch != 'd' && ch != 'g' && ch != 'f' && ch != 'c' && ch != 't' && ch != 'm' => {
             // Shortcut keys a-z (excluding reserved keys) to jump to server
             let idx = (ch as u8 - b'a') as usize;
             let display_order = app.display_order_servers();
-            if idx < display_order.len() {
-                app.selected_index = idx;
+            if let Some(&server_idx) = display_order.get(idx) {
+                app.select_server(server_idx);
                 // Immediately connect
-                return HandleResult::LaunchSsh(display_order[idx]);
+                return HandleResult::LaunchSsh(app.servers[server_idx].id);
             }
         }
         KeyCode::Char(c) if c.is_ascii_digit() => {
             // Shortcut keys 0-9 for servers 26-35
             let idx = 26 + (c as u8 - b'0') as usize;
             let display_order = app.display_order_servers();
-            if idx < display_order.len() {
-                app.selected_index = idx;
-                return HandleResult::LaunchSsh(display_order[idx]);
+            if let Some(&server_idx) = display_order.get(idx) {
+                app.select_server(server_idx);
+                return HandleResult::LaunchSsh(app.servers[server_idx].id);
             }
         }
         KeyCode::Char('r') => {
             return HandleResult::RefreshAll;
         }
         KeyCode::Char('R') => {
-            let display_order = app.display_order_servers();
-            if let Some(&idx) = display_order.get(app.selected_index) {
-                return HandleResult::RefreshServer(idx);
+            if let Some(id) = app.selected_server_id() {
+                return HandleResult::RefreshServer(id);
             }
         }
         KeyCode::Char('d') | KeyCode::Char(' ') => {
@@ -249,7 +368,7 @@ fn handle_server_list_input(app: &mut App, key: KeyEvent) -> HandleResult {
             app.selected_index = 0;
         }
         KeyCode::End => {
-            let count = app.display_order_servers().len();
+            let count = app.list_rows().len();
             if count > 0 {
                 app.selected_index = count - 1;
             }
@@ -258,7 +377,7 @@ fn handle_server_list_input(app: &mut App, key: KeyEvent) -> HandleResult {
             app.selected_index = app.selected_index.saturating_sub(10);
         }
         KeyCode::PageDown => {
-            let count = app.display_order_servers().len();
+            let count = app.list_rows().len();
             app.selected_index = (app.selected_index + 10).min(count.saturating_sub(1));
         }
         _ => {}
@@ -275,13 +394,43 @@ fn handle_group_list_input(app: &mut App, key: KeyEvent) -> HandleResult {
             app.select_next();
         }
         KeyCode::Enter | KeyCode::Char('l') => {
-            // Switch to server view filtered by this group
-            if let Some(group) = app.groups.get(app.selected_group) {
-                app.filter_text = group.name.clone();
+            if let Some(ListRow::Group { path, .. }) = app.group_rows().get(app.selected_group) {
+                let path = path.clone();
                 app.view_mode = ViewMode::ServerList;
                 app.selected_index = 0;
+                match app.group_by {
+                    // Switch to server view filtered by this group
+                    GroupBy::Name => app.filter_text = path,
+                    // The list is grouped the same way: jump to the group's header
+                    GroupBy::Bastion | GroupBy::Tag => {
+                        app.filter_text.clear();
+                        if let Some(pos) = app.list_rows().iter().position(
+                            |row| matches!(row, ListRow::Group { path: p, .. } if *p == path),
+                        ) {
+                            app.selected_index = pos;
+                        }
+                    }
+                }
             }
         }
+        KeyCode::Char(' ') => {
+            return collapse_result(app.toggle_selected_group());
+        }
+        KeyCode::Left => {
+            return collapse_result(app.collapse_selected_group());
+        }
+        KeyCode::Right => {
+            return collapse_result(app.expand_selected_group());
+        }
+        KeyCode::Char('Z') => {
+            return collapse_result(app.toggle_all_groups());
+        }
+        KeyCode::Char('B') => {
+            app.toggle_group_by(GroupBy::Bastion);
+        }
+        KeyCode::Char('g') => {
+            app.toggle_group_by(GroupBy::Tag);
+        }
         KeyCode::Esc | KeyCode::Char('h') => {
             app.status_message = None;
             app.view_mode = ViewMode::ServerList;
@@ -301,21 +450,33 @@ fn handle_details_input(app: &mut App, key: KeyEvent) -> HandleResult {
             app.view_mode = ViewMode::ServerList;
         }
         KeyCode::Enter => {
-            let display_order = app.display_order_servers();
-            if let Some(&idx) = display_order.get(app.selected_index) {
-                return HandleResult::LaunchSsh(idx);
+            if let Some(id) = app.selected_server_id() {
+                return HandleResult::LaunchSsh(id);
             }
         }
         KeyCode::Up | KeyCode::Char('k') => {
             app.select_previous();
+            return resolve_if_panel_open(app);
         }
         KeyCode::Down | KeyCode::Char('j') => {
             app.select_next();
+            return resolve_if_panel_open(app);
+        }
+        KeyCode::Char('e') => {
+            // Toggle the effective config panel
+            app.show_effective_config = !app.show_effective_config;
+            app.effective_scroll = 0;
+            return resolve_if_panel_open(app);
+        }
+        KeyCode::PageDown => {
+            app.effective_scroll = app.effective_scroll.saturating_add(10);
+        }
+        KeyCode::PageUp => {
+            app.effective_scroll = app.effective_scroll.saturating_sub(10);
         }
         KeyCode::Char('r') | KeyCode::Char('R') => {
-            let display_order = app.display_order_servers();
-            if let Some(&idx) = display_order.get(app.selected_index) {
-                return HandleResult::RefreshServer(idx);
+            if let Some(id) = app.selected_server_id() {
+                return HandleResult::RefreshServer(id);
             }
         }
         _ => {}
@@ -323,6 +484,29 @@ fn handle_details_input(app: &mut App, key: KeyEvent) -> HandleResult {
     HandleResult::Continue
 }
 
+/// Ask the main loop to save collapsed groups if they changed
+fn collapse_result(changed: bool) -> HandleResult {
+    if changed {
+        HandleResult::GroupsCollapsed
+    } else {
+        HandleResult::Continue
+    }
+}
+
+/// Request `ssh -G` for the selected server when the effective config panel is showing
+fn resolve_if_panel_open(app: &mut App) -> HandleResult {
+    if !app.show_effective_config {
+        return HandleResult::Continue;
+    }
+    match app.selected_server_id() {
+        Some(id) => {
+            app.effective_scroll = 0;
+            HandleResult::ResolveEffectiveConfig(id)
+        }
+        None => HandleResult::Continue,
+    }
+}
+
 fn handle_help_input(app: &mut App, key: KeyEvent) -> HandleResult {
     match key.code {
         KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('?') => {
@@ -417,11 +601,10 @@ fn handle_tunnel_input(app: &mut App, key: KeyEvent) -> HandleResult {
         }
         KeyCode::Enter => {
             if !app.tunnel_input.is_empty() {
-                let display_order = app.display_order_servers();
-                if let Some(&idx) = display_order.get(app.selected_index) {
+                if let Some(id) = app.selected_server_id() {
                     let spec = app.tunnel_input.clone();
                     app.stop_tunnel_input();
-                    return HandleResult::OpenTunnel(idx, spec);
+                    return HandleResult::OpenTunnel(id, spec);
                 }
             }
             app.stop_tunnel_input();
@@ -437,6 +620,52 @@ fn handle_tunnel_input(app: &mut App, key: KeyEvent) -> HandleResult {
     HandleResult::Continue
 }
 
+fn handle_tag_input(app: &mut App, key: KeyEvent) -> HandleResult {
+    match key.code {
+        KeyCode::Esc => {
+            app.stop_tag_input();
+        }
+        KeyCode::Enter => {
+            return if app.submit_tags() {
+                HandleResult::TagsChanged
+            } else {
+                HandleResult::Continue
+            };
+        }
+        KeyCode::Backspace => {
+            app.tag_input_pop();
+        }
+        KeyCode::Char(c) => {
+            app.tag_input_push(c);
+        }
+        _ => {}
+    }
+    HandleResult::Continue
+}
+
+fn handle_view_input(app: &mut App, key: KeyEvent) -> HandleResult {
+    match key.code {
+        KeyCode::Esc => {
+            app.stop_view_input();
+        }
+        KeyCode::Enter => {
+            return if app.submit_view() {
+                HandleResult::ViewsChanged
+            } else {
+                HandleResult::Continue
+            };
+        }
+        KeyCode::Backspace => {
+            app.view_input_pop();
+        }
+        KeyCode::Char(c) => {
+            app.view_input_push(c);
+        }
+        _ => {}
+    }
+    HandleResult::Continue
+}
+
 fn handle_tunnels_input(app: &mut App, key: KeyEvent) -> HandleResult {
     let display_items = app.tunnel_manager.get_display_items();
     let display_count = display_items.len();
@@ -445,15 +674,13 @@ fn handle_tunnels_input(app: &mut App, key: KeyEvent) -> HandleResult {
         KeyCode::Esc | KeyCode::Char('q') => {
             app.view_mode = ViewMode::ServerList;
         }
-        KeyCode::Up | KeyCode::Char('k') => {
-            if app.selected_tunnel > 0 {
-                app.selected_tunnel -= 1;
-            }
+        KeyCode::Up | KeyCode::Char('k') if app.selected_tunnel > 0 => {
+            app.selected_tunnel -= 1;
         }
-        KeyCode::Down | KeyCode::Char('j') => {
-            if display_count > 0 && app.selected_tunnel < display_count - 1 {
-                app.selected_tunnel += 1;
-            }
+        KeyCode::Down | KeyCode::Char('j')
+            if display_count > 0 && app.selected_tunnel < display_count - 1 =>
+        {
+            app.selected_tunnel += 1;
         }
         KeyCode::Char('d') | KeyCode::Delete => {
             // Close selected tunnel or group
@@ -494,24 +721,19 @@ fn handle_install_menu_input(app: &mut App, key: KeyEvent) -> HandleResult {
         KeyCode::Esc | KeyCode::Char('q') => {
             app.is_showing_install_menu = false;
         }
-        KeyCode::Up | KeyCode::Char('k') => {
-            if app.install_menu_selection > 0 {
-                app.install_menu_selection -= 1;
-            }
+        KeyCode::Up | KeyCode::Char('k') if app.install_menu_selection > 0 => {
+            app.install_menu_selection -= 1;
         }
-        KeyCode::Down | KeyCode::Char('j') => {
-            if app.install_menu_selection < MENU_ITEMS - 1 {
-                app.install_menu_selection += 1;
-            }
+        KeyCode::Down | KeyCode::Char('j') if app.install_menu_selection < MENU_ITEMS - 1 => {
+            app.install_menu_selection += 1;
         }
         KeyCode::Enter => {
             app.is_showing_install_menu = false;
             match app.install_menu_selection {
                 0 => return HandleResult::InstallMoshLocally,
                 1 => {
-                    let display_order = app.display_order_servers();
-                    if let Some(&idx) = display_order.get(app.selected_index) {
-                        return HandleResult::InstallMoshOnServer(idx);
+                    if let Some(id) = app.selected_server_id() {
+                        return HandleResult::InstallMoshOnServer(id);
                     }
                 }
                 2 => return HandleResult::InstallMoshOnAllServers,
@@ -530,9 +752,8 @@ fn handle_install_menu_input(app: &mut App, key: KeyEvent) -> HandleResult {
         }
         KeyCode::Char('2') => {
             app.is_showing_install_menu = false;
-            let display_order = app.display_order_servers();
-            if let Some(&idx) = display_order.get(app.selected_index) {
-                return HandleResult::InstallMoshOnServer(idx);
+            if let Some(id) = app.selected_server_id() {
+                return HandleResult::InstallMoshOnServer(id);
             }
         }
         KeyCode::Char('3') => {
@@ -1,18 +1,28 @@
+use std::collections::HashMap;
+
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Clear, Gauge, List, ListItem, Paragraph, Wrap},
+    widgets::{
+        Block, Borders, Cell, Clear, Gauge, List, ListItem, ListState, Paragraph, Row, Table, Wrap,
+    },
     Frame,
 };
 
-use crate::app::{App, SortOrder, ViewMode};
-use crate::health::format_bytes;
-use crate::server::HealthStatus;
+use crate::app::{App, GroupBy, HostForm, HostFormMode, ListRow, SortOrder, ViewMode, DIRECT_GROUP};
+use crate::checks::CheckLevel;
+use crate::health::{format_age, format_bytes};
+use crate::server::{tilde_path, GpuInfo, GroupSummary, HealthStatus, Issue, Server};
+use crate::ssh::HostEntry;
 use crate::tunnel::TunnelDisplayItem;
+use crate::views::Column;
 
 const MAX_WIDTH: u16 = 120;
 
+/// Lines of an error shown in the details view
+const MAX_ERROR_LINES: usize = 4;
+
 /// Constrain content to max width, aligned left
 fn constrained_rect(area: Rect, max_width: u16) -> Rect {
     Rect {
@@ -74,10 +84,28 @@ pub fn draw(frame: &mut Frame, app: &App) {
         draw_tunnel_input(frame, app);
     }
 
+    // Draw tag input overlay if active
+    if app.is_editing_tags {
+        draw_tag_input(frame, app);
+    }
+
+    // Draw view name input overlay if active
+    if app.is_naming_view {
+        draw_view_input(frame, app);
+    }
+
     // Draw install menu overlay if active
     if app.is_showing_install_menu {
         draw_install_menu(frame, app);
     }
+
+    // Draw host editor overlays if active
+    if let Some(ref form) = app.host_form {
+        draw_host_form(frame, form);
+    }
+    if app.is_confirming_delete {
+        draw_delete_confirmation(frame, app);
+    }
 }
 
 fn draw_header(frame: &mut Frame, app: &App, area: Rect) {
@@ -91,8 +119,14 @@ fn draw_header(frame: &mut Frame, app: &App, area: Rect) {
                 format!(" ggoto - {} / {} servers ", count, total)
             }
         }
-        ViewMode::GroupList => format!(" ggoto - {} groups ", app.groups.len()),
-        ViewMode::ServerDetails => " Server Details ".to_string(),
+        ViewMode::GroupList => {
+            let count: usize = app.groups.iter().map(|g| g.group_count()).sum();
+            format!(" ggoto - {} groups ", count)
+        }
+        ViewMode::ServerDetails => match app.selected_server().and_then(|s| s.data_age()) {
+            Some(age) => format!(" Server Details (checked {} ago) ", format_age(age)),
+            None => " Server Details ".to_string(),
+        },
         ViewMode::CommandOutput => " Command Output ".to_string(),
         ViewMode::Tunnels => format!(" Tunnels ({}) ", app.tunnel_manager.count()),
         ViewMode::Help => " Help ".to_string(),
@@ -108,7 +142,13 @@ fn draw_header(frame: &mut Frame, app: &App, area: Rect) {
         SortOrder::Group => "[Group]",
     };
 
-    let header_text = format!("{} sorted by {}", title, sort_indicator);
+    let mut header_text = format!("{} sorted by {}", title, sort_indicator);
+    if let Some(view) = app.active_view.and_then(|i| app.view_file.views.get(i)) {
+        header_text.push_str(&format!(" view: {} ", view.name));
+    }
+    if let Some(interval) = app.refresh_interval {
+        header_text.push_str(&format!(" refresh: {} ", format_age(interval)));
+    }
 
     let block = Block::default()
         .borders(Borders::ALL)
@@ -125,6 +165,7 @@ fn short_gpu_name(name: &str) -> String {
         s == "RTX" || s == "GTX" || s == "GT" || s == "T4" || s == "A100"
         || s == "A10" || s == "A40" || s == "A30" || s == "V100" || s == "H100"
         || s == "H200" || s == "L40" || s == "L4"
+        || (s.starts_with("MI") && s[2..].starts_with(|c: char| c.is_ascii_digit()))
     }) {
         if pos + 1 < parts.len() && parts[pos + 1].chars().next().is_some_and(|c| c.is_ascii_digit()) {
             format!("{}{}", parts[pos], parts[pos + 1])
@@ -136,21 +177,99 @@ fn short_gpu_name(name: &str) -> String {
     }
 }
 
-fn draw_server_list(frame: &mut Frame, app: &App, area: Rect) {
-    use std::collections::BTreeMap;
+/// Color of a custom check's value
+fn check_color(level: CheckLevel) -> Color {
+    match level {
+        CheckLevel::Ok => Color::Green,
+        CheckLevel::Warn => Color::Yellow,
+        CheckLevel::Critical => Color::Red,
+    }
+}
+
+/// Truncate a string to at most `max` characters, marking the cut with an ellipsis
+fn truncate(s: &str, max: usize) -> String {
+    if s.chars().count() <= max {
+        s.to_string()
+    } else {
+        let mut out: String = s.chars().take(max.saturating_sub(1)).collect();
+        out.push('…');
+        out
+    }
+}
 
-    let filtered = app.filtered_servers();
-    let display_order = app.display_order_servers();
+/// Tree header for a group: fold marker, name, server count and a health/load rollup
+fn group_header_line(app: &App, row: &ListRow) -> Line<'static> {
+    let ListRow::Group { name, depth, servers, collapsed, .. } = row else {
+        return Line::default();
+    };
+    let summary = GroupSummary::new(&app.servers, servers);
+    let marker = if *collapsed { "▸" } else { "▾" };
+    let label = match app.group_by {
+        GroupBy::Bastion if name != DIRECT_GROUP => format!("via {}", name),
+        _ => name.clone(),
+    };
+    let plural = if summary.servers == 1 { "" } else { "s" };
 
-    // Get the actual server index that is currently selected (using display order)
-    let selected_server_idx = display_order.get(app.selected_index).copied();
+    let mut spans = vec![
+        Span::styled(
+            format!("{}{} {} ({} server{})", "  ".repeat(*depth), marker, label, summary.servers, plural),
+            Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+        ),
+    ];
+    if summary.up > 0 {
+        spans.push(Span::styled(format!("  {} up", summary.up), Style::default().fg(Color::Green)));
+    }
+    if summary.down > 0 {
+        spans.push(Span::styled(format!("  {} down", summary.down), Style::default().fg(Color::Red)));
+    }
+    if let Some(cpu) = summary.avg_cpu {
+        spans.push(Span::styled(format!("  CPU {:.0}%", cpu), Style::default().fg(Color::DarkGray)));
+    }
+    if summary.gpus > 0 {
+        spans.push(Span::styled(format!("  {} GPUs", summary.gpus), Style::default().fg(Color::DarkGray)));
+    }
+    Line::from(spans)
+}
 
-    // Group servers by their group name
-    let mut grouped: BTreeMap<String, Vec<usize>> = BTreeMap::new();
-    for &idx in &filtered {
-        let group = app.servers[idx].group.clone().unwrap_or_default();
-        grouped.entry(group).or_default().push(idx);
+/// Host name padded to `width`, with the characters at `positions` highlighted
+fn highlighted_host(host: &str, positions: &[usize], width: usize) -> Vec<Span<'static>> {
+    let normal = Style::default().fg(Color::White);
+    let matched = Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+
+    let mut spans: Vec<Span> = Vec::new();
+    let mut run = String::new();
+    let mut run_matched = false;
+    for (i, c) in host.chars().enumerate() {
+        let is_match = positions.contains(&i);
+        if is_match != run_matched && !run.is_empty() {
+            let style = if run_matched { matched } else { normal };
+            spans.push(Span::styled(std::mem::take(&mut run), style));
+        }
+        run_matched = is_match;
+        run.push(c);
+    }
+    if !run.is_empty() {
+        spans.push(Span::styled(run, if run_matched { matched } else { normal }));
+    }
+    let len = host.chars().count();
+    if len < width {
+        spans.push(Span::raw(" ".repeat(width - len)));
     }
+    spans
+}
+
+fn draw_server_list(frame: &mut Frame, app: &App, area: Rect) {
+    let rows = app.list_rows();
+
+    // Characters of each host matched by the search
+    let highlights: HashMap<usize, Vec<usize>> = if app.filter_text.is_empty() {
+        HashMap::new()
+    } else {
+        app.filter_matches()
+            .into_iter()
+            .map(|m| (m.index, m.host_positions))
+            .collect()
+    };
 
     // Build list items with column header, group headers and server rows
     let mut items: Vec<ListItem> = Vec::new();
@@ -158,150 +277,192 @@ fn draw_server_list(frame: &mut Frame, app: &App, area: Rect) {
 
     // Column header - use same widths as data rows
     let hdr = Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD);
-    let header_line = Line::from(vec![
+    let mut header_spans = vec![
         Span::styled(format!("{:>3}", "#"), hdr),
         Span::raw("  "),  // Space for star
         Span::styled(format!("{:<13}", "Host"), hdr),
-        Span::styled(format!("{:>8}", "Ping"), hdr),
-        Span::raw(" "),   // Space for mosh indicator
-        Span::styled(format!("{:<14}", "CPU"), hdr),
-        Span::styled(format!("{:<13}", "RAM"), hdr),
-        Span::styled(format!("{:<18}", "GPU"), hdr),
-        Span::styled(format!("{:>5}", "Users"), hdr),
-        Span::raw("  "),
-        Span::styled(format!("{:<8}", "Last"), hdr),
-    ]);
-    items.push(ListItem::new(header_line));
+    ];
+    for column in &app.columns {
+        header_spans.extend(match column {
+            Column::Via => vec![Span::styled(format!("{:<12}", "Via"), hdr)],
+            Column::Ping => vec![
+                Span::styled(format!("{:>8}", "Ping"), hdr),
+                Span::raw(" "),   // Space for mosh indicator
+            ],
+            Column::Age => vec![Span::styled(format!("{:>4} ", "Age"), hdr)],
+            Column::Cpu => vec![Span::styled(format!("{:<14}", "CPU"), hdr)],
+            Column::Ram => vec![Span::styled(format!("{:<13}", "RAM"), hdr)],
+            Column::Gpu => vec![Span::styled(format!("{:<18}", "GPU"), hdr)],
+            Column::Users => vec![Span::styled(format!("{:>5}", "Users"), hdr), Span::raw("  ")],
+            Column::Last => vec![Span::styled(format!("{:<8}", "Last"), hdr)],
+            Column::Tags => vec![Span::styled(format!("{:<16}", "Tags"), hdr)],
+            Column::Description => vec![],
+            Column::Check(name) => vec![Span::styled(format!("{:<12}", truncate(name, 11)), hdr)],
+        });
+    }
+    items.push(ListItem::new(Line::from(header_spans)));
+
+    for (row_index, row) in rows.iter().enumerate() {
+        let is_selected = row_index == app.selected_index;
+        let selected_style = Style::default().bg(Color::DarkGray).add_modifier(Modifier::BOLD);
+
+        let idx = match row {
+            ListRow::Server { index, .. } => *index,
+            ListRow::Group { .. } => {
+                let style = if is_selected { selected_style } else { Style::default() };
+                items.push(ListItem::new(group_header_line(app, row)).style(style));
+                continue;
+            }
+        };
 
-    for (group_name, server_indices) in &grouped {
-        // Group header
-        let header_text = format!("▸ {} ({} servers)", group_name, server_indices.len());
-        items.push(ListItem::new(Line::from(vec![
-            Span::styled(header_text, Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
-        ])));
-
-        // Servers in this group
-        for &idx in server_indices {
-            let server = &app.servers[idx];
-            let is_selected = Some(idx) == selected_server_idx;
-
-            // Color code latency: green <100ms, yellow 100-500ms, red >500ms
-            let (latency_str, latency_color) = match server.latency_ms() {
-                Some(ms) => {
-                    let color = if ms <= 100 {
-                        Color::Green
-                    } else if ms <= 500 {
-                        Color::Yellow
-                    } else {
-                        Color::Red
-                    };
-                    (format!("{}ms", ms), color)
-                }
-                None => {
-                    let color = match server.status {
-                        HealthStatus::Unknown => Color::DarkGray,
-                        _ => Color::Red, // Unreachable
-                    };
-                    ("-".to_string(), color)
-                }
-            };
+        let server = &app.servers[idx];
 
-            let (cpu_str, ram_str, gpu_str, gpu_color) = if let Some(ref m) = server.metrics {
-                let cpu = if m.cpu_cores > 0 {
-                    format!("{:>3}% ({:>2}c)", m.cpu_usage as u32, m.cpu_cores)
+        // Color code latency: green <100ms, yellow 100-500ms, red >500ms
+        let (latency_str, latency_color) = match server.latency_ms() {
+            Some(ms) => {
+                let color = if ms <= 100 {
+                    Color::Green
+                } else if ms <= 500 {
+                    Color::Yellow
                 } else {
-                    format!("{:>3}%", m.cpu_usage as u32)
+                    Color::Red
                 };
+                (format!("{}ms", ms), color)
+            }
+            None => match server.status {
+                HealthStatus::Unknown => ("-".to_string(), Color::DarkGray),
+                HealthStatus::BlockedByBastion(_) => ("blocked".to_string(), Color::Yellow),
+                HealthStatus::Unreachable(ref error) => (error.kind.label().to_string(), Color::Red),
+                _ => ("-".to_string(), Color::Red),
+            },
+        };
 
-                let ram = format!("{:>6}/{:<6}", format_bytes(m.ram_used), format_bytes(m.ram_total));
+        let via_str = server.via_label().unwrap_or_default();
 
-                let (gpu, gpu_util) = if m.gpus.is_empty() {
-                    ("-".to_string(), 0.0)
-                } else {
-                    let count = m.gpus.len();
-                    let avg_util: f32 = m.gpus.iter().map(|g| g.utilization).sum::<f32>() / count as f32;
-                    let short = short_gpu_name(&m.gpus[0].name);
-                    (format!("{}x{} {:>3}%", count, short, avg_util as u32), avg_util)
-                };
+        // Age of the last check; highlighted once a periodic refresh has been missed
+        let age_str = server.data_age().map(format_age).unwrap_or_else(|| "-".to_string());
+        let age_color = match app.refresh_interval {
+            Some(interval) if server.last_check.is_some() && server.metrics_stale(interval * 2) => Color::Yellow,
+            _ => Color::DarkGray,
+        };
 
-                let color = if gpu_util > 80.0 {
-                    Color::Red
-                } else if gpu_util > 50.0 {
-                    Color::Yellow
-                } else if gpu_util > 0.0 {
-                    Color::Green
-                } else {
-                    Color::DarkGray
-                };
+        let (cpu_str, ram_str, gpu_str, gpu_color) = if let Some(ref m) = server.metrics {
+            let cpu = if m.cpu_cores > 0 {
+                format!("{:>3}% ({:>2}c)", m.cpu_usage as u32, m.cpu_cores)
+            } else {
+                format!("{:>3}%", m.cpu_usage as u32)
+            };
 
-                (cpu, ram, gpu, color)
+            let ram = format!("{:>6}/{:<6}", format_bytes(m.ram_used), format_bytes(m.ram_total));
+
+            let (gpu, gpu_util) = if m.gpus.is_empty() {
+                ("-".to_string(), 0.0)
             } else {
-                ("-".to_string(), "-".to_string(), "-".to_string(), Color::DarkGray)
+                let count = m.gpus.len();
+                let avg_util: f32 = m.gpus.iter().map(|g| g.utilization).sum::<f32>() / count as f32;
+                let short = short_gpu_name(&m.gpus[0].name);
+                (format!("{}x{} {:>3}%", count, short, avg_util as u32), avg_util)
             };
 
-            let users_str = server.metrics.as_ref()
-                .map(|m| format!("{}", m.logged_in_users.len()))
-                .unwrap_or_else(|| "-".to_string());
+            let color = if gpu_util > 80.0 {
+                Color::Red
+            } else if gpu_util > 50.0 {
+                Color::Yellow
+            } else if gpu_util > 0.0 {
+                Color::Green
+            } else {
+                Color::DarkGray
+            };
 
-            // Mosh indicator: M if server has mosh-server
-            let mosh_indicator = server.metrics.as_ref()
-                .map(|m| if m.mosh_server_path.is_some() { "M" } else { " " })
-                .unwrap_or(" ");
+            (cpu, ram, gpu, color)
+        } else {
+            ("-".to_string(), "-".to_string(), "-".to_string(), Color::DarkGray)
+        };
 
-            // Get last connection time
-            let last_str = app.history.format_last_connected(&server.host);
+        let users_str = server.metrics.as_ref()
+            .map(|m| format!("{}", m.logged_in_users.len()))
+            .unwrap_or_else(|| "-".to_string());
 
-            // Check if favorite
-            let is_favorite = app.history.is_favorite(&server.host);
-            let fav_indicator = if is_favorite { "★" } else { " " };
+        // Mosh indicator: M if server has mosh-server
+        let mosh_indicator = server.metrics.as_ref()
+            .map(|m| if m.mosh_server_path.is_some() { "M" } else { " " })
+            .unwrap_or(" ");
 
-            // Generate shortcut key: a-z for first 26, then 0-9
-            let shortcut = if flat_index < 26 {
-                ((b'a' + flat_index as u8) as char).to_string()
-            } else if flat_index < 36 {
-                ((b'0' + (flat_index - 26) as u8) as char).to_string()
-            } else {
-                " ".to_string()
-            };
+        // Get last connection time
+        let last_str = app.history.format_last_connected(&server.host);
 
-            // Build the line with styled spans - match header widths
-            let line = Line::from(vec![
-                Span::styled(format!("{:>3}", shortcut), Style::default().fg(Color::DarkGray)),
-                Span::styled(format!(" {}", fav_indicator), Style::default().fg(Color::Yellow)),
-                Span::styled(format!("{:<13}", server.host), Style::default().fg(Color::White)),
-                Span::styled(format!("{:>8}", latency_str), Style::default().fg(latency_color)),
-                Span::styled(mosh_indicator, Style::default().fg(Color::Magenta)),
-                Span::raw(format!("{:<14}", cpu_str)),
-                Span::raw(format!("{:<13}", ram_str)),
-                Span::styled(format!("{:<18}", gpu_str), Style::default().fg(gpu_color)),
-                Span::styled(format!("{:>5}", users_str), Style::default().fg(Color::DarkGray)),
-                Span::raw("  "),
-                Span::styled(format!("{:<8}", last_str), Style::default().fg(Color::Magenta)),
-            ]);
+        let tags_str = server.tags.iter().cloned().collect::<Vec<_>>().join(",");
 
-            let style = if is_selected {
-                Style::default().bg(Color::DarkGray).add_modifier(Modifier::BOLD)
-            } else {
-                Style::default()
-            };
+        // Check if favorite
+        let is_favorite = app.history.is_favorite(&server.host);
+        let fav_indicator = if is_favorite { "★" } else { " " };
+
+        // Generate shortcut key: a-z for first 26, then 0-9
+        let shortcut = if flat_index < 26 {
+            ((b'a' + flat_index as u8) as char).to_string()
+        } else if flat_index < 36 {
+            ((b'0' + (flat_index - 26) as u8) as char).to_string()
+        } else {
+            " ".to_string()
+        };
 
-            items.push(ListItem::new(line).style(style));
-            flat_index += 1;
+        // Build the line with styled spans - match header widths
+        let mut spans = vec![
+            Span::styled(format!("{:>3}", shortcut), Style::default().fg(Color::DarkGray)),
+            Span::styled(format!(" {}", fav_indicator), Style::default().fg(Color::Yellow)),
+        ];
+        let positions = highlights.get(&idx).map(|p| p.as_slice()).unwrap_or_default();
+        spans.extend(highlighted_host(&server.host, positions, 13));
+        for column in &app.columns {
+            spans.extend(match column {
+                Column::Via => vec![Span::styled(format!("{:<12}", truncate(&via_str, 11)), Style::default().fg(Color::Blue))],
+                Column::Ping => vec![
+                    Span::styled(format!("{:>8}", latency_str), Style::default().fg(latency_color)),
+                    Span::styled(mosh_indicator, Style::default().fg(Color::Magenta)),
+                ],
+                Column::Age => vec![Span::styled(format!("{:>4} ", age_str), Style::default().fg(age_color))],
+                Column::Cpu => vec![Span::raw(format!("{:<14}", cpu_str))],
+                Column::Ram => vec![Span::raw(format!("{:<13}", ram_str))],
+                Column::Gpu => vec![Span::styled(format!("{:<18}", gpu_str), Style::default().fg(gpu_color))],
+                Column::Users => vec![
+                    Span::styled(format!("{:>5}", users_str), Style::default().fg(Color::DarkGray)),
+                    Span::raw("  "),
+                ],
+                Column::Last => vec![Span::styled(format!("{:<8}", last_str), Style::default().fg(Color::Magenta))],
+                Column::Tags => vec![Span::styled(format!("{:<16}", truncate(&tags_str, 15)), Style::default().fg(Color::Green))],
+                // Clipped at the edge of the list when there's no room
+                Column::Description => vec![Span::styled(server.description.as_deref().unwrap_or(""), Style::default().fg(Color::DarkGray))],
+                Column::Check(name) => {
+                    let (value, color) = match server.check_results.iter().find(|c| &c.name == name) {
+                        Some(result) => (result.value.as_str(), check_color(result.level)),
+                        None => ("-", Color::DarkGray),
+                    };
+                    vec![Span::styled(format!("{:<12}", truncate(value, 11)), Style::default().fg(color))]
+                }
+            });
         }
+        let line = Line::from(spans);
+
+        let style = if is_selected { selected_style } else { Style::default() };
+
+        items.push(ListItem::new(line).style(style));
+        flat_index += 1;
     }
 
     let list = List::new(items)
         .block(Block::default().borders(Borders::ALL).title(" Servers "));
 
-    frame.render_widget(list, area);
+    // Scroll to keep the selection in view; the first item is the column header
+    let mut state = ListState::default().with_selected(Some(app.selected_index + 1));
+    frame.render_stateful_widget(list, area, &mut state);
 }
 
 fn draw_group_list(frame: &mut Frame, app: &App, area: Rect) {
     let items: Vec<ListItem> = app
-        .groups
+        .group_rows()
         .iter()
         .enumerate()
-        .map(|(i, group)| {
+        .map(|(i, row)| {
             let style = if i == app.selected_group {
                 Style::default()
                     .bg(Color::DarkGray)
@@ -310,14 +471,19 @@ fn draw_group_list(frame: &mut Frame, app: &App, area: Rect) {
                 Style::default()
             };
 
-            let content = format!("{} ({} servers)", group.name, group.servers.len());
-            ListItem::new(content).style(style)
+            ListItem::new(group_header_line(app, row)).style(style)
         })
         .collect();
 
-    let list = List::new(items).block(Block::default().borders(Borders::ALL).title(" Groups "));
+    let title = match app.group_by {
+        GroupBy::Name => " Groups ",
+        GroupBy::Bastion => " Groups by Bastion ",
+        GroupBy::Tag => " Groups by Tag ",
+    };
+    let list = List::new(items).block(Block::default().borders(Borders::ALL).title(title));
 
-    frame.render_widget(list, area);
+    let mut state = ListState::default().with_selected(Some(app.selected_group));
+    frame.render_stateful_widget(list, area, &mut state);
 }
 
 fn draw_server_details(frame: &mut Frame, app: &App, area: Rect) {
@@ -332,15 +498,6 @@ fn draw_server_details(frame: &mut Frame, app: &App, area: Rect) {
         }
     };
 
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Length(8), // Basic info
-            Constraint::Length(6), // System metrics
-            Constraint::Min(4),    // GPU / Users
-        ])
-        .split(area);
-
     // Basic info - color code latency
     let (latency_str, latency_color) = match server.latency_ms() {
         Some(ms) => {
@@ -364,8 +521,8 @@ fn draw_server_details(frame: &mut Frame, app: &App, area: Rect) {
 
     let status_color = match server.status {
         HealthStatus::Healthy => Color::Green,
-        HealthStatus::Degraded => Color::Yellow,
-        HealthStatus::Unreachable => Color::Red,
+        HealthStatus::Degraded(_) | HealthStatus::BlockedByBastion(_) => Color::Yellow,
+        HealthStatus::Unreachable(_) => Color::Red,
         HealthStatus::Unknown => Color::DarkGray,
     };
 
@@ -391,10 +548,7 @@ fn draw_server_details(frame: &mut Frame, app: &App, area: Rect) {
         ]),
         Line::from(vec![
             Span::raw("Status:   "),
-            Span::styled(
-                format!("{:?}", server.status),
-                Style::default().fg(status_color),
-            ),
+            Span::styled(server.status.label(), Style::default().fg(status_color)),
         ]),
         Line::from(vec![
             Span::raw("Latency:  "),
@@ -420,11 +574,107 @@ fn draw_server_details(frame: &mut Frame, app: &App, area: Rect) {
         all_lines.push(line);
     }
 
+    // Full text of what went wrong; ssh errors can run over a few lines
+    let error = match server.status {
+        HealthStatus::Unreachable(ref error) => Some(("Error:    ", error.message.as_str())),
+        HealthStatus::Degraded(ref issues) => issues.iter().find_map(|issue| match issue {
+            Issue::NoMetrics(error) => Some(("Metrics:  ", error.as_str())),
+            _ => None,
+        }),
+        _ => None,
+    };
+    if let Some((label, text)) = error {
+        let lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+        for (i, line) in lines.take(MAX_ERROR_LINES).enumerate() {
+            all_lines.push(Line::from(vec![
+                Span::raw(if i == 0 { label } else { "          " }),
+                Span::styled(line.to_string(), Style::default().fg(status_color)),
+            ]));
+        }
+    }
+
+    // Custom checks, with the output of the ones that aren't ok
+    for result in &server.check_results {
+        let mut spans = vec![
+            Span::raw(format!("{:<10}", format!("{}:", truncate(&result.name, 8)))),
+            Span::styled(result.value.clone(), Style::default().fg(check_color(result.level))),
+        ];
+        let output = result.output.lines().next().unwrap_or_default();
+        if result.level != CheckLevel::Ok && !output.is_empty() && output != result.value {
+            spans.push(Span::styled(format!("  {}", output), Style::default().fg(Color::DarkGray)));
+        }
+        all_lines.push(Line::from(spans));
+    }
+
+    // Bastion chain, in connection order
+    if !server.jump_chain.is_empty() {
+        let mut spans = vec![Span::raw("Via:      ")];
+        for (i, hop) in server.jump_chain.iter().enumerate() {
+            if i > 0 {
+                spans.push(Span::styled(" → ", Style::default().fg(Color::DarkGray)));
+            }
+            spans.push(Span::styled(hop.to_string(), Style::default().fg(Color::Blue)));
+        }
+        spans.push(Span::styled(" → ", Style::default().fg(Color::DarkGray)));
+        spans.push(Span::styled(&server.host, Style::default().fg(Color::Cyan)));
+        all_lines.push(Line::from(spans));
+    }
+    if let Some(ref command) = server.proxy_command {
+        all_lines.push(Line::from(vec![
+            Span::raw("Proxy:    "),
+            Span::styled(command, Style::default().fg(Color::White)),
+        ]));
+    }
+    if let Some(ref description) = server.description {
+        all_lines.push(Line::from(vec![
+            Span::raw("Desc:     "),
+            Span::styled(description, Style::default().fg(Color::White)),
+        ]));
+    }
+    if let Some(ref owner) = server.owner {
+        all_lines.push(Line::from(vec![
+            Span::raw("Owner:    "),
+            Span::styled(owner, Style::default().fg(Color::White)),
+        ]));
+    }
+    if !server.tags.is_empty() {
+        let tags = server.tags.iter().cloned().collect::<Vec<_>>().join(", ");
+        all_lines.push(Line::from(vec![
+            Span::raw("Tags:     "),
+            Span::styled(tags, Style::default().fg(Color::Green)),
+        ]));
+    }
+    if let Some(ref location) = server.defined_at {
+        all_lines.push(Line::from(vec![
+            Span::raw("Source:   "),
+            Span::styled(location.to_string(), Style::default().fg(Color::DarkGray)),
+        ]));
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(all_lines.len() as u16 + 2), // Basic info
+            Constraint::Length(6),                          // System metrics
+            Constraint::Min(4),                             // GPU / Users
+        ])
+        .split(area);
+
     let info_block = Block::default()
         .borders(Borders::ALL)
         .title(format!(" {} ", server.host));
     frame.render_widget(Paragraph::new(all_lines).block(info_block), chunks[0]);
 
+    // The effective config panel replaces the metrics below the basic info
+    if app.show_effective_config {
+        let panel_area = Rect {
+            height: area.height.saturating_sub(chunks[0].height),
+            ..chunks[1]
+        };
+        draw_effective_config(frame, app, server, panel_area);
+        return;
+    }
+
     // System metrics
     if let Some(ref metrics) = server.metrics {
         let metrics_chunks = Layout::default()
@@ -455,32 +705,28 @@ fn draw_server_details(frame: &mut Frame, app: &App, area: Rect) {
             .label(ram_label);
         frame.render_widget(ram_gauge, metrics_chunks[1]);
 
-        // GPU and users
-        let bottom_chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
-            .split(chunks[2]);
-
-        // GPU info
-        let gpu_lines: Vec<Line> = if metrics.gpus.is_empty() {
-            vec![Line::from("No GPU detected")]
+        // GPUs get the full width for their table, with users below
+        let bottom_chunks = if metrics.gpus.is_empty() {
+            Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .split(chunks[2])
         } else {
-            metrics
-                .gpus
-                .iter()
-                .map(|gpu| {
-                    Line::from(format!(
-                        "{}: {}% | {} / {}",
-                        gpu.name,
-                        gpu.utilization,
-                        format_bytes(gpu.memory_used),
-                        format_bytes(gpu.memory_total)
-                    ))
-                })
-                .collect()
+            Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([
+                    Constraint::Length(metrics.gpus.len() as u16 + 3),
+                    Constraint::Min(3),
+                ])
+                .split(chunks[2])
         };
+
         let gpu_block = Block::default().borders(Borders::ALL).title(" GPUs ");
-        frame.render_widget(Paragraph::new(gpu_lines).block(gpu_block), bottom_chunks[0]);
+        if metrics.gpus.is_empty() {
+            frame.render_widget(Paragraph::new("No GPU detected").block(gpu_block), bottom_chunks[0]);
+        } else {
+            frame.render_widget(gpu_table(&metrics.gpus).block(gpu_block), bottom_chunks[0]);
+        }
 
         // Users
         let users_text = if metrics.logged_in_users.is_empty() {
@@ -502,6 +748,149 @@ fn draw_server_details(frame: &mut Frame, app: &App, area: Rect) {
     }
 }
 
+/// One row per GPU: load, memory, sensors and who is running on it
+fn gpu_table(gpus: &[GpuInfo]) -> Table<'static> {
+    let dim = Style::default().fg(Color::DarkGray);
+    let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    let rows: Vec<Row> = gpus
+        .iter()
+        .map(|gpu| {
+            // nvidia-smi names like "Tesla V100" leave out the vendor
+            let vendor = gpu.vendor.label();
+            let name = if gpu.name.starts_with(vendor) {
+                gpu.name.clone()
+            } else {
+                format!("{} {}", vendor, gpu.name)
+            };
+            let temperature_color = match gpu.temperature {
+                Some(t) if t >= 85.0 => Color::Red,
+                Some(t) if t >= 75.0 => Color::Yellow,
+                _ => Color::White,
+            };
+            let power = match (gpu.power_draw, gpu.power_limit) {
+                (Some(draw), Some(limit)) => Some(format!("{:.0}/{:.0}W", draw, limit)),
+                (Some(draw), None) => Some(format!("{:.0}W", draw)),
+                _ => None,
+            };
+            let ecc_color = if gpu.ecc_errors.unwrap_or(0) > 0 { Color::Red } else { Color::White };
+            let owners = gpu
+                .owners()
+                .iter()
+                .map(|(user, memory)| format!("{} {}", user, format_bytes(*memory)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            Row::new(vec![
+                Cell::from(gpu.index.to_string()),
+                Cell::from(name),
+                Cell::from(format!("{:.0}%", gpu.utilization))
+                    .style(Style::default().fg(gauge_color(gpu.utilization))),
+                Cell::from(format!(
+                    "{} / {}",
+                    format_bytes(gpu.memory_used),
+                    format_bytes(gpu.memory_total)
+                ))
+                .style(Style::default().fg(gauge_color(gpu.memory_percent()))),
+                Cell::from(or_dash(gpu.temperature.map(|t| format!("{:.0}°C", t))))
+                    .style(Style::default().fg(temperature_color)),
+                Cell::from(or_dash(power)),
+                Cell::from(or_dash(gpu.ecc_errors.map(|e| e.to_string())))
+                    .style(Style::default().fg(ecc_color)),
+                Cell::from(if owners.is_empty() { "-".to_string() } else { owners }),
+            ])
+        })
+        .collect();
+
+    let header = Row::new(["#", "GPU", "Util", "Memory", "Temp", "Power", "ECC", "Users"]).style(dim);
+    let widths = [
+        Constraint::Length(2),
+        Constraint::Min(16),
+        Constraint::Length(5),
+        Constraint::Length(13),
+        Constraint::Length(5),
+        Constraint::Length(9),
+        Constraint::Length(4),
+        Constraint::Min(16),
+    ];
+    Table::new(rows, widths).header(header)
+}
+
+/// ggoto's own parsed value for an option shown in the effective config panel
+fn parsed_option_value(server: &Server, key: &str) -> String {
+    match key {
+        "hostname" => server.hostname.clone(),
+        "user" => server.user.clone().unwrap_or_else(|| "(default)".to_string()),
+        "port" => server.port.to_string(),
+        "identityfile" => server.identity_file.clone().unwrap_or_else(|| "(none)".to_string()),
+        "proxyjump" => server
+            .jump_chain
+            .iter()
+            .map(|hop| hop.to_string())
+            .collect::<Vec<_>>()
+            .join(","),
+        "proxycommand" => server.proxy_command.clone().unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+/// Options resolved by `ssh -G`, with values that disagree with the parsed config highlighted
+fn draw_effective_config(frame: &mut Frame, app: &App, server: &Server, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Effective Config (ssh -G) ");
+
+    let resolved = app
+        .effective_config
+        .as_ref()
+        .filter(|(id, _)| *id == server.id)
+        .map(|(_, result)| result);
+
+    let lines: Vec<Line> = match resolved {
+        _ if app.resolving_config == Some(server.id) => {
+            vec![Line::from("Resolving with ssh -G...").style(Style::default().fg(Color::DarkGray))]
+        }
+        None => vec![Line::from("Press 'e' to resolve").style(Style::default().fg(Color::DarkGray))],
+        Some(Err(e)) => vec![Line::from(e.as_str()).style(Style::default().fg(Color::Red))],
+        Some(Ok(config)) => {
+            // Show disagreements first, then everything else in ssh's order
+            let (differing, same): (Vec<_>, Vec<_>) = config
+                .options
+                .iter()
+                .partition(|(key, _)| config.differs(server, key));
+
+            let mut lines: Vec<Line> = differing
+                .iter()
+                .map(|(key, value)| {
+                    Line::from(vec![
+                        Span::styled(
+                            format!("{:<24}", key),
+                            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+                        ),
+                        Span::styled(value.as_str(), Style::default().fg(Color::Yellow)),
+                        Span::styled(
+                            format!("  (ggoto: {})", parsed_option_value(server, key)),
+                            Style::default().fg(Color::DarkGray),
+                        ),
+                    ])
+                })
+                .collect();
+            lines.extend(same.iter().map(|(key, value)| {
+                Line::from(vec![
+                    Span::styled(format!("{:<24}", key), Style::default().fg(Color::DarkGray)),
+                    Span::raw(value.as_str()),
+                ])
+            }));
+            lines
+        }
+    };
+
+    frame.render_widget(
+        Paragraph::new(lines)
+            .block(block)
+            .scroll((app.effective_scroll, 0)),
+        area,
+    );
+}
+
 fn draw_help(frame: &mut Frame, area: Rect) {
     let help_text = vec![
         Line::from(vec![Span::styled(
@@ -511,14 +900,19 @@ fn draw_help(frame: &mut Frame, area: Rect) {
         Line::from("  j/↓       Move down"),
         Line::from("  k/↑       Move up"),
         Line::from("  a-z, 0-9  Quick connect to server"),
-        Line::from("  Enter     Connect to selected server"),
+        Line::from("  Enter     Connect to selected server / fold group"),
+        Line::from("  F         Connect to the freest GPU (in group / list)"),
+        Line::from("  ←/→       Collapse/expand group"),
+        Line::from("  Z         Collapse/expand all groups"),
         Line::from("  d/Space   Show server details"),
+        Line::from("  e         Effective config via ssh -G (in details)"),
         Line::from(""),
         Line::from(vec![Span::styled(
             "Search",
             Style::default().add_modifier(Modifier::BOLD),
         )]),
-        Line::from("  /         Search/filter servers (regex)"),
+        Line::from("  /         Fuzzy search (re: prefix for regex)"),
+        Line::from("            cpu<20 gpu.free>=1 status:up tag:prod ..."),
         Line::from("  n         Next match"),
         Line::from("  N         Previous match"),
         Line::from("  Esc       Clear search"),
@@ -528,6 +922,10 @@ fn draw_help(frame: &mut Frame, area: Rect) {
             Style::default().add_modifier(Modifier::BOLD),
         )]),
         Line::from("  G         Switch to group view"),
+        Line::from("  B         Toggle grouping by bastion"),
+        Line::from("  g         Toggle grouping by tag"),
+        Line::from("  V         Next saved view"),
+        Line::from("  W         Save current view"),
         Line::from("  Esc       Back to server list"),
         Line::from("  ?         Toggle help"),
         Line::from(""),
@@ -537,12 +935,22 @@ fn draw_help(frame: &mut Frame, area: Rect) {
         )]),
         Line::from("  c         Run command on server"),
         Line::from("  f         Toggle favorite ★"),
+        Line::from("  #         Edit tags"),
         Line::from("  s         Cycle sort order"),
         Line::from("  r         Refresh all servers"),
         Line::from("  R         Refresh selected server"),
         Line::from("  m         Toggle mosh/ssh mode"),
         Line::from("  M         Mosh install menu"),
         Line::from(""),
+        Line::from(vec![Span::styled(
+            "Edit SSH Config",
+            Style::default().add_modifier(Modifier::BOLD),
+        )]),
+        Line::from("  A         Add host"),
+        Line::from("  E         Edit selected host"),
+        Line::from("  C         Duplicate selected host"),
+        Line::from("  X         Delete selected host"),
+        Line::from(""),
         Line::from(vec![Span::styled(
             "Tunnels",
             Style::default().add_modifier(Modifier::BOLD),
@@ -615,6 +1023,12 @@ fn draw_status_bar(frame: &mut Frame, app: &App, area: Rect) {
             ViewMode::GroupList => Line::from(vec![
                 Span::styled(" Enter", Style::default().fg(Color::Yellow)),
                 Span::raw(":select  "),
+                Span::styled("←/→", Style::default().fg(Color::Yellow)),
+                Span::raw(":fold  "),
+                Span::styled("B", Style::default().fg(Color::Yellow)),
+                Span::raw(":by bastion  "),
+                Span::styled("g", Style::default().fg(Color::Yellow)),
+                Span::raw(":by tag  "),
                 Span::styled("Esc", Style::default().fg(Color::Yellow)),
                 Span::raw(":back  "),
                 Span::styled("?", Style::default().fg(Color::Yellow)),
@@ -625,6 +1039,8 @@ fn draw_status_bar(frame: &mut Frame, app: &App, area: Rect) {
                 Span::raw(":connect  "),
                 Span::styled("r", Style::default().fg(Color::Yellow)),
                 Span::raw(":refresh  "),
+                Span::styled("e", Style::default().fg(Color::Yellow)),
+                Span::raw(":ssh -G  "),
                 Span::styled("j/k", Style::default().fg(Color::Yellow)),
                 Span::raw(":nav  "),
                 Span::styled("q", Style::default().fg(Color::Yellow)),
@@ -836,6 +1252,78 @@ fn draw_tunnel_input(frame: &mut Frame, app: &App) {
     frame.render_widget(input, popup_area);
 }
 
+fn draw_tag_input(frame: &mut Frame, app: &App) {
+    let area = constrained_rect(frame.area(), MAX_WIDTH);
+    let popup_width = area.width.min(70);
+
+    let Some(server) = app.selected_server() else {
+        return;
+    };
+    // Tags from annotations and rules can only be changed in their config
+    let fixed: Vec<&str> = server
+        .tags
+        .difference(&server.local_tags)
+        .map(|t| t.as_str())
+        .collect();
+    let hint = if fixed.is_empty() {
+        "Comma or space separated (e.g., gpu, a100)".to_string()
+    } else {
+        format!("From config: {}", fixed.join(", "))
+    };
+
+    let popup_area = Rect {
+        x: area.x + (area.width - popup_width) / 2,
+        y: area.height / 2 - 3,
+        width: popup_width,
+        height: 5,
+    };
+
+    frame.render_widget(Clear, popup_area);
+
+    let text = vec![
+        Line::from(hint).style(Style::default().fg(Color::DarkGray)),
+        Line::from(format!("→ {}", app.tag_input)).style(Style::default().fg(Color::White)),
+    ];
+
+    let input = Paragraph::new(text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" Tags for {} ", server.host))
+            .style(Style::default().fg(Color::Green)),
+    );
+
+    frame.render_widget(input, popup_area);
+}
+
+fn draw_view_input(frame: &mut Frame, app: &App) {
+    let area = constrained_rect(frame.area(), MAX_WIDTH);
+    let popup_width = area.width.min(70);
+
+    let popup_area = Rect {
+        x: area.x + (area.width - popup_width) / 2,
+        y: area.height / 2 - 3,
+        width: popup_width,
+        height: 5,
+    };
+
+    frame.render_widget(Clear, popup_area);
+
+    let text = vec![
+        Line::from("Saves the filter, sort order, grouping and columns")
+            .style(Style::default().fg(Color::DarkGray)),
+        Line::from(format!("→ {}", app.view_input)).style(Style::default().fg(Color::White)),
+    ];
+
+    let input = Paragraph::new(text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Save View ")
+            .style(Style::default().fg(Color::Green)),
+    );
+
+    frame.render_widget(input, popup_area);
+}
+
 fn draw_tunnels(frame: &mut Frame, app: &App, area: Rect) {
     let display_items = app.tunnel_manager.get_display_items();
 
@@ -1040,3 +1528,96 @@ fn draw_install_menu(frame: &mut Frame, app: &App) {
 
     frame.render_widget(Paragraph::new(lines).block(block), popup_area);
 }
+
+fn draw_host_form(frame: &mut Frame, form: &HostForm) {
+    let area = constrained_rect(frame.area(), MAX_WIDTH);
+    let popup_width = area.width.min(70);
+    let popup_height = (HostEntry::FIELD_COUNT as u16 + 5).min(area.height);
+
+    let popup_area = Rect {
+        x: area.x + (area.width.saturating_sub(popup_width)) / 2,
+        y: area.y + (area.height.saturating_sub(popup_height)) / 2,
+        width: popup_width,
+        height: popup_height,
+    };
+
+    frame.render_widget(Clear, popup_area);
+
+    let title = match (form.mode, &form.source) {
+        (HostFormMode::Edit, Some((host, at))) => format!(" Edit {} ({}) ", host, at),
+        (HostFormMode::Duplicate, Some((host, _))) => format!(" Duplicate {} ", host),
+        _ => format!(" Add host to {} ", tilde_path(&form.file)),
+    };
+
+    let mut lines = vec![Line::from("")];
+    for field in 0..HostEntry::FIELD_COUNT {
+        let is_focused = field == form.focused;
+        let style = if is_focused {
+            Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(Color::White)
+        };
+        let cursor = if is_focused { "_" } else { "" };
+        lines.push(Line::from(vec![
+            Span::styled(if is_focused { " ▸ " } else { "   " }, style),
+            Span::styled(format!("{:<14}", HostEntry::field_label(field)), style),
+            Span::styled(format!("{}{}", form.entry.field(field), cursor), Style::default().fg(Color::White)),
+        ]));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![
+        Span::styled("  Tab/↑↓", Style::default().fg(Color::DarkGray)),
+        Span::raw(": field  "),
+        Span::styled("Enter", Style::default().fg(Color::DarkGray)),
+        Span::raw(": save  "),
+        Span::styled("Esc", Style::default().fg(Color::DarkGray)),
+        Span::raw(": cancel  "),
+        Span::styled("(empty = unset)", Style::default().fg(Color::DarkGray)),
+    ]));
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .style(Style::default().fg(Color::Magenta));
+
+    frame.render_widget(Paragraph::new(lines).block(block), popup_area);
+}
+
+fn draw_delete_confirmation(frame: &mut Frame, app: &App) {
+    let Some(server) = app.selected_server() else {
+        return;
+    };
+    let area = constrained_rect(frame.area(), MAX_WIDTH);
+    let popup_width = area.width.min(70);
+
+    let popup_area = Rect {
+        x: area.x + (area.width.saturating_sub(popup_width)) / 2,
+        y: area.y + area.height / 2 - 2,
+        width: popup_width,
+        height: 4,
+    };
+
+    frame.render_widget(Clear, popup_area);
+
+    let location = server
+        .defined_at
+        .as_ref()
+        .map(|at| at.to_string())
+        .unwrap_or_default();
+    let text = vec![
+        Line::from(format!("Remove {} from {}?", server.host, location)),
+        Line::from(vec![
+            Span::styled("y", Style::default().fg(Color::Yellow)),
+            Span::raw(": delete  "),
+            Span::styled("any other key", Style::default().fg(Color::Yellow)),
+            Span::raw(": cancel"),
+        ]),
+    ];
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Delete host ")
+        .style(Style::default().fg(Color::Red));
+
+    frame.render_widget(Paragraph::new(text).block(block), popup_area);
+}
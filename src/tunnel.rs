@@ -1,10 +1,14 @@
 use std::collections::HashMap;
 use std::net::TcpListener;
 use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
 
 use anyhow::{Context, Result};
 
-use crate::server::Server;
+use crate::server::{Server, ServerId};
+use crate::ssh::Backend;
+use crate::ssh::mux::MasterHold;
+use crate::ssh::native::NativeForward;
 
 /// Default port range for tunnels
 pub const DEFAULT_PORT_START: u16 = 8000;
@@ -33,14 +37,32 @@ pub enum TunnelDisplayItem {
     },
 }
 
+/// What keeps a tunnel open
+#[derive(Debug)]
+pub enum TunnelHandle {
+    /// A dedicated `ssh -N -L` process
+    Process(Child),
+    /// A forward on the server's shared connection, reached with these arguments
+    Shared {
+        master_args: Vec<String>,
+        /// Keeps the master from closing while the forward is idle
+        hold: Arc<MasterHold>,
+    },
+    /// A listener thread forwarding over an in-process SSH session
+    Native(NativeForward),
+    /// A forward that only exists in a scripted backend (tests and demo mode)
+    Scripted,
+}
+
 /// Represents an active SSH tunnel
 #[derive(Debug)]
 pub struct Tunnel {
     pub local_port: u16,
     pub remote_host: String,
     pub remote_port: u16,
+    pub server_id: ServerId,
     pub server_host: String,
-    pub process: Child,
+    pub handle: TunnelHandle,
     /// Group ID for tunnels opened as a range (None = individual tunnel)
     pub group_id: Option<u32>,
 }
@@ -49,17 +71,59 @@ impl Tunnel {
     /// Check if tunnel is still running
     #[allow(dead_code)]
     pub fn is_alive(&mut self) -> bool {
-        match self.process.try_wait() {
-            Ok(Some(_)) => false, // Process exited
-            Ok(None) => true,     // Still running
-            Err(_) => false,      // Error checking
+        match self.handle {
+            TunnelHandle::Process(ref mut process) => match process.try_wait() {
+                Ok(Some(_)) => false, // Process exited
+                Ok(None) => true,     // Still running
+                Err(_) => false,      // Error checking
+            },
+            // The forward lives as long as the shared connection
+            TunnelHandle::Shared {
+                ref master_args,
+                ref hold,
+            } => {
+                hold.is_alive()
+                    && Command::new("ssh")
+                        .args(master_args)
+                        .args(["-O", "check", &self.server_host])
+                        .stdin(Stdio::null())
+                        .stdout(Stdio::null())
+                        .stderr(Stdio::null())
+                        .status()
+                        .is_ok_and(|s| s.success())
+            }
+            TunnelHandle::Native(ref forward) => forward.is_alive(),
+            TunnelHandle::Scripted => true,
         }
     }
 
+    /// The `-L` forward spec
+    fn forward_spec(&self) -> String {
+        format!("{}:{}:{}", self.local_port, self.remote_host, self.remote_port)
+    }
+
     /// Close the tunnel
     pub fn close(&mut self) -> Result<()> {
-        self.process.kill().context("Failed to kill tunnel process")?;
-        self.process.wait().context("Failed to wait for tunnel process")?;
+        match self.handle {
+            TunnelHandle::Process(ref mut process) => {
+                process.kill().context("Failed to kill tunnel process")?;
+                process.wait().context("Failed to wait for tunnel process")?;
+            }
+            TunnelHandle::Shared { ref master_args, .. } => {
+                // The master may already be gone, taking the forward with it
+                let _ = Command::new("ssh")
+                    .args(master_args)
+                    .args(["-O", "cancel", "-L"])
+                    .arg(self.forward_spec())
+                    .arg(&self.server_host)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status();
+            }
+            TunnelHandle::Native(ref mut forward) => forward.close(),
+            TunnelHandle::Scripted => {}
+        }
         Ok(())
     }
 }
@@ -111,6 +175,7 @@ impl TunnelManager {
     /// Open a new tunnel
     pub fn open_tunnel(
         &mut self,
+        backend: &dyn Backend,
         server: &Server,
         remote_host: &str,
         remote_port: u16,
@@ -124,56 +189,15 @@ impl TunnelManager {
                 .context("No available ports in range")?,
         };
 
-        // Build SSH tunnel command
-        let mut args = vec![
-            "-N".to_string(),        // No remote command
-            "-L".to_string(),        // Local port forwarding
-            format!("{}:{}:{}", local_port, remote_host, remote_port),
-            "-o".to_string(),
-            "BatchMode=yes".to_string(),
-            "-o".to_string(),
-            "ExitOnForwardFailure=yes".to_string(),
-            "-o".to_string(),
-            "ServerAliveInterval=30".to_string(),
-            "-o".to_string(),
-            "ServerAliveCountMax=3".to_string(),
-        ];
-
-        // Add user if specified
-        if let Some(ref user) = server.user {
-            args.push("-l".to_string());
-            args.push(user.clone());
-        }
-
-        // Add port if not default
-        if server.port != 22 {
-            args.push("-p".to_string());
-            args.push(server.port.to_string());
-        }
-
-        // Add identity file if specified
-        if let Some(ref identity) = server.identity_file {
-            args.push("-i".to_string());
-            args.push(identity.clone());
-        }
-
-        // Add the host
-        args.push(server.host.clone());
-
-        let process = Command::new("ssh")
-            .args(&args)
-            .stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()
-            .context("Failed to start SSH tunnel")?;
+        let handle = backend.open_forward(server, local_port, remote_host, remote_port)?;
 
         let tunnel = Tunnel {
             local_port,
             remote_host: remote_host.to_string(),
             remote_port,
+            server_id: server.id,
             server_host: server.host.clone(),
-            process,
+            handle,
             group_id,
         };
 
@@ -206,12 +230,11 @@ impl TunnelManager {
     }
 
     /// Close all tunnels for a specific server
-    #[allow(dead_code)]
-    pub fn close_server_tunnels(&mut self, server_host: &str) -> Result<()> {
+    pub fn close_server_tunnels(&mut self, server_id: ServerId) -> Result<()> {
         let ports: Vec<u16> = self
             .tunnels
             .iter()
-            .filter(|(_, t)| t.server_host == server_host)
+            .filter(|(_, t)| t.server_id == server_id)
             .map(|(&p, _)| p)
             .collect();
 
@@ -232,10 +255,10 @@ impl TunnelManager {
 
     /// Get tunnels for a specific server
     #[allow(dead_code)]
-    pub fn get_server_tunnels(&self, server_host: &str) -> Vec<&Tunnel> {
+    pub fn get_server_tunnels(&self, server_id: ServerId) -> Vec<&Tunnel> {
         self.tunnels
             .values()
-            .filter(|t| t.server_host == server_host)
+            .filter(|t| t.server_id == server_id)
             .collect()
     }
 
@@ -310,3 +333,43 @@ impl TunnelManager {
         self.get_display_items().len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssh::ScriptedBackend;
+
+    #[test]
+    fn test_tunnel_lifecycle() {
+        let backend = ScriptedBackend::new();
+        let server = Server::new("db".to_string(), "10.0.2.1".to_string());
+        let mut manager = TunnelManager::new();
+
+        let port = manager
+            .open_tunnel(&backend, &server, "localhost", 5432, Some(15432), None)
+            .unwrap();
+        assert_eq!(port, 15432);
+        let group = manager.next_group_id();
+        for (local, remote) in [(18000, 8000), (18001, 8001)] {
+            manager
+                .open_tunnel(&backend, &server, "web", remote, Some(local), Some(group))
+                .unwrap();
+        }
+        assert_eq!(
+            backend.calls(),
+            vec![
+                "forward db 15432:localhost:5432",
+                "forward db 18000:web:8000",
+                "forward db 18001:web:8001",
+            ]
+        );
+        assert_eq!(manager.get_server_tunnels(server.id).len(), 3);
+        // The range shows as one item
+        assert_eq!(manager.display_count(), 2);
+
+        assert_eq!(manager.close_group(group).unwrap(), 2);
+        assert_eq!(manager.count(), 1);
+        manager.close_server_tunnels(server.id).unwrap();
+        assert_eq!(manager.count(), 0);
+    }
+}
@@ -0,0 +1,213 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// A column of the server list. The shortcut, favorite marker and host are always shown.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Column {
+    Via,
+    Ping,
+    Age,
+    Cpu,
+    Ram,
+    Gpu,
+    Users,
+    Last,
+    Tags,
+    Description,
+    /// A custom check from the settings, `check:<name>` in the views file
+    Check(String),
+}
+
+impl Column {
+    pub const ALL: [Column; 10] = [
+        Column::Via,
+        Column::Ping,
+        Column::Age,
+        Column::Cpu,
+        Column::Ram,
+        Column::Gpu,
+        Column::Users,
+        Column::Last,
+        Column::Tags,
+        Column::Description,
+    ];
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Column::Via => "via",
+            Column::Ping => "ping",
+            Column::Age => "age",
+            Column::Cpu => "cpu",
+            Column::Ram => "ram",
+            Column::Gpu => "gpu",
+            Column::Users => "users",
+            Column::Last => "last",
+            Column::Tags => "tags",
+            Column::Description => "description",
+            Column::Check(name) => name,
+        }
+    }
+}
+
+impl TryFrom<String> for Column {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        if let Some(check) = name.strip_prefix("check:") {
+            return Ok(Column::Check(check.to_string()));
+        }
+        Column::ALL
+            .into_iter()
+            .find(|c| c.as_str() == name)
+            .ok_or_else(|| format!("unknown column `{}`", name))
+    }
+}
+
+impl From<Column> for String {
+    fn from(column: Column) -> Self {
+        match column {
+            Column::Check(name) => format!("check:{}", name),
+            column => column.as_str().to_string(),
+        }
+    }
+}
+
+/// A named preset of filter, sort order, grouping and columns
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct View {
+    pub name: String,
+    /// Search text and predicates, as typed after `/`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub filter: String,
+    /// Sort order name, as stored in history (`name`, `cpu`, `recent`, ...)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    /// Grouping mode: `name`, `bastion` or `tag`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_by: Option<String>,
+    /// Only show servers in this group (and its subgroups)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Only show servers with this tag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// Columns to show, in the usual order; all of them when left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub columns: Option<Vec<Column>>,
+}
+
+impl View {
+    /// The filter with the group and tag scope added as predicates
+    pub fn query(&self) -> String {
+        let scope = [
+            self.group.as_ref().map(|g| format!("group:{}", g)),
+            self.tag.as_ref().map(|t| format!("tag:{}", t)),
+        ];
+        scope
+            .into_iter()
+            .flatten()
+            .chain((!self.filter.is_empty()).then(|| self.filter.clone()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Saved views, kept in `views.json` next to the history
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ViewFile {
+    pub views: Vec<View>,
+}
+
+impl ViewFile {
+    /// Get the views file path
+    fn views_path() -> Result<PathBuf> {
+        let config_dir =
+            dirs::config_dir().ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?;
+        let ggoto_dir = config_dir.join("ggoto");
+        fs::create_dir_all(&ggoto_dir)?;
+        Ok(ggoto_dir.join("views.json"))
+    }
+
+    /// Load views from disk
+    pub fn load() -> Result<Self> {
+        let path = Self::views_path()?;
+        if path.exists() {
+            let content = fs::read_to_string(&path)?;
+            Ok(serde_json::from_str(&content)?)
+        } else {
+            Ok(ViewFile::default())
+        }
+    }
+
+    /// Save views to disk
+    pub fn save(&self) -> Result<()> {
+        let path = Self::views_path()?;
+        let content = serde_json::to_string_pretty(&self)?;
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Position of a view by name
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.views.iter().position(|v| v.name == name)
+    }
+
+    /// Add a view, replacing one with the same name. Returns its position.
+    pub fn upsert(&mut self, view: View) -> usize {
+        match self.position(&view.name) {
+            Some(i) => {
+                self.views[i] = view;
+                i
+            }
+            None => {
+                self.views.push(view);
+                self.views.len() - 1
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_view_file() {
+        let mut file: ViewFile = serde_json::from_str(
+            r#"[
+                { "name": "free GPUs", "filter": "gpu.free>=1", "sort": "cpu", "columns": ["gpu", "users", "check:nginx"] },
+                { "name": "prod web", "group": "prod-web", "tag": "live", "filter": "status:up" }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            file.views[0].columns,
+            Some(vec![Column::Gpu, Column::Users, Column::Check("nginx".to_string())])
+        );
+        let saved = serde_json::to_string(&file.views[0].columns).unwrap();
+        assert_eq!(saved, r#"["gpu","users","check:nginx"]"#);
+        assert!(serde_json::from_str::<Vec<Column>>(r#"["gpus"]"#).is_err());
+        assert_eq!(file.views[1].query(), "group:prod-web tag:live status:up");
+
+        let replaced = View {
+            name: "prod web".to_string(),
+            filter: "web".to_string(),
+            sort: None,
+            group_by: Some("bastion".to_string()),
+            group: None,
+            tag: None,
+            columns: None,
+        };
+        assert_eq!(file.upsert(replaced.clone()), 1);
+        assert_eq!(file.views[1], replaced);
+        assert_eq!(
+            serde_json::to_string(&replaced).unwrap(),
+            r#"{"name":"prod web","filter":"web","group_by":"bastion"}"#
+        );
+    }
+}